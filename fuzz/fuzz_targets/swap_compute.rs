@@ -0,0 +1,93 @@
+//! `PoolInfo::swap_compute` 的 fuzz 目标
+//!
+//! 用任意的仓位、价格、数量构造池子（与单元测试共用 router_service::fixtures），swap 计算只允许返回错误，不允许 panic
+//! 运行：`cargo +nightly fuzz run swap_compute`
+#![no_main]
+
+use arbitrary::Arbitrary;
+use dex_router::service::router_service::{fixtures, types::PoolInfo};
+use libfuzzer_sys::fuzz_target;
+use raydium_amm_v3::libraries::tick_math;
+
+const TICK_SPACINGS: [u16; 4] = [1, 10, 60, 120];
+/// 链上 AmmConfig 要求 trade_fee_rate 小于该值
+const FEE_RATE_DENOMINATOR: u32 = 1_000_000;
+
+#[derive(Debug, Arbitrary)]
+struct Position {
+  /// 以 tick_spacing 为单位
+  tick_lower: i16,
+  width: u16,
+  liquidity: u64,
+}
+
+#[derive(Debug, Arbitrary)]
+struct SwapInput {
+  tick_spacing: u8,
+  tick_current: i32,
+  trade_fee_rate: u32,
+  positions: Vec<Position>,
+  zero_for_one: bool,
+  base_input: bool,
+  amount: u64,
+  sqrt_price_limit_x64: u128,
+  /// 从 tick-array 队列中去掉一个，模拟链上数据不完整
+  missing_tick_array: Option<u8>,
+}
+
+fn build_pool(input: &SwapInput) -> PoolInfo {
+  let tick_spacing = TICK_SPACINGS[input.tick_spacing as usize % TICK_SPACINGS.len()];
+  let spacing = i32::from(tick_spacing);
+  let tick_current = input.tick_current.rem_euclid(2 * tick_math::MAX_TICK) - tick_math::MAX_TICK;
+
+  let positions: Vec<(i32, i32, u128)> = input
+    .positions
+    .iter()
+    .map(|position| {
+      let tick_lower = i32::from(position.tick_lower) * spacing;
+      let tick_upper = tick_lower + (i32::from(position.width) + 1) * spacing;
+      (tick_lower, tick_upper, u128::from(position.liquidity))
+    })
+    .filter(|(tick_lower, tick_upper, _)| *tick_lower >= tick_math::MIN_TICK && *tick_upper <= tick_math::MAX_TICK)
+    .collect();
+
+  fixtures::synthetic_pool(tick_spacing, tick_current, input.trade_fee_rate % FEE_RATE_DENOMINATOR, &positions)
+}
+
+fuzz_target!(|input: SwapInput| {
+  let pool = build_pool(&input);
+  let tick_array_bitmap_extension = &pool.dynamic_info.tick_array_bitmap_extension;
+
+  let Ok((is_exist, first_tick_array_start_index)) = pool.get_first_initialized_tick_array(tick_array_bitmap_extension, input.zero_for_one)
+  else {
+    return;
+  };
+  let Ok(mut tick_arrays) = pool.get_tick_array_dequeue(first_tick_array_start_index, input.zero_for_one) else {
+    return;
+  };
+  if let Some(index) = input.missing_tick_array {
+    if !tick_arrays.is_empty() {
+      tick_arrays.remove(index as usize % tick_arrays.len());
+    }
+  }
+
+  let result = pool.swap_compute(
+    input.zero_for_one,
+    input.base_input,
+    is_exist,
+    input.amount,
+    first_tick_array_start_index,
+    input.sqrt_price_limit_x64,
+    tick_array_bitmap_extension,
+    &mut tick_arrays,
+  );
+
+  if let Ok((state, _)) = result {
+    assert!(state.amount_specified_remaining <= input.amount);
+    if input.zero_for_one {
+      assert!(state.sqrt_price_x64 <= pool.dynamic_info.sqrt_price_x64);
+    } else {
+      assert!(state.sqrt_price_x64 >= pool.dynamic_info.sqrt_price_x64);
+    }
+  }
+});
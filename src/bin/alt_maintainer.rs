@@ -0,0 +1,76 @@
+//! 热门池子的地址查找表维护工具
+//!
+//! 根据 nacos 中配置的热门池子（lookup_table_pools）计算期望写入查找表的地址，与配置的查找表对比，
+//! 生成 create / extend / deactivate 交易（未签名，base64），由 operator 的 authority 私钥签名后按顺序发送。
+//!
+//! 用法: ALT_AUTHORITY=<authority pubkey> alt_maintainer [--dry-run]
+//! --dry-run 只打印维护计划，不构造交易
+
+use std::{env, str::FromStr};
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use dex_router::nacos_config;
+use dex_router::service::{
+  core::{
+    build_tx::{MessageVersion, TransactionBuilder},
+    lookup_table::{self, HOT_TICK_ARRAY_RADIUS, LookupTableAction},
+  },
+  router_service::route_utils,
+};
+use solana_sdk::pubkey::Pubkey;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let dry_run = env::args().any(|arg| arg == "--dry-run");
+  let authority = Pubkey::from_str(&env::var("ALT_AUTHORITY").map_err(|_| "ALT_AUTHORITY is not set")?)?;
+
+  nacos_config::entrance::init_nacos().await?;
+  let nacos_config = nacos_config::entrance::get_nacos_config().await;
+  let rpc_client = nacos_config.get_rand_rpc();
+
+  let hot_pools = nacos_config.get_lookup_table_pools()?;
+  if hot_pools.is_empty() {
+    return Err("lookup_table_pools is not configured".into());
+  }
+  let pools = route_utils::fetch_all_clmm_pools(&rpc_client).await?;
+  for hot_pool in hot_pools.iter().filter(|hot_pool| !pools.iter().any(|pool| pool.base_info.id == **hot_pool)) {
+    println!("hot pool {} is not a clmm pool, skipped", hot_pool);
+  }
+  let desired = lookup_table::hot_pool_lookup_addresses(&pools, &hot_pools, HOT_TICK_ARRAY_RADIUS);
+
+  let existing = lookup_table::fetch_existing_lookup_tables(&rpc_client, &nacos_config.get_address_lookup_tables()?).await?;
+  let recent_slots = lookup_table::fetch_recent_block_slots(&rpc_client).await?;
+  let actions = lookup_table::plan_lookup_table_maintenance(&desired, &existing, &authority, &recent_slots)?;
+
+  println!("hot pools: {}, desired addresses: {}, existing tables: {}", hot_pools.len(), desired.len(), existing.len());
+  for (idx, action) in actions.iter().enumerate() {
+    match action {
+      LookupTableAction::Create { table, recent_slot } => println!("[{}] create {} (recent_slot {})", idx, table, recent_slot),
+      LookupTableAction::Extend { table, addresses } => println!("[{}] extend {} with {} addresses", idx, table, addresses.len()),
+      LookupTableAction::Deactivate { table } => println!("[{}] deactivate {}", idx, table),
+    }
+  }
+  if actions.is_empty() {
+    println!("lookup tables are up to date");
+  }
+  if dry_run || actions.is_empty() {
+    return Ok(());
+  }
+
+  // 新建的查找表需要在 extend 前先上链，交易需按顺序发送，并在 blockhash 过期（约 150 个 slot）前签名发送完
+  let blockhash = rpc_client.get_latest_blockhash().await?;
+  for (idx, action) in actions.iter().enumerate() {
+    let vtx = TransactionBuilder::build_versioned_transaction_sync(
+      blockhash,
+      &authority,
+      &action.instructions(&authority),
+      &[],
+      &vec![],
+      MessageVersion::Legacy,
+    )?;
+    println!("[{}] {}", idx, BASE64_STANDARD.encode(bincode::serialize(&vtx)?));
+  }
+
+  Ok(())
+}
@@ -0,0 +1,8 @@
+pub mod app_state;
+pub mod config;
+pub mod constants;
+pub mod logging;
+pub mod nacos_config;
+pub mod service;
+pub mod startup;
+pub mod trace;
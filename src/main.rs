@@ -1,24 +1,16 @@
 extern crate core;
 
-use crate::service::{pb::query::*, pb::router::*, query_service::DexQueryService, router_service::DexRouterService};
+use dex_router::service::{pb::query::*, pb::router::*, query_service::DexQueryService, router_service::DexRouterService};
+use dex_router::trace::set_trace_id;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tonic::{Request, Status, transport::Server};
 use tower::{ServiceBuilder, util::MapRequestLayer};
-use trace::set_trace_id;
 
-use crate::app_state::AppState;
+use dex_router::app_state::AppState;
+use dex_router::{logging, nacos_config};
 use log::{error, info, warn};
 
-mod app_state;
-mod config;
-mod constants;
-mod logging;
-mod nacos_config;
-mod service;
-mod startup;
-mod trace;
-
 const GRPC_PORT: u16 = 9090;
 
 #[tokio::main]
@@ -36,8 +28,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let grpc_server_addr: SocketAddr = format!("[::]:{}", GRPC_PORT).parse()?;
   println!("1 Server listening on {}", grpc_server_addr);
 
-  let dex_query_service = DexQueryService {};
-  let dex_router_service = DexRouterService {};
+  let dex_query_service = DexQueryService::default();
+  let dex_router_service = DexRouterService::default();
   println!("2 Server listening on {}", grpc_server_addr);
 
   // let mut receiver = config_watcher.sender.subscribe(); // 创建 receiver
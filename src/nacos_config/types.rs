@@ -1,5 +1,17 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::service::core::{
+  platform_fee::{BPS_DENOMINATOR, FeeSide, PlatformFeeConfig},
+  priority_fee::PriorityFeeConfig,
+  sponsor::{KeypairSource, SponsorConfig, SponsorLimits},
+  tx_sender::SendOptions,
+};
 
 /// nacos中存储的配置
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -10,8 +22,102 @@ pub struct NacosConfig {
 
   /// cu 模拟计算后的增加的系数， 基点， 1代表 1/10000
   pub cu_factor_basis: u32,
+
+  /// 是否每笔交易都模拟执行获取 cu，为 false 时只在 cu 模型的估算不可信时模拟
+  #[serde(default)]
+  pub always_simulate_cu: bool,
+
+  /// 中转代币(hub)列表，多跳路由时中间代币只能是这些代币（如 USDC, USDT, WSOL）
+  /// 为空时不限制中间代币
+  #[serde(default)]
+  pub hub_mints: Vec<String>,
+
+  /// 询价结果的有效期，以 slot 为单位，为 0 时使用默认值
+  #[serde(default)]
+  pub quote_ttl_slots: u64,
+
+  /// 服务端保存询价结果时的 HMAC 签名密钥，为空时不签名
+  /// 不参与序列化，避免打印配置时泄露
+  #[serde(default, skip_serializing)]
+  pub quote_hmac_secret: String,
+
+  /// 是否接受客户端回传、没有 quote_id 的询价结果，默认不接受
+  /// 回传的询价结果可以被客户端修改，只在未配置签名密钥时生效
+  #[serde(default)]
+  pub accept_client_quotes: bool,
+
+  /// 构建 V0 交易时可以使用的地址查找表(ALT)
+  #[serde(default)]
+  pub address_lookup_tables: Vec<String>,
+
+  /// 热门池子列表，维护地址查找表时只写入这些池子的账户
+  #[serde(default)]
+  pub lookup_table_pools: Vec<String>,
+
+  /// 自动估算优先费时使用的百分位，为 0 时使用默认值
+  #[serde(default)]
+  pub priority_fee_percentile: u8,
+
+  /// 自动估算优先费的下限（微 lamports）
+  #[serde(default)]
+  pub priority_fee_min_micro_lamports: u64,
+
+  /// 自动估算优先费的上限（微 lamports），为 0 时使用默认值
+  #[serde(default)]
+  pub priority_fee_max_micro_lamports: u64,
+
+  /// 小费(tip)账户列表，构建交易时从中随机选择一个
+  #[serde(default)]
+  pub tip_accounts: Vec<String>,
+
+  /// 平台费，基点，为 0 时不收取
+  #[serde(default)]
+  pub platform_fee_bps: u16,
+
+  /// 平台费从输入代币(input)还是输出代币(output)中扣除
+  #[serde(default)]
+  pub platform_fee_side: FeeSide,
+
+  /// 接收平台费的钱包地址
+  #[serde(default)]
+  pub platform_fee_owner: String,
+
+  /// 平台费中分给推荐人的比例，基点
+  #[serde(default)]
+  pub referral_fee_share_bps: u16,
+
+  /// 提交的交易未上链时的重发间隔（毫秒），为 0 时使用默认值
+  #[serde(default)]
+  pub tx_resend_interval_ms: u64,
+
+  /// 提交的交易的最长跟踪时间（秒），为 0 时使用默认值
+  #[serde(default)]
+  pub tx_track_timeout_secs: u64,
+
+  /// 代付手续费账户的 keypair 文件路径
+  #[serde(default)]
+  pub sponsor_keypair_path: String,
+
+  /// 保存代付手续费账户私钥的环境变量名，优先于 keypair 文件
+  #[serde(default)]
+  pub sponsor_keypair_env: String,
+
+  /// 代付账户单笔交易最多支付的交易费（lamports），为 0 时使用默认值
+  #[serde(default)]
+  pub sponsor_max_lamports_per_tx: u64,
+
+  /// 代付账户单笔交易最多支付的新建账户租金（lamports），为 0 时使用默认值
+  #[serde(default)]
+  pub sponsor_max_rent_lamports_per_tx: u64,
+
+  /// 代付交易允许的最高计算单元价格（微 lamports），为 0 时使用默认值
+  #[serde(default)]
+  pub sponsor_max_compute_unit_price: u64,
 }
 
+/// 询价结果默认的有效期，按 400ms 一个 slot 约 60 秒
+pub const DEFAULT_QUOTE_TTL_SLOTS: u64 = 150;
+
 impl NacosConfig {
   /// 随机获取一个 rpc_client 信息
   pub fn get_rand_rpc(&self) -> RpcClient {
@@ -32,4 +138,116 @@ impl NacosConfig {
   pub fn get_cu_factor(&self) -> f64 {
     self.cu_factor_basis as f64 / 10000.0 + 1.0
   }
+
+  /// 获取询价结果的有效期（slot 数）
+  pub fn get_quote_ttl_slots(&self) -> u64 {
+    if self.quote_ttl_slots == 0 { DEFAULT_QUOTE_TTL_SLOTS } else { self.quote_ttl_slots }
+  }
+
+  /// 获取自动估算优先费的参数
+  pub fn get_priority_fee_config(&self) -> PriorityFeeConfig {
+    let default = PriorityFeeConfig::default();
+    PriorityFeeConfig {
+      percentile: if self.priority_fee_percentile == 0 { default.percentile } else { self.priority_fee_percentile },
+      min_micro_lamports: self.priority_fee_min_micro_lamports,
+      max_micro_lamports: if self.priority_fee_max_micro_lamports == 0 {
+        default.max_micro_lamports
+      } else {
+        self.priority_fee_max_micro_lamports
+      },
+    }
+  }
+
+  /// 获取平台费配置，未配置平台费时返回 None
+  pub fn get_platform_fee_config(&self) -> Result<Option<PlatformFeeConfig>> {
+    if self.platform_fee_bps == 0 {
+      return Ok(None);
+    }
+    if u64::from(self.platform_fee_bps) >= BPS_DENOMINATOR || u64::from(self.referral_fee_share_bps) > BPS_DENOMINATOR {
+      return Err(anyhow::anyhow!(
+        "Invalid platform fee in config: fee {} bps, referral share {} bps",
+        self.platform_fee_bps,
+        self.referral_fee_share_bps
+      ));
+    }
+    let fee_owner = Pubkey::from_str(&self.platform_fee_owner)
+      .map_err(|e| anyhow::anyhow!("Invalid platform fee owner in config: {}, {}", self.platform_fee_owner, e))?;
+
+    Ok(Some(PlatformFeeConfig {
+      fee_bps: self.platform_fee_bps,
+      side: self.platform_fee_side,
+      fee_owner,
+      referral_share_bps: self.referral_fee_share_bps,
+    }))
+  }
+
+  /// 获取提交交易时重发和跟踪的参数
+  pub fn get_send_options(&self) -> SendOptions {
+    let default = SendOptions::default();
+    SendOptions {
+      resend_interval: if self.tx_resend_interval_ms == 0 {
+        default.resend_interval
+      } else {
+        Duration::from_millis(self.tx_resend_interval_ms)
+      },
+      track_timeout: if self.tx_track_timeout_secs == 0 { default.track_timeout } else { Duration::from_secs(self.tx_track_timeout_secs) },
+    }
+  }
+
+  /// 获取代付配置，未配置代付账户时返回 None
+  pub fn get_sponsor_config(&self) -> Option<SponsorConfig> {
+    let source = if !self.sponsor_keypair_env.is_empty() {
+      KeypairSource::Env(self.sponsor_keypair_env.clone())
+    } else if !self.sponsor_keypair_path.is_empty() {
+      KeypairSource::File(self.sponsor_keypair_path.clone())
+    } else {
+      return None;
+    };
+    let default = SponsorLimits::default();
+    let limits = SponsorLimits {
+      max_lamports_per_tx: if self.sponsor_max_lamports_per_tx == 0 {
+        default.max_lamports_per_tx
+      } else {
+        self.sponsor_max_lamports_per_tx
+      },
+      max_rent_lamports_per_tx: if self.sponsor_max_rent_lamports_per_tx == 0 {
+        default.max_rent_lamports_per_tx
+      } else {
+        self.sponsor_max_rent_lamports_per_tx
+      },
+      max_compute_unit_price: if self.sponsor_max_compute_unit_price == 0 {
+        default.max_compute_unit_price
+      } else {
+        self.sponsor_max_compute_unit_price
+      },
+    };
+    Some(SponsorConfig { source, limits })
+  }
+
+  /// 获取地址查找表列表
+  pub fn get_address_lookup_tables(&self) -> Result<Vec<Pubkey>> {
+    self
+      .address_lookup_tables
+      .iter()
+      .map(|key| Pubkey::from_str(key).map_err(|e| anyhow::anyhow!("Invalid address lookup table in config: {}, {}", key, e)))
+      .collect()
+  }
+
+  /// 获取维护地址查找表的热门池子列表
+  pub fn get_lookup_table_pools(&self) -> Result<Vec<Pubkey>> {
+    self
+      .lookup_table_pools
+      .iter()
+      .map(|key| Pubkey::from_str(key).map_err(|e| anyhow::anyhow!("Invalid lookup table pool in config: {}, {}", key, e)))
+      .collect()
+  }
+
+  /// 获取中转代币列表
+  pub fn get_hub_mints(&self) -> Result<Vec<Pubkey>> {
+    self
+      .hub_mints
+      .iter()
+      .map(|mint| Pubkey::from_str(mint).map_err(|e| anyhow::anyhow!("Invalid hub mint in config: {}, {}", mint, e)))
+      .collect()
+  }
 }
@@ -3,10 +3,11 @@ use anchor_lang::{AccountDeserialize, AccountSerialize};
 use anyhow::Result;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::account::Account;
+use solana_sdk::program_pack::Pack;
 use solana_sdk::pubkey::Pubkey;
 use spl_token_2022::extension::transfer_fee::TransferFeeConfig;
-use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensionsMut};
-use spl_token_2022::state::Mint;
+use spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensionsMut};
+use spl_token_2022::state::{Account as TokenAccount, Mint};
 
 use super::types::MintAccountBaseInfo;
 
@@ -48,6 +49,15 @@ impl<'a> AccountPuller<'a> {
             let mut mint_info =
               MintAccountBaseInfo { mint: *pubkey, decimal: mint_state.base.decimals, program_id: account.owner, ..Default::default() };
 
+            mint_info.token_account_len = if account.owner == spl_token_2022::id() {
+              // ATA 程序创建 Token-2022 账户时总会带上 ImmutableOwner 扩展
+              let mut account_extensions = ExtensionType::get_required_init_account_extensions(&mint_state.get_extension_types()?);
+              account_extensions.push(ExtensionType::ImmutableOwner);
+              ExtensionType::try_calculate_account_len::<TokenAccount>(&account_extensions)?
+            } else {
+              TokenAccount::LEN
+            };
+
             mint_info.transfer_fee_config = if account.owner == spl_token_2022::id() {
               match mint_state.get_extension::<TransferFeeConfig>() {
                 Ok(transfer_fee_config) => Some(*transfer_fee_config),
@@ -8,17 +8,33 @@ use solana_sdk::{
   compute_budget::ComputeBudgetInstruction,
   hash::{Hash, Hasher},
   instruction::Instruction,
-  message::{AddressLookupTableAccount, VersionedMessage, v0::Message as V0Message},
+  message::{AddressLookupTableAccount, Message as LegacyMessage, VersionedMessage, v0::Message as V0Message},
   pubkey::Pubkey,
   signature::{Keypair, Signature},
   signers::Signers,
   transaction::VersionedTransaction,
 };
 
+use super::{lookup_table, nonce::DurableNonce};
+
 /// 最大的 cu 值
 pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1400000;
 /// 添加 compute budget 指令之后的最小 cu 增加值
 pub const MIN_CU_INCREASE_AFTER_ADD_COMPUTE_BUDGET_IX: u32 = 450;
+/// 序列化后的交易最大字节数
+pub const PACKET_DATA_SIZE: usize = 1232;
+/// 一笔交易最多可以锁定的账户数量
+pub const MAX_TX_ACCOUNT_LOCKS: usize = 64;
+
+/// 交易消息的格式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MessageVersion {
+  /// 支持地址查找表（ALT）的 V0 格式
+  #[default]
+  V0,
+  /// 旧格式，不支持地址查找表，部分钱包、托管系统只支持该格式
+  Legacy,
+}
 
 #[derive(Default)]
 pub struct TransactionBuilder {
@@ -27,6 +43,8 @@ pub struct TransactionBuilder {
   payer: Pubkey,
   signers: Vec<Keypair>,
   address_lookup_tables: Vec<AddressLookupTableAccount>,
+  message_version: MessageVersion,
+  durable_nonce: Option<DurableNonce>,
 }
 
 // Shared implementation for all RequestBuilders
@@ -59,6 +77,15 @@ impl TransactionBuilder {
     self.instructions.push(ix);
   }
 
+  pub fn set_message_version(&mut self, message_version: MessageVersion) {
+    self.message_version = message_version;
+  }
+
+  /// 使用 durable nonce 代替 recent blockhash
+  pub fn set_durable_nonce(&mut self, durable_nonce: DurableNonce) {
+    self.durable_nonce = Some(durable_nonce);
+  }
+
   pub fn add_alt(&mut self, alt: AddressLookupTableAccount) {
     self.address_lookup_tables.push(alt);
   }
@@ -67,33 +94,43 @@ impl TransactionBuilder {
     self.signers.push(signer);
   }
 
+  /// 在模拟或估算的 cu 值上增加余量，得到交易的 cu limit
+  pub fn compute_unit_limit_with_margin(cu: u64, cu_factor: f64) -> u32 {
+    let mut real_cu = (cu as f64 * cu_factor) as u64;
+    if real_cu.saturating_sub(cu) < MIN_CU_INCREASE_AFTER_ADD_COMPUTE_BUDGET_IX.into() {
+      real_cu = cu + MIN_CU_INCREASE_AFTER_ADD_COMPUTE_BUDGET_IX as u64;
+    }
+    real_cu.min(MAX_COMPUTE_UNIT_LIMIT.into()) as u32
+  }
+
   /// 构造 VersionedTrsansaction
+  /// compute_unit_limit 由调用方通过模拟执行或 cu 模型估算得到
   pub async fn build_versioned_transaction(
     &self,
     async_rpc_client: &AsyncRpcClient,
     cu_price: u64,
-    cu_factor: f64,
+    compute_unit_limit: u32,
   ) -> Result<VersionedTransaction> {
-    // 否则（需要设置cu，并且没有设置cu），模拟执行交易，获取 cu 值; 并估算最终的cu值
-    let sim_cu = self.simulate_transaction(async_rpc_client).await?;
-    let mut real_cu = (sim_cu as f64 * cu_factor) as u64;
-    if real_cu - sim_cu < MIN_CU_INCREASE_AFTER_ADD_COMPUTE_BUDGET_IX.into() {
-      real_cu = sim_cu + MIN_CU_INCREASE_AFTER_ADD_COMPUTE_BUDGET_IX as u64;
-    }
-
     // 在指令集中增加设置cu的指令
+    // 使用 durable nonce 时，advance_nonce_account 必须是第一条指令
 
     let mut new_ixs = vec![];
-    new_ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(real_cu as u32));
+    new_ixs.extend(self.durable_nonce.map(|durable_nonce| durable_nonce.advance_instruction()));
+    new_ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit));
     new_ixs.push(ComputeBudgetInstruction::set_compute_unit_price(cu_price));
     new_ixs.extend_from_slice(&self.instructions.as_slice());
 
+    let recent_blockhash = match self.durable_nonce {
+      Some(durable_nonce) => durable_nonce.nonce,
+      None => async_rpc_client.get_latest_blockhash().await?,
+    };
     let vtx = Self::build_versioned_transaction_sync(
-      async_rpc_client.get_latest_blockhash().await?,
+      recent_blockhash,
       &self.payer,
       &new_ixs,
       &self.address_lookup_tables,
       &self.signers,
+      self.message_version,
     )?;
 
     Ok(vtx)
@@ -104,14 +141,21 @@ impl TransactionBuilder {
     let mut tmp_ixs: Vec<Instruction>;
 
     // try to set max compute unit limit
-    tmp_ixs = vec![ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT)];
+    tmp_ixs = self.durable_nonce.map(|durable_nonce| durable_nonce.advance_instruction()).into_iter().collect();
+    tmp_ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT));
     tmp_ixs.extend_from_slice(&self.instructions);
 
     let ixs: &[Instruction] = &tmp_ixs;
 
     // simulate transaction, 使用空的hash值，模拟执行交易
-    let pre_tx =
-      Self::build_versioned_transaction_sync(Hasher::default().result(), &self.payer, ixs, &self.address_lookup_tables, &self.signers)?;
+    let pre_tx = Self::build_versioned_transaction_sync(
+      Hasher::default().result(),
+      &self.payer,
+      ixs,
+      &self.address_lookup_tables,
+      &self.signers,
+      self.message_version,
+    )?;
     let sim_config = RpcSimulateTransactionConfig {
       sig_verify: false,
       commitment: None,
@@ -144,9 +188,19 @@ impl TransactionBuilder {
     instructions: &[Instruction],
     addr_lookup_table: &[AddressLookupTableAccount],
     keypairs: &Vec<Keypair>,
+    message_version: MessageVersion,
   ) -> Result<VersionedTransaction> {
-    let v0_message = V0Message::try_compile(payer_pubkey, instructions, addr_lookup_table, recent_blockhash)?;
-    let message = VersionedMessage::V0(v0_message);
+    let message = match message_version {
+      // 只使用能覆盖足够多账户的查找表
+      MessageVersion::V0 => {
+        let lookup_tables = lookup_table::select_lookup_tables(payer_pubkey, instructions, addr_lookup_table);
+        VersionedMessage::V0(V0Message::try_compile(payer_pubkey, instructions, &lookup_tables, recent_blockhash)?)
+      }
+      // 旧格式不支持地址查找表，所有账户都放在交易中
+      MessageVersion::Legacy => {
+        VersionedMessage::Legacy(LegacyMessage::new_with_blockhash(instructions, Some(payer_pubkey), &recent_blockhash))
+      }
+    };
 
     // let versioned_tx = VersionedTransaction::try_new(message, keypairs)?;
     // 将 try_new 的代码拷贝过来进行改造
@@ -183,6 +237,34 @@ impl TransactionBuilder {
       })
       .collect::<Result<Vec<Signature>, anyhow::Error>>()?;
 
-    Ok(VersionedTransaction { signatures, message })
+    let vtx = VersionedTransaction { signatures, message };
+    Self::validate_transaction_limits(&vtx)?;
+
+    Ok(vtx)
+  }
+
+  /// 检查交易的账户数量和序列化后的大小是否超过链上限制
+  pub fn validate_transaction_limits(vtx: &VersionedTransaction) -> Result<()> {
+    let lookup_account_count: usize = vtx
+      .message
+      .address_table_lookups()
+      .map(|lookups| lookups.iter().map(|lookup| lookup.writable_indexes.len() + lookup.readonly_indexes.len()).sum())
+      .unwrap_or_default();
+    let account_count = vtx.message.static_account_keys().len() + lookup_account_count;
+    // 旧格式交易无法使用地址查找表压缩，超出限制时只能改用 V0 格式
+    let hint = match vtx.message {
+      VersionedMessage::Legacy(_) => ", legacy transactions cannot use address lookup tables, request a V0 transaction instead",
+      VersionedMessage::V0(_) => "",
+    };
+    if account_count > MAX_TX_ACCOUNT_LOCKS {
+      return Err(anyhow::anyhow!("Transaction uses {} accounts, exceeds limit {}{}", account_count, MAX_TX_ACCOUNT_LOCKS, hint));
+    }
+
+    let tx_size = bincode::serialized_size(vtx)? as usize;
+    if tx_size > PACKET_DATA_SIZE {
+      return Err(anyhow::anyhow!("Transaction size {} bytes exceeds limit {} bytes{}", tx_size, PACKET_DATA_SIZE, hint));
+    }
+
+    Ok(())
   }
 }
@@ -7,15 +7,57 @@ use anchor_lang::prelude::*;
 use anchor_spl::memo::Memo;
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use solana_sdk::{signature::Keypair, system_program, transaction::VersionedTransaction};
-use spl_associated_token_account::get_associated_token_address;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+  compute_budget::ComputeBudgetInstruction, instruction::Instruction, message::AddressLookupTableAccount, signature::Keypair,
+  system_program, transaction::VersionedTransaction,
+};
 
 use crate::{
   constants::{BYREAL_CLMM_PROGRAM_ID, BYREAL_CLMM_ROUTING_PROGRAM_ID},
   nacos_config::{entrance::get_nacos_config, types::NacosConfig},
 };
 
-use super::build_tx::{self, TransactionBuilder};
+use super::build_tx::{self, MessageVersion, TransactionBuilder};
+use super::cu_model::{self, CuEstimate};
+use super::lookup_table;
+use super::nonce::DurableNonce;
+
+/// swap 指令前后附加的指令
+#[derive(Debug, Default, Clone)]
+pub struct ExtraInstructions {
+  /// 放在 swap 指令之前，如创建 ATA、封装 SOL
+  pub setup: Vec<Instruction>,
+  /// 放在 swap 指令之后，如解封 SOL
+  pub cleanup: Vec<Instruction>,
+}
+
+/// 构建交易的选项
+#[derive(Debug, Default, Clone)]
+pub struct SwapTxOptions {
+  /// 交易优先费，计算单元价格（以微 lamports 为单位）
+  pub cu_price: u64,
+  /// 交易消息的格式
+  pub message_version: MessageVersion,
+  /// 候选的地址查找表，V0 交易会从中选出能覆盖路由账户的查找表
+  pub address_lookup_tables: Vec<AddressLookupTableAccount>,
+  /// cu 模型的估算结果，不可信时模拟执行交易获取 cu
+  pub cu_estimate: CuEstimate,
+  /// 使用 durable nonce 代替 recent blockhash，交易在 nonce 被推进前一直有效
+  pub durable_nonce: Option<DurableNonce>,
+  /// 服务端代付时 fee payer 的私钥，构建的交易由其部分签名，钱包的签名由用户补上
+  pub fee_payer_keypair: Option<Arc<Keypair>>,
+}
+
+/// 路由合约一笔交易支持的最大跳数（池子数量）
+/// 由交易的账户数量限制推算：routing 指令有 9 个固定账户，每跳至少 9 个账户（7 个核心账户、bitmap 扩展和 tick array），
+/// 4 跳时 routing 指令至少使用 45 个账户，加上计算预算、创建 ATA、平台费等指令的账户，
+/// 穿过多个 tick array 时已接近 build_tx::MAX_TX_ACCOUNT_LOCKS（64）
+/// 账户数量和交易大小（build_tx::PACKET_DATA_SIZE）在构建交易时另外检查
+pub const MAX_ROUTE_HOPS: usize = 4;
+
+/// routing 指令的 anchor 指令标识符
+pub const ROUTING_V3_DISCRIMINATOR: [u8; 8] = [104, 36, 195, 124, 102, 53, 165, 213];
 
 /// 自定义的RoutingV3指令参数结构
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
@@ -25,15 +67,19 @@ pub struct RoutingV3Args {
   pub swap_account_counts: Vec<u8>,
 }
 
-pub async fn build_route_tx(
+/// 多跳路由的 routing 指令
+pub fn route_instruction(
   payer: &Pubkey,
   input_token_mint: &Pubkey,
   input_token_account: &Pubkey,
   amount_in: u64,
   amount_out_minimum: u64,
   swap_infos: &[SwapRouteInfo],
-  cu_price: u64,
-) -> Result<VersionedTransaction> {
+) -> Result<Instruction> {
+  if swap_infos.is_empty() || swap_infos.len() > MAX_ROUTE_HOPS {
+    return Err(anyhow::anyhow!("Route hop count {} is not in 1..={}", swap_infos.len(), MAX_ROUTE_HOPS));
+  }
+
   // 准备核心账户列表
   let mut accounts = Vec::new();
 
@@ -62,9 +108,8 @@ pub async fn build_route_tx(
     // pool_state
     accounts.push(AccountMeta::new(swap_info.pool_state, false));
 
-    // output_token_account (自动计算ATA)
-    let output_token_account = get_associated_token_address(payer, &swap_info.output_token_mint);
-    accounts.push(AccountMeta::new(output_token_account, false));
+    // output_token_account
+    accounts.push(AccountMeta::new(swap_info.output_token_account, false));
 
     // input_vault
     accounts.push(AccountMeta::new(swap_info.input_vault, false));
@@ -90,7 +135,9 @@ pub async fn build_route_tx(
       current_swap_account_count += 1;
     }
 
-    swap_account_counts.push(current_swap_account_count as u8);
+    let current_swap_account_count = u8::try_from(current_swap_account_count)
+      .map_err(|_| anyhow::anyhow!("Too many accounts in one swap: {}", current_swap_account_count))?;
+    swap_account_counts.push(current_swap_account_count);
   }
 
   // ===== 写法1 =====
@@ -103,8 +150,7 @@ pub async fn build_route_tx(
   let mut serialized_args = args.try_to_vec()?;
 
   // 3. 创建最终的指令数据：在序列化数据前添加8字节anchor指令标识符
-  // routing 指令的前缀: [104, 36, 195, 124, 102, 53, 165, 213]
-  let mut data = vec![104, 36, 195, 124, 102, 53, 165, 213];
+  let mut data = ROUTING_V3_DISCRIMINATOR.to_vec();
   data.append(&mut serialized_args);
 
   // 创建指令
@@ -126,63 +172,40 @@ pub async fn build_route_tx(
   // ])
   // .instructions()?;
 
-  let mut tx_builder = TransactionBuilder::default();
-  tx_builder.set_program(BYREAL_CLMM_ROUTING_PROGRAM_ID);
-  tx_builder.set_payer(*payer);
-  tx_builder.add_instruction(ix);
-
-  // 创建和签名交易
-  let nacos_config = get_nacos_config().await;
-  let async_rpc_client = nacos_config.get_rand_rpc();
-  let cu_factor = nacos_config.get_cu_factor();
-
-  let vtx = tx_builder.build_versioned_transaction(&async_rpc_client, cu_price, cu_factor).await?;
-
-  Ok(vtx)
+  Ok(ix)
 }
 
-pub async fn build_swap_v2_tx(
-  // nacos_config: &NacosConfig,
+/// 单池 swap_v2 指令
+pub fn swap_v2_instruction(
   payer: &Pubkey,
-  amm_config: &Pubkey,
-  pool_state: &Pubkey,
-  input_mint_account: &Pubkey,
-  output_mint_account: &Pubkey,
-  input_vault_account: &Pubkey,
-  output_vault_account: &Pubkey,
-  observation_state: &Pubkey,
-  tick_array_bitmap_extension: &Pubkey,
-  tick_array_keys: &[Pubkey],
+  input_token_account: &Pubkey,
+  swap_info: &SwapRouteInfo,
   amount: u64,
   other_amount_threshold: u64,
   sqrt_price_limit_x64: u128,
   is_base_input: bool,
-  cu_price: u64,
-) -> Result<VersionedTransaction> {
-  let input_token_account = get_associated_token_address(payer, input_mint_account);
-  let output_token_account = get_associated_token_address(payer, output_mint_account);
-
+) -> Instruction {
   let accounts = raydium_amm_v3::accounts::SwapSingleV2 {
     payer: *payer,
-    amm_config: *amm_config,
-    pool_state: *pool_state,
-    input_token_account: input_token_account,
-    output_token_account: output_token_account,
-    input_vault: *input_vault_account,
-    output_vault: *output_vault_account,
-    observation_state: *observation_state,
+    amm_config: swap_info.amm_config,
+    pool_state: swap_info.pool_state,
+    input_token_account: *input_token_account,
+    output_token_account: swap_info.output_token_account,
+    input_vault: swap_info.input_vault,
+    output_vault: swap_info.output_vault,
+    observation_state: swap_info.observation_state,
     token_program: spl_token::id(),
     token_program_2022: spl_token_2022::id(),
     memo_program: Memo::id(),
-    input_vault_mint: *input_mint_account,
-    output_vault_mint: *output_mint_account,
+    input_vault_mint: swap_info.input_token_mint,
+    output_vault_mint: swap_info.output_token_mint,
   };
   let mut remaining_accounts = Vec::new();
-  remaining_accounts.push(AccountMeta::new_readonly(*tick_array_bitmap_extension, false));
-  for tick_array in tick_array_keys {
+  if let Some(tick_array_bitmap_extension) = swap_info.tick_array_bitmap_extension {
+    remaining_accounts.push(AccountMeta::new_readonly(tick_array_bitmap_extension, false));
+  }
+  for tick_array in swap_info.tick_arrays.iter() {
     remaining_accounts.push(AccountMeta::new(*tick_array, false));
-
-    println!("===================tick_array-keys: {}", tick_array.to_string())
   }
   let args = raydium_amm_v3::instruction::SwapV2 {
     amount: amount,
@@ -190,31 +213,179 @@ pub async fn build_swap_v2_tx(
     sqrt_price_limit_x64: sqrt_price_limit_x64,
     is_base_input: is_base_input,
   };
+  let mut account_metas = accounts.to_account_metas(None);
+  account_metas.extend(remaining_accounts);
 
-  let mut tx_builder = TransactionBuilder::default();
+  Instruction { program_id: BYREAL_CLMM_PROGRAM_ID, accounts: account_metas, data: args.data() }
+}
 
-  tx_builder.set_program(BYREAL_CLMM_PROGRAM_ID);
+/// swap 指令前后加上附加指令，得到组装交易使用的 TransactionBuilder
+fn swap_tx_builder(
+  payer: &Pubkey,
+  swap_ix: Instruction,
+  extra_instructions: &ExtraInstructions,
+  tx_options: &SwapTxOptions,
+) -> TransactionBuilder {
+  let mut tx_builder = TransactionBuilder::default();
   tx_builder.set_payer(*payer);
-  tx_builder.add_build_instruction(accounts, Some(remaining_accounts), args);
+  tx_builder.set_message_version(tx_options.message_version);
+  for alt in tx_options.address_lookup_tables.iter() {
+    tx_builder.add_alt(alt.clone());
+  }
+  if let Some(durable_nonce) = tx_options.durable_nonce {
+    tx_builder.set_durable_nonce(durable_nonce);
+  }
+  if let Some(fee_payer_keypair) = &tx_options.fee_payer_keypair {
+    tx_builder.add_signer(fee_payer_keypair.insecure_clone());
+  }
+  // 创建 ATA、封装 SOL 等前置指令
+  for setup_ix in extra_instructions.setup.iter() {
+    tx_builder.add_instruction(setup_ix.clone());
+  }
+  tx_builder.add_instruction(swap_ix);
+  for cleanup_ix in extra_instructions.cleanup.iter() {
+    tx_builder.add_instruction(cleanup_ix.clone());
+  }
+  tx_builder
+}
+
+/// 组装并构建 swap 交易
+pub async fn build_swap_tx(
+  payer: &Pubkey,
+  swap_ix: Instruction,
+  extra_instructions: &ExtraInstructions,
+  tx_options: &SwapTxOptions,
+) -> Result<VersionedTransaction> {
+  let tx_builder = swap_tx_builder(payer, swap_ix, extra_instructions, tx_options);
 
+  // 创建和签名交易
   let nacos_config = get_nacos_config().await;
   let async_rpc_client = nacos_config.get_rand_rpc();
-  let cu_factor = nacos_config.get_cu_factor();
+  let compute_unit_limit = resolve_compute_unit_limit(&tx_builder, &async_rpc_client, tx_options, &nacos_config).await?;
 
-  let vtx = tx_builder.build_versioned_transaction(&async_rpc_client, cu_price, cu_factor).await?;
+  let vtx = tx_builder.build_versioned_transaction(&async_rpc_client, tx_options.cu_price, compute_unit_limit).await?;
 
   Ok(vtx)
 }
 
+/// 构建用于模拟执行的 swap 交易
+/// 使用最大的 cu limit，模拟结果反映交易实际消耗的 cu，不会因为估算偏低而失败
+pub async fn build_simulation_tx(
+  payer: &Pubkey,
+  swap_ix: Instruction,
+  extra_instructions: &ExtraInstructions,
+  tx_options: &SwapTxOptions,
+) -> Result<VersionedTransaction> {
+  let tx_builder = swap_tx_builder(payer, swap_ix, extra_instructions, tx_options);
+
+  let nacos_config = get_nacos_config().await;
+  let async_rpc_client = nacos_config.get_rand_rpc();
+  tx_builder.build_versioned_transaction(&async_rpc_client, tx_options.cu_price, build_tx::MAX_COMPUTE_UNIT_LIMIT).await
+}
+
+/// swap 交易的各部分指令，由调用方与自己的指令组装成交易
+/// 组装顺序：advance_nonce（如有）、compute budget、setup、swap、cleanup
+#[derive(Debug, Clone)]
+pub struct SwapInstructions {
+  /// 使用 durable nonce 时必须作为交易的第一条指令
+  pub advance_nonce: Option<Instruction>,
+  pub compute_budget: Vec<Instruction>,
+  pub setup: Vec<Instruction>,
+  pub swap: Instruction,
+  pub cleanup: Vec<Instruction>,
+  /// 能覆盖交易账户的地址查找表，旧格式交易为空
+  pub address_lookup_tables: Vec<Pubkey>,
+  pub compute_unit_limit: u32,
+}
+
+/// 返回 swap 交易的各部分指令，cu limit 的确定方式与构建交易时相同
+pub async fn build_swap_instructions(
+  payer: &Pubkey,
+  swap_ix: Instruction,
+  extra_instructions: &ExtraInstructions,
+  tx_options: &SwapTxOptions,
+) -> Result<SwapInstructions> {
+  let tx_builder = swap_tx_builder(payer, swap_ix.clone(), extra_instructions, tx_options);
+
+  let nacos_config = get_nacos_config().await;
+  let async_rpc_client = nacos_config.get_rand_rpc();
+  let compute_unit_limit = resolve_compute_unit_limit(&tx_builder, &async_rpc_client, tx_options, &nacos_config).await?;
+
+  let address_lookup_tables = match tx_options.message_version {
+    MessageVersion::V0 => {
+      let ixs: Vec<Instruction> =
+        extra_instructions.setup.iter().chain([&swap_ix]).chain(extra_instructions.cleanup.iter()).cloned().collect();
+      lookup_table::select_lookup_tables(payer, &ixs, &tx_options.address_lookup_tables).into_iter().map(|table| table.key).collect()
+    }
+    MessageVersion::Legacy => Vec::new(),
+  };
+
+  Ok(SwapInstructions {
+    advance_nonce: tx_options.durable_nonce.map(|durable_nonce| durable_nonce.advance_instruction()),
+    compute_budget: vec![
+      ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+      ComputeBudgetInstruction::set_compute_unit_price(tx_options.cu_price),
+    ],
+    setup: extra_instructions.setup.clone(),
+    swap: swap_ix,
+    cleanup: extra_instructions.cleanup.clone(),
+    address_lookup_tables,
+    compute_unit_limit,
+  })
+}
+
+/// 确定交易的 cu limit
+/// 模型估算可信且没有配置强制模拟时直接使用估算值，否则模拟执行交易，并用模拟结果校准模型
+async fn resolve_compute_unit_limit(
+  tx_builder: &TransactionBuilder,
+  async_rpc_client: &RpcClient,
+  tx_options: &SwapTxOptions,
+  nacos_config: &NacosConfig,
+) -> Result<u32> {
+  let estimate = &tx_options.cu_estimate;
+  let cu = if nacos_config.always_simulate_cu || !estimate.confident {
+    match tx_builder.simulate_transaction(async_rpc_client).await {
+      Ok(sim_cu) => {
+        cu_model::record_simulation(&estimate.features, sim_cu).await;
+        sim_cu
+      }
+      // 只是因为模型不可信而模拟时，模拟失败退回到模型的估算值
+      Err(e) if !nacos_config.always_simulate_cu && estimate.units > 0 => {
+        ::log::warn!("simulate transaction failed, use modelled cu {}: {}", estimate.units, e);
+        estimate.units
+      }
+      Err(e) => return Err(e),
+    }
+  } else {
+    estimate.units
+  };
+
+  Ok(TransactionBuilder::compute_unit_limit_with_margin(cu, nacos_config.get_cu_factor()))
+}
+
 /// 单个路由跳数的交换信息
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SwapRouteInfo {
   pub amm_config: Pubkey,
   pub pool_state: Pubkey,
+  /// 输入代币，路由合约中由上一跳的输出决定，不放入账户列表
+  pub input_token_mint: Pubkey,
   pub output_token_mint: Pubkey,
+  /// 接收该跳输出代币的 token 账户，需要按输出代币所属的 token program 推导
+  pub output_token_account: Pubkey,
   pub input_vault: Pubkey,
   pub output_vault: Pubkey,
   pub observation_state: Pubkey,
   pub tick_array_bitmap_extension: Option<Pubkey>,
   pub tick_arrays: Vec<Pubkey>,
 }
+
+impl SwapRouteInfo {
+  /// 该跳 swap 会写入的池子相关账户，用于估算优先费
+  pub fn writable_pool_accounts(&self) -> Vec<Pubkey> {
+    let mut accounts = vec![self.pool_state, self.input_vault, self.output_vault, self.observation_state];
+    accounts.extend(self.tick_array_bitmap_extension);
+    accounts.extend(self.tick_arrays.iter().copied());
+    accounts
+  }
+}
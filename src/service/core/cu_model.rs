@@ -0,0 +1,129 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::RwLock;
+
+/// 单次 swap（一跳）的基础 cu 消耗
+pub const HOP_BASE_CU: u64 = 45_000;
+/// swap 每多跨越一个 tick array 增加的 cu
+pub const TICK_ARRAY_CROSSING_CU: u64 = 15_000;
+/// 每个 Token-2022 代币的转账额外消耗（扩展解析、transfer-fee 计算）
+pub const TOKEN_2022_MINT_CU: u64 = 8_000;
+/// 多跳时路由合约的额外消耗
+pub const ROUTE_PROGRAM_CU: u64 = 10_000;
+/// 创建一个 ATA 的消耗
+pub const ATA_CREATE_CU: u64 = 25_000;
+/// 其他附加指令（封装/解封 SOL 等）的消耗
+pub const EXTRA_INSTRUCTION_CU: u64 = 5_000;
+
+/// 最多保留的校准样本数量
+const MAX_CALIBRATION_SAMPLES: usize = 256;
+/// 校准样本少于该数量时，模型的估算不可信
+const MIN_CALIBRATION_SAMPLES: usize = 8;
+/// 校准样本中实际/模型比值的 90 分位与 10 分位之比超过该值时，模型的估算不可信
+const MAX_CALIBRATION_SPREAD: f64 = 1.3;
+/// 单跳跨越的 tick array 超过该数量时，模型的估算不可信
+const MAX_CONFIDENT_TICK_ARRAY_CROSSINGS: usize = 2;
+
+lazy_static::lazy_static! {
+  static ref CU_CALIBRATION: Arc<RwLock<CuCalibration>> = Arc::new(RwLock::new(CuCalibration::default()));
+}
+
+/// 单跳 swap 影响 cu 的特征
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HopCuFeatures {
+  /// swap 使用的 tick array 数量
+  pub tick_arrays: usize,
+  /// 该跳输入、输出代币中 Token-2022 代币的数量
+  pub token_2022_mints: usize,
+}
+
+impl HopCuFeatures {
+  /// token_programs: 该跳输入、输出代币所属的 token program
+  pub fn new(tick_arrays: usize, token_programs: [&Pubkey; 2]) -> Self {
+    Self { tick_arrays, token_2022_mints: token_programs.iter().filter(|program_id| ***program_id == spl_token_2022::id()).count() }
+  }
+
+  /// 跨越的 tick array 数量，第一个 tick array 包含当前价格，不算跨越
+  pub fn tick_array_crossings(&self) -> usize {
+    self.tick_arrays.saturating_sub(1)
+  }
+}
+
+/// 整笔交易影响 cu 的特征
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RouteCuFeatures {
+  pub hops: Vec<HopCuFeatures>,
+  /// 交易中新建 ATA 的数量
+  pub ata_creates: usize,
+  /// 其他附加指令的数量
+  pub extra_instructions: usize,
+}
+
+impl RouteCuFeatures {
+  /// 未经校准的模型估算值
+  pub fn model_cu(&self) -> u64 {
+    let hops_cu: u64 = self
+      .hops
+      .iter()
+      .map(|hop| {
+        HOP_BASE_CU + hop.tick_array_crossings() as u64 * TICK_ARRAY_CROSSING_CU + hop.token_2022_mints as u64 * TOKEN_2022_MINT_CU
+      })
+      .sum();
+    let route_cu = if self.hops.len() > 1 { ROUTE_PROGRAM_CU } else { 0 };
+    hops_cu + route_cu + self.ata_creates as u64 * ATA_CREATE_CU + self.extra_instructions as u64 * EXTRA_INSTRUCTION_CU
+  }
+}
+
+/// cu 估算结果
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CuEstimate {
+  pub features: RouteCuFeatures,
+  /// 校准后的估算值，未包含 cu_factor 的余量
+  pub units: u64,
+  /// 估算是否可信，不可信时需要模拟执行交易获取 cu
+  pub confident: bool,
+}
+
+/// 模型校准：记录模拟执行得到的实际 cu 与模型估算值的比值
+#[derive(Debug, Default, Clone)]
+pub struct CuCalibration {
+  ratios: VecDeque<f64>,
+}
+
+impl CuCalibration {
+  pub fn record(&mut self, features: &RouteCuFeatures, simulated_cu: u64) {
+    let model_cu = features.model_cu();
+    if model_cu == 0 || simulated_cu == 0 {
+      return;
+    }
+    if self.ratios.len() == MAX_CALIBRATION_SAMPLES {
+      self.ratios.pop_front();
+    }
+    self.ratios.push_back(simulated_cu as f64 / model_cu as f64);
+  }
+
+  pub fn estimate(&self, features: &RouteCuFeatures) -> CuEstimate {
+    let mut ratios: Vec<f64> = self.ratios.iter().copied().collect();
+    ratios.sort_by(f64::total_cmp);
+    let calibrated = ratios.len() >= MIN_CALIBRATION_SAMPLES;
+    let percentile = |p: usize| ratios[(ratios.len() - 1) * p / 100];
+
+    // 使用中位数校准，样本不足时直接使用模型值
+    let factor = if calibrated { percentile(50) } else { 1.0 };
+    let stable = calibrated && percentile(90) / percentile(10) <= MAX_CALIBRATION_SPREAD;
+    let simple_route = features.hops.iter().all(|hop| hop.tick_array_crossings() <= MAX_CONFIDENT_TICK_ARRAY_CROSSINGS);
+
+    CuEstimate { features: features.clone(), units: (features.model_cu() as f64 * factor).ceil() as u64, confident: stable && simple_route }
+  }
+}
+
+/// 使用全局的校准数据估算 cu
+pub async fn estimate_cu(features: &RouteCuFeatures) -> CuEstimate {
+  CU_CALIBRATION.read().await.estimate(features)
+}
+
+/// 记录一次模拟执行的结果，用于校准模型
+pub async fn record_simulation(features: &RouteCuFeatures, simulated_cu: u64) {
+  CU_CALIBRATION.write().await.record(features, simulated_cu);
+}
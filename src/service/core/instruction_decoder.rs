@@ -0,0 +1,233 @@
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{Result, anyhow};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+  instruction::CompiledInstruction,
+  message::{AddressLookupTableAccount, VersionedMessage},
+  pubkey::Pubkey,
+  transaction::VersionedTransaction,
+};
+
+use super::clmm_program::{ROUTING_V3_DISCRIMINATOR, RoutingV3Args, SwapRouteInfo};
+use super::lookup_table;
+use crate::{
+  constants::{BYREAL_CLMM_PROGRAM_ID, BYREAL_CLMM_ROUTING_PROGRAM_ID},
+  service::router_service::types::PoolInfo,
+};
+
+/// swap_v2 指令中 remaining accounts 之前的账户数量
+const SWAP_V2_ACCOUNT_COUNT: usize = 13;
+/// routing 指令中各跳账户之前的账户数量
+const ROUTING_ACCOUNT_COUNT: usize = 9;
+/// routing 指令中每一跳的核心账户数量
+const ROUTING_HOP_ACCOUNT_COUNT: usize = 7;
+
+/// 解析出的 clmm 和路由合约指令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClmmInstruction {
+  /// clmm 合约的单池 swap_v2 指令
+  SwapV2 {
+    payer: Pubkey,
+    input_token_account: Pubkey,
+    hop: SwapRouteInfo,
+    amount: u64,
+    /// 指定输入时为最少输出，指定输出时为最多输入
+    other_amount_threshold: u64,
+    sqrt_price_limit_x64: u128,
+    is_base_input: bool,
+  },
+  /// 路由合约的多跳 routing 指令，只支持指定输入
+  Routing {
+    payer: Pubkey,
+    input_token_account: Pubkey,
+    input_token_mint: Pubkey,
+    amount_in: u64,
+    amount_out_minimum: u64,
+    hops: Vec<SwapRouteInfo>,
+  },
+}
+
+impl ClmmInstruction {
+  /// 经过的池子
+  pub fn pools(&self) -> Vec<Pubkey> {
+    match self {
+      ClmmInstruction::SwapV2 { hop, .. } => vec![hop.pool_state],
+      ClmmInstruction::Routing { hops, .. } => hops.iter().map(|hop| hop.pool_state).collect(),
+    }
+  }
+
+  /// 最少输出数量，指定输出的 swap 没有最少输出
+  pub fn min_amount_out(&self) -> Option<u64> {
+    match self {
+      ClmmInstruction::SwapV2 { other_amount_threshold, is_base_input: true, .. } => Some(*other_amount_threshold),
+      ClmmInstruction::SwapV2 { .. } => None,
+      ClmmInstruction::Routing { amount_out_minimum, .. } => Some(*amount_out_minimum),
+    }
+  }
+}
+
+/// 交易中解析出的指令及其在交易中的序号
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+  pub index: usize,
+  pub instruction: ClmmInstruction,
+}
+
+/// 交易的完整账户列表：静态账户、查找表中的可写账户、查找表中的只读账户
+/// 查找表需要由调用方提供
+pub fn resolve_account_keys(message: &VersionedMessage, lookup_tables: &[AddressLookupTableAccount]) -> Result<Vec<Pubkey>> {
+  let mut account_keys = message.static_account_keys().to_vec();
+  let Some(lookups) = message.address_table_lookups() else {
+    return Ok(account_keys);
+  };
+
+  let (mut writable, mut readonly) = (Vec::new(), Vec::new());
+  for lookup in lookups {
+    let table = lookup_tables
+      .iter()
+      .find(|table| table.key == lookup.account_key)
+      .ok_or_else(|| anyhow!("Address lookup table {} is not provided", lookup.account_key))?;
+    let address = |index: &u8| {
+      table.addresses.get(usize::from(*index)).copied().ok_or_else(|| anyhow!("Index {} out of range in lookup table {}", index, table.key))
+    };
+    for index in lookup.writable_indexes.iter() {
+      writable.push(address(index)?);
+    }
+    for index in lookup.readonly_indexes.iter() {
+      readonly.push(address(index)?);
+    }
+  }
+  account_keys.extend(writable);
+  account_keys.extend(readonly);
+  Ok(account_keys)
+}
+
+/// 解析交易中的 swap_v2 和 routing 指令，其他指令被跳过
+pub fn decode_transaction(vtx: &VersionedTransaction, lookup_tables: &[AddressLookupTableAccount]) -> Result<Vec<DecodedInstruction>> {
+  let account_keys = resolve_account_keys(&vtx.message, lookup_tables)?;
+  let mut decoded = Vec::new();
+  for (index, ix) in vtx.message.instructions().iter().enumerate() {
+    if let Some(instruction) =
+      decode_instruction(&account_keys, ix).map_err(|e| anyhow!("Failed to decode instruction {}: {}", index, e))?
+    {
+      decoded.push(DecodedInstruction { index, instruction });
+    }
+  }
+  Ok(decoded)
+}
+
+/// 从链上获取交易使用的地址查找表后解析交易
+pub async fn decode_transaction_with_rpc(rpc_client: &RpcClient, vtx: &VersionedTransaction) -> Result<Vec<DecodedInstruction>> {
+  let table_keys: Vec<Pubkey> = vtx.message.address_table_lookups().unwrap_or_default().iter().map(|lookup| lookup.account_key).collect();
+  let lookup_tables =
+    if table_keys.is_empty() { Vec::new() } else { lookup_table::load_lookup_tables_for_decoding(rpc_client, &table_keys).await? };
+  decode_transaction(vtx, &lookup_tables)
+}
+
+/// 解析单条指令，不是 swap_v2 或 routing 指令时返回 None
+pub fn decode_instruction(account_keys: &[Pubkey], ix: &CompiledInstruction) -> Result<Option<ClmmInstruction>> {
+  let key = |index: u8| account_keys.get(usize::from(index)).copied().ok_or_else(|| anyhow!("Account index {} out of range", index));
+  let program_id = key(ix.program_id_index)?;
+  let accounts = ix.accounts.iter().map(|index| key(*index)).collect::<Result<Vec<Pubkey>>>()?;
+  if ix.data.len() < 8 {
+    return Ok(None);
+  }
+  let (discriminator, args) = ix.data.split_at(8);
+
+  if program_id == BYREAL_CLMM_PROGRAM_ID && discriminator == raydium_amm_v3::instruction::SwapV2::DISCRIMINATOR {
+    decode_swap_v2(&accounts, args).map(Some)
+  } else if program_id == BYREAL_CLMM_ROUTING_PROGRAM_ID && discriminator == ROUTING_V3_DISCRIMINATOR {
+    decode_routing(&accounts, args).map(Some)
+  } else {
+    Ok(None)
+  }
+}
+
+fn decode_swap_v2(accounts: &[Pubkey], args: &[u8]) -> Result<ClmmInstruction> {
+  if accounts.len() < SWAP_V2_ACCOUNT_COUNT {
+    return Err(anyhow!("swap_v2 requires at least {} accounts, got {}", SWAP_V2_ACCOUNT_COUNT, accounts.len()));
+  }
+  let args = raydium_amm_v3::instruction::SwapV2::try_from_slice(args)?;
+  // 账户顺序与 SwapSingleV2 一致，之后是 tick_array_bitmap_extension（可选）和 tick arrays
+  let (tick_array_bitmap_extension, tick_arrays) = split_tick_accounts(&accounts[2], &accounts[SWAP_V2_ACCOUNT_COUNT..]);
+  let hop = SwapRouteInfo {
+    amm_config: accounts[1],
+    pool_state: accounts[2],
+    input_token_mint: accounts[11],
+    output_token_mint: accounts[12],
+    output_token_account: accounts[4],
+    input_vault: accounts[5],
+    output_vault: accounts[6],
+    observation_state: accounts[7],
+    tick_array_bitmap_extension,
+    tick_arrays,
+  };
+  Ok(ClmmInstruction::SwapV2 {
+    payer: accounts[0],
+    input_token_account: accounts[3],
+    hop,
+    amount: args.amount,
+    other_amount_threshold: args.other_amount_threshold,
+    sqrt_price_limit_x64: args.sqrt_price_limit_x64,
+    is_base_input: args.is_base_input,
+  })
+}
+
+fn decode_routing(accounts: &[Pubkey], args: &[u8]) -> Result<ClmmInstruction> {
+  if accounts.len() < ROUTING_ACCOUNT_COUNT {
+    return Err(anyhow!("routing requires at least {} accounts, got {}", ROUTING_ACCOUNT_COUNT, accounts.len()));
+  }
+  let args = RoutingV3Args::try_from_slice(args)?;
+  let hop_accounts_total: usize = args.swap_account_counts.iter().map(|count| usize::from(*count)).sum();
+  if hop_accounts_total != accounts.len() - ROUTING_ACCOUNT_COUNT {
+    return Err(anyhow!(
+      "swap account counts sum to {}, but routing has {} hop accounts",
+      hop_accounts_total,
+      accounts.len() - ROUTING_ACCOUNT_COUNT
+    ));
+  }
+
+  // 每一跳的输入代币是上一跳的输出代币，第一跳是 routing 的输入代币
+  let input_token_mint = accounts[3];
+  let mut hops = Vec::with_capacity(args.swap_account_counts.len());
+  let mut hop_accounts = &accounts[ROUTING_ACCOUNT_COUNT..];
+  for count in args.swap_account_counts.iter() {
+    let count = usize::from(*count);
+    if count < ROUTING_HOP_ACCOUNT_COUNT {
+      return Err(anyhow!("Each hop requires at least {} accounts, got {}", ROUTING_HOP_ACCOUNT_COUNT, count));
+    }
+    let (current, rest) = hop_accounts.split_at(count);
+    hop_accounts = rest;
+    let (tick_array_bitmap_extension, tick_arrays) = split_tick_accounts(&current[1], &current[ROUTING_HOP_ACCOUNT_COUNT..]);
+    let hop_input_mint = hops.last().map_or(input_token_mint, |hop: &SwapRouteInfo| hop.output_token_mint);
+    hops.push(SwapRouteInfo {
+      amm_config: current[0],
+      pool_state: current[1],
+      input_token_mint: hop_input_mint,
+      output_token_mint: current[5],
+      output_token_account: current[2],
+      input_vault: current[3],
+      output_vault: current[4],
+      observation_state: current[6],
+      tick_array_bitmap_extension,
+      tick_arrays,
+    });
+  }
+
+  Ok(ClmmInstruction::Routing {
+    payer: accounts[1],
+    input_token_account: accounts[2],
+    input_token_mint,
+    amount_in: args.amount_in,
+    amount_out_minimum: args.amount_out_minimum,
+    hops,
+  })
+}
+
+/// 池子的附加账户：第一个账户是池子的 tick_array_bitmap_extension 时单独取出，其余为 tick arrays
+fn split_tick_accounts(pool_state: &Pubkey, accounts: &[Pubkey]) -> (Option<Pubkey>, Vec<Pubkey>) {
+  match accounts.split_first() {
+    Some((first, rest)) if *first == PoolInfo::tick_array_bitmap_extension_key(pool_state) => (Some(*first), rest.to_vec()),
+    _ => (None, accounts.to_vec()),
+  }
+}
@@ -0,0 +1,282 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use raydium_amm_v3::states::TickArrayState;
+use solana_address_lookup_table_interface::{
+  instruction as lookup_table_instruction,
+  state::{AddressLookupTable, LOOKUP_TABLE_MAX_ADDRESSES},
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, message::AddressLookupTableAccount, pubkey::Pubkey};
+use tokio::sync::RwLock;
+
+use crate::service::router_service::types::PoolInfo;
+
+/// 地址查找表内容的缓存时间，超过后重新从链上获取
+pub const LOOKUP_TABLE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// 使用一个查找表至少要节省的账户数量
+/// 每个查找表占用 32 字节地址和 2 字节长度，每个通过查找表加载的账户节省 31 字节
+const MIN_ACCOUNTS_PER_LOOKUP_TABLE: usize = 2;
+
+/// 单条 extend 指令最多写入的地址数量，避免超过交易大小限制
+pub const MAX_EXTEND_ADDRESSES_PER_IX: usize = 20;
+
+/// 维护查找表时，当前价格所在 tick array 左右各保留的 tick array 数量
+pub const HOT_TICK_ARRAY_RADIUS: i32 = 2;
+
+/// 新建查找表时从最近多少个 slot 中选取产生了区块的 slot
+/// 创建指令要求 recent_slot 仍在 SlotHashes 中（最近 512 个 slot），留出签名和发送的时间
+pub const RECENT_BLOCK_SLOT_RANGE: u64 = 150;
+
+lazy_static::lazy_static! {
+  /// 查找表的内容和是否仍然可用（未停用），不存在的查找表为 None
+  static ref LOOKUP_TABLE_CACHE: Arc<RwLock<HashMap<Pubkey, (Option<(AddressLookupTableAccount, bool)>, Instant)>>> =
+    Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// 获取构建新交易可以使用的地址查找表，优先使用缓存
+/// 不存在或已经停用的查找表会被跳过
+pub async fn load_lookup_tables(rpc_client: &RpcClient, keys: &[Pubkey]) -> Result<Vec<AddressLookupTableAccount>> {
+  let tables = load_lookup_table_states(rpc_client, keys).await?;
+  Ok(tables.into_iter().filter_map(|(table, active)| active.then_some(table)).collect())
+}
+
+/// 获取解析已有交易需要的地址查找表，优先使用缓存
+/// 停用但尚未关闭的查找表仍可读取，交易在停用前构建时仍然引用它，因此保留；不存在的查找表会被跳过
+pub async fn load_lookup_tables_for_decoding(rpc_client: &RpcClient, keys: &[Pubkey]) -> Result<Vec<AddressLookupTableAccount>> {
+  let tables = load_lookup_table_states(rpc_client, keys).await?;
+  Ok(tables.into_iter().map(|(table, _)| table).collect())
+}
+
+/// 获取查找表的内容和是否未停用，按 keys 的顺序返回，不存在的查找表会被跳过
+async fn load_lookup_table_states(rpc_client: &RpcClient, keys: &[Pubkey]) -> Result<Vec<(AddressLookupTableAccount, bool)>> {
+  let now = Instant::now();
+  let mut tables = HashMap::new();
+  let mut stale_keys = Vec::new();
+  {
+    let cache = LOOKUP_TABLE_CACHE.read().await;
+    for key in keys {
+      match cache.get(key) {
+        Some((table, loaded_at)) if now.duration_since(*loaded_at) < LOOKUP_TABLE_CACHE_TTL => {
+          tables.insert(*key, table.clone());
+        }
+        _ => stale_keys.push(*key),
+      }
+    }
+  }
+
+  if !stale_keys.is_empty() {
+    let accounts = rpc_client.get_multiple_accounts(&stale_keys).await?;
+    let mut cache = LOOKUP_TABLE_CACHE.write().await;
+    for (key, account) in stale_keys.into_iter().zip(accounts) {
+      let table = match account {
+        Some(account) => {
+          let table =
+            AddressLookupTable::deserialize(&account.data).map_err(|e| anyhow::anyhow!("Invalid address lookup table {}: {}", key, e))?;
+          // 停用的查找表在关闭前仍可读取，但不能再用于新交易
+          Some((AddressLookupTableAccount { key, addresses: table.addresses.to_vec() }, table.meta.deactivation_slot == u64::MAX))
+        }
+        None => None,
+      };
+      cache.insert(key, (table.clone(), now));
+      tables.insert(key, table);
+    }
+  }
+
+  // 保持配置中的顺序
+  Ok(keys.iter().filter_map(|key| tables.remove(key).flatten()).collect())
+}
+
+/// 从候选的查找表中选出交易需要使用的查找表
+/// 每次选择能覆盖最多剩余账户的查找表，直到剩余的查找表都不值得使用
+/// 签名账户和被调用的程序必须放在交易中，不能通过查找表加载
+pub fn select_lookup_tables(
+  payer: &Pubkey,
+  instructions: &[Instruction],
+  candidates: &[AddressLookupTableAccount],
+) -> Vec<AddressLookupTableAccount> {
+  let program_ids: HashSet<Pubkey> = instructions.iter().map(|ix| ix.program_id).collect();
+  let mut uncovered: HashSet<Pubkey> = instructions
+    .iter()
+    .flat_map(|ix| ix.accounts.iter())
+    .filter(|meta| !meta.is_signer && meta.pubkey != *payer && !program_ids.contains(&meta.pubkey))
+    .map(|meta| meta.pubkey)
+    .collect();
+
+  let mut selected = Vec::new();
+  let mut remaining: Vec<&AddressLookupTableAccount> = candidates.iter().collect();
+  loop {
+    let best = remaining
+      .iter()
+      .enumerate()
+      .map(|(idx, table)| (idx, table.addresses.iter().filter(|address| uncovered.contains(*address)).count()))
+      .max_by_key(|(_, covered)| *covered);
+    let Some((idx, covered)) = best else { break };
+    if covered < MIN_ACCOUNTS_PER_LOOKUP_TABLE {
+      break;
+    }
+
+    let table = remaining.swap_remove(idx);
+    for address in table.addresses.iter() {
+      uncovered.remove(address);
+    }
+    selected.push(table.clone());
+  }
+
+  selected
+}
+
+/// 链上已存在的查找表（包括已停用的）
+#[derive(Debug, Clone)]
+pub struct ExistingLookupTable {
+  pub key: Pubkey,
+  pub authority: Option<Pubkey>,
+  pub deactivated: bool,
+  pub addresses: Vec<Pubkey>,
+}
+
+/// 查找表维护动作，每个动作对应一笔需要 authority 签名的交易
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupTableAction {
+  Create { table: Pubkey, recent_slot: u64 },
+  Extend { table: Pubkey, addresses: Vec<Pubkey> },
+  Deactivate { table: Pubkey },
+}
+
+impl LookupTableAction {
+  /// 生成动作对应的指令，authority 同时作为 payer
+  pub fn instructions(&self, authority: &Pubkey) -> Vec<Instruction> {
+    match self {
+      LookupTableAction::Create { recent_slot, .. } => {
+        vec![lookup_table_instruction::create_lookup_table(*authority, *authority, *recent_slot).0]
+      }
+      LookupTableAction::Extend { table, addresses } => {
+        vec![lookup_table_instruction::extend_lookup_table(*table, *authority, Some(*authority), addresses.clone())]
+      }
+      LookupTableAction::Deactivate { table } => vec![lookup_table_instruction::deactivate_lookup_table(*table, *authority)],
+    }
+  }
+}
+
+/// 池子交易时常用的账户：池子、金库、预言机、配置、bitmap 扩展以及当前价格附近的 tick array
+pub fn pool_lookup_addresses(pool: &PoolInfo, tick_array_radius: i32) -> Vec<Pubkey> {
+  let base_info = &pool.base_info;
+  let mut addresses = vec![
+    base_info.id,
+    base_info.amm_config,
+    base_info.token_vault_a,
+    base_info.token_vault_b,
+    base_info.observation_key,
+    base_info.tick_array_bitmap_extension_key,
+  ];
+
+  let tick_count = TickArrayState::tick_count(base_info.tick_spacing);
+  let current_start_index = TickArrayState::get_array_start_index(pool.dynamic_info.tick_current, base_info.tick_spacing);
+  addresses.extend(
+    pool
+      .dynamic_info
+      .all_tick_array_state
+      .iter()
+      .filter(|tick_array| ((tick_array.start_tick_index - current_start_index) / tick_count).abs() <= tick_array_radius)
+      .map(|tick_array| PoolInfo::get_pda_tick_array_address(&base_info.id, tick_array.start_tick_index)),
+  );
+
+  addresses
+}
+
+/// 期望写入查找表的地址：只包含 hot_pools 中的池子，按 hot_pools 的顺序去重
+/// 不在 pools 中的热门池子会被跳过
+pub fn hot_pool_lookup_addresses(pools: &[PoolInfo], hot_pools: &[Pubkey], tick_array_radius: i32) -> Vec<Pubkey> {
+  let mut seen = HashSet::new();
+  hot_pools
+    .iter()
+    .filter_map(|hot_pool| pools.iter().find(|pool| pool.base_info.id == *hot_pool))
+    .flat_map(|pool| pool_lookup_addresses(pool, tick_array_radius))
+    .filter(|address| seen.insert(*address))
+    .collect()
+}
+
+/// 获取链上查找表的原始状态，不存在的查找表会被跳过
+pub async fn fetch_existing_lookup_tables(rpc_client: &RpcClient, keys: &[Pubkey]) -> Result<Vec<ExistingLookupTable>> {
+  let accounts = rpc_client.get_multiple_accounts(keys).await?;
+  let mut tables = Vec::with_capacity(keys.len());
+  for (key, account) in keys.iter().zip(accounts) {
+    let Some(account) = account else { continue };
+    let table =
+      AddressLookupTable::deserialize(&account.data).map_err(|e| anyhow::anyhow!("Invalid address lookup table {}: {}", key, e))?;
+    tables.push(ExistingLookupTable {
+      key: *key,
+      authority: table.meta.authority,
+      deactivated: table.meta.deactivation_slot != u64::MAX,
+      addresses: table.addresses.to_vec(),
+    });
+  }
+  Ok(tables)
+}
+
+/// 最近产生了区块的 slot，从新到旧，用于推导新建查找表的地址
+/// 跳过的 slot 不在 SlotHashes 中，用它创建查找表会失败
+pub async fn fetch_recent_block_slots(rpc_client: &RpcClient) -> Result<Vec<u64>> {
+  let slot = rpc_client.get_slot().await?;
+  let mut slots = rpc_client.get_blocks(slot.saturating_sub(RECENT_BLOCK_SLOT_RANGE), Some(slot)).await?;
+  slots.reverse();
+  Ok(slots)
+}
+
+/// 对比期望的地址集合与已有查找表，生成维护计划
+/// - 已有的有效查找表覆盖的地址不再重复写入
+/// - 缺失的地址优先写入 authority 管理的、未满的查找表，不够时新建查找表
+/// - authority 管理的、不包含任何期望地址的查找表会被停用
+/// 新建查找表依次使用 recent_slots 中的 slot 推导地址，保证同一批次内地址不重复，slot 不够时返回错误
+/// recent_slots 为最近产生了区块的 slot，见 fetch_recent_block_slots
+pub fn plan_lookup_table_maintenance(
+  desired: &[Pubkey],
+  existing: &[ExistingLookupTable],
+  authority: &Pubkey,
+  recent_slots: &[u64],
+) -> Result<Vec<LookupTableAction>> {
+  let desired_set: HashSet<Pubkey> = desired.iter().copied().collect();
+  let active_tables: Vec<&ExistingLookupTable> = existing.iter().filter(|table| !table.deactivated).collect();
+  let covered: HashSet<Pubkey> = active_tables.iter().flat_map(|table| table.addresses.iter().copied()).collect();
+
+  let mut seen = HashSet::new();
+  let missing: Vec<Pubkey> = desired.iter().copied().filter(|address| !covered.contains(address) && seen.insert(*address)).collect();
+
+  let mut actions = Vec::new();
+  let mut writable_tables = Vec::new();
+  for table in active_tables.iter().filter(|table| table.authority == Some(*authority)) {
+    if !table.addresses.iter().any(|address| desired_set.contains(address)) {
+      actions.push(LookupTableAction::Deactivate { table: table.key });
+    } else if table.addresses.len() < LOOKUP_TABLE_MAX_ADDRESSES {
+      writable_tables.push((table.key, LOOKUP_TABLE_MAX_ADDRESSES - table.addresses.len()));
+    }
+  }
+
+  let mut remaining = missing.as_slice();
+  let mut slots = recent_slots.iter();
+  while !remaining.is_empty() {
+    let (table, capacity) = match writable_tables.pop() {
+      Some(writable) => writable,
+      None => {
+        let recent_slot =
+          *slots.next().ok_or(anyhow::anyhow!("Not enough recent slots to create lookup tables: {}", recent_slots.len()))?;
+        let table = lookup_table_instruction::derive_lookup_table_address(authority, recent_slot).0;
+        actions.push(LookupTableAction::Create { table, recent_slot });
+        (table, LOOKUP_TABLE_MAX_ADDRESSES)
+      }
+    };
+
+    let (batch, rest) = remaining.split_at(capacity.min(remaining.len()));
+    for chunk in batch.chunks(MAX_EXTEND_ADDRESSES_PER_IX) {
+      actions.push(LookupTableAction::Extend { table, addresses: chunk.to_vec() });
+    }
+    remaining = rest;
+  }
+
+  Ok(actions)
+}
@@ -1,5 +1,20 @@
 pub mod account_puller;
 pub mod build_tx;
 pub mod clmm_program;
+pub mod cu_model;
+pub mod instruction_decoder;
+pub mod lookup_table;
+pub mod nonce;
+pub mod platform_fee;
+pub mod preflight;
+pub mod priority_fee;
 pub mod result_utils;
+pub mod simulation;
+pub mod sponsor;
+#[cfg(test)]
+mod test;
+pub mod tip;
+pub mod tx_check;
+pub mod token_account;
+pub mod tx_sender;
 pub mod types;
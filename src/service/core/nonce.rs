@@ -0,0 +1,69 @@
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+  hash::Hash,
+  instruction::Instruction,
+  message::VersionedMessage,
+  nonce::state::{State, Versions},
+  pubkey::Pubkey,
+  system_program,
+};
+use solana_system_interface::instruction::{self as system_instruction, SystemInstruction};
+
+/// 使用 durable nonce 构建交易需要的信息
+/// 交易的第一条指令为 advance_nonce_account，并使用 nonce 值代替 recent blockhash，交易在 nonce 被推进前一直有效
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DurableNonce {
+  /// nonce 账户
+  pub account: Pubkey,
+  /// nonce 账户的 authority，需要对交易签名
+  pub authority: Pubkey,
+  /// nonce 账户中当前保存的 nonce 值
+  pub nonce: Hash,
+}
+
+impl DurableNonce {
+  pub fn advance_instruction(&self) -> Instruction {
+    system_instruction::advance_nonce_account(&self.account, &self.authority)
+  }
+}
+
+/// 解析 nonce 账户数据，返回 authority 和当前的 nonce 值
+pub fn parse_nonce_account(account: &Pubkey, data: &[u8]) -> Result<(Pubkey, Hash)> {
+  let versions: Versions = bincode::deserialize(data).map_err(|e| anyhow::anyhow!("Invalid nonce account {}: {}", account, e))?;
+  match versions.state() {
+    State::Initialized(data) => Ok((data.authority, data.blockhash())),
+    State::Uninitialized => Err(anyhow::anyhow!("Nonce account {} is not initialized", account)),
+  }
+}
+
+/// 从链上获取 nonce 账户当前的 authority 和 nonce 值
+pub async fn fetch_nonce_account(rpc_client: &RpcClient, account: &Pubkey) -> Result<(Pubkey, Hash)> {
+  let nonce_account = rpc_client.get_account(account).await?;
+  if nonce_account.owner != system_program::id() {
+    return Err(anyhow::anyhow!("Nonce account {} is not owned by the system program", account));
+  }
+  parse_nonce_account(account, &nonce_account.data)
+}
+
+/// 从链上获取 nonce 账户，并检查 authority
+pub async fn load_durable_nonce(rpc_client: &RpcClient, account: &Pubkey, authority: &Pubkey) -> Result<DurableNonce> {
+  let (nonce_authority, nonce) = fetch_nonce_account(rpc_client, account).await?;
+  if nonce_authority != *authority {
+    return Err(anyhow::anyhow!("Nonce account {} authority is {}, not {}", account, nonce_authority, authority));
+  }
+  Ok(DurableNonce { account: *account, authority: *authority, nonce })
+}
+
+/// 交易的第一条指令是 advance_nonce_account 时，返回使用的 nonce 账户
+pub fn durable_nonce_account(message: &VersionedMessage) -> Option<Pubkey> {
+  let account_keys = message.static_account_keys();
+  let ix = message.instructions().first()?;
+  if account_keys.get(usize::from(ix.program_id_index)) != Some(&system_program::id()) {
+    return None;
+  }
+  match bincode::deserialize(&ix.data) {
+    Ok(SystemInstruction::AdvanceNonceAccount) => account_keys.get(usize::from(*ix.accounts.first()?)).copied(),
+    _ => None,
+  }
+}
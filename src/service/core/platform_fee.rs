@@ -0,0 +1,163 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+
+use super::types::MintAccountBaseInfo;
+
+/// 基点的分母
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// 平台费从输入代币还是输出代币中扣除
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeSide {
+  Input,
+  #[default]
+  Output,
+}
+
+/// 平台费配置
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlatformFeeConfig {
+  /// 平台费，基点
+  pub fee_bps: u16,
+  pub side: FeeSide,
+  /// 接收平台费的钱包，平台费转入该钱包对应代币的 ATA
+  pub fee_owner: Pubkey,
+  /// 请求中指定推荐人时，平台费中分给推荐人的比例，基点
+  pub referral_share_bps: u16,
+}
+
+/// 扣除平台费后的询价金额
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlatformFeeAmounts {
+  /// 用户支付的输入数量（包含从输入中扣除的平台费）
+  pub input_amount: u64,
+  /// 用户收到的输出数量（已扣除从输出中扣除的平台费）
+  pub output_amount: u64,
+  pub fee_amount: u64,
+}
+
+impl PlatformFeeConfig {
+  fn fee_of(&self, amount: u64) -> u64 {
+    (u128::from(amount) * u128::from(self.fee_bps) / u128::from(BPS_DENOMINATOR)) as u64
+  }
+
+  /// 路由计算时使用的指定数量
+  /// - 指定输入、从输入扣费：先扣除平台费，剩余部分参与 swap
+  /// - 指定输出、从输出扣费：swap 需要多输出平台费的部分，使扣费后用户收到指定的数量
+  pub fn route_amount(&self, amount: u64, base_input: bool) -> u64 {
+    match (base_input, self.side) {
+      (true, FeeSide::Input) => amount - self.fee_of(amount),
+      (false, FeeSide::Output) => {
+        let net_bps = u128::from(BPS_DENOMINATOR - u64::from(self.fee_bps));
+        (u128::from(amount) * u128::from(BPS_DENOMINATOR)).div_ceil(net_bps) as u64
+      }
+      _ => amount,
+    }
+  }
+
+  /// 根据路由计算出的 swap 输入、输出数量，得到用户视角的金额和平台费
+  pub fn quote_amounts(&self, amount: u64, base_input: bool, swap_amount_in: u64, swap_amount_out: u64) -> PlatformFeeAmounts {
+    match (base_input, self.side) {
+      (true, FeeSide::Input) => {
+        PlatformFeeAmounts { input_amount: amount, output_amount: swap_amount_out, fee_amount: self.fee_of(amount) }
+      }
+      (true, FeeSide::Output) => {
+        let fee_amount = self.fee_of(swap_amount_out);
+        PlatformFeeAmounts { input_amount: swap_amount_in, output_amount: swap_amount_out - fee_amount, fee_amount }
+      }
+      (false, FeeSide::Input) => {
+        let fee_amount = self.fee_of(swap_amount_in);
+        PlatformFeeAmounts { input_amount: swap_amount_in + fee_amount, output_amount: amount, fee_amount }
+      }
+      (false, FeeSide::Output) => {
+        PlatformFeeAmounts { input_amount: swap_amount_in, output_amount: amount, fee_amount: swap_amount_out.saturating_sub(amount) }
+      }
+    }
+  }
+}
+
+/// 根据询价结果中的金额和平台费，得到 swap 指令的指定数量和滑点阈值
+/// 询价结果中的金额和阈值都是用户视角的（包含/扣除了平台费），swap 指令需要换算回 swap 本身的数量
+pub fn swap_amounts(
+  fee_side: FeeSide,
+  fee_amount: u64,
+  base_input: bool,
+  input_amount: u64,
+  output_amount: u64,
+  other_amount_threshold: u64,
+) -> Result<(u64, u64)> {
+  let specified_amount = if base_input { input_amount } else { output_amount };
+  let overflow = || anyhow::anyhow!("Platform fee {} does not fit the quoted amounts", fee_amount);
+  match (base_input, fee_side) {
+    // 输入中的平台费先转走，swap 使用剩余部分
+    (true, FeeSide::Input) => Ok((specified_amount.checked_sub(fee_amount).ok_or_else(overflow)?, other_amount_threshold)),
+    (false, FeeSide::Input) => Ok((specified_amount, other_amount_threshold.checked_sub(fee_amount).ok_or_else(overflow)?)),
+    // 输出中的平台费在 swap 后转走，swap 的输出需要多出平台费的部分
+    (true, FeeSide::Output) => Ok((specified_amount, other_amount_threshold.checked_add(fee_amount).ok_or_else(overflow)?)),
+    (false, FeeSide::Output) => Ok((specified_amount.checked_add(fee_amount).ok_or_else(overflow)?, other_amount_threshold)),
+  }
+}
+
+/// 平台费的接收账户和金额
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeTransfer {
+  pub destination: Pubkey,
+  pub amount: u64,
+}
+
+/// 计算平台费的转账：推荐人分得 referral_share_bps 的部分，其余归平台
+/// 接收账户为各自钱包持有该代币的 ATA，按代币所属的 token program 推导
+pub fn fee_transfers(
+  config: &PlatformFeeConfig,
+  fee_mint: &MintAccountBaseInfo,
+  fee_amount: u64,
+  referral_owner: Option<&Pubkey>,
+) -> Vec<FeeTransfer> {
+  let referral_amount = match referral_owner {
+    Some(_) => (u128::from(fee_amount) * u128::from(config.referral_share_bps) / u128::from(BPS_DENOMINATOR)) as u64,
+    None => 0,
+  };
+
+  let mut transfers = Vec::new();
+  if fee_amount > referral_amount {
+    transfers.push(FeeTransfer {
+      destination: get_associated_token_address_with_program_id(&config.fee_owner, &fee_mint.mint, &fee_mint.program_id),
+      amount: fee_amount - referral_amount,
+    });
+  }
+  if let (Some(referral_owner), true) = (referral_owner, referral_amount > 0) {
+    transfers.push(FeeTransfer {
+      destination: get_associated_token_address_with_program_id(referral_owner, &fee_mint.mint, &fee_mint.program_id),
+      amount: referral_amount,
+    });
+  }
+  transfers
+}
+
+/// 平台费的转账指令
+/// 使用 transfer_checked，Token-2022 代币（如带 transfer-fee 扩展）只能使用该指令转账
+pub fn fee_transfer_instructions(
+  fee_mint: &MintAccountBaseInfo,
+  source: &Pubkey,
+  authority: &Pubkey,
+  transfers: &[FeeTransfer],
+) -> Result<Vec<Instruction>> {
+  transfers
+    .iter()
+    .map(|transfer| {
+      Ok(spl_token_2022::instruction::transfer_checked(
+        &fee_mint.program_id,
+        source,
+        &fee_mint.mint,
+        &transfer.destination,
+        authority,
+        &[],
+        transfer.amount,
+        fee_mint.decimal,
+      )?)
+    })
+    .collect()
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_token_2022::{
+  extension::StateWithExtensions,
+  state::{Account as TokenAccount, AccountState},
+};
+
+use super::account_puller::AccountPuller;
+use crate::service::router_service::error::PreflightError;
+
+/// 每个签名的交易费
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// 交易需要钱包支付的 SOL
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SolRequirement {
+  /// 交易的签名数量
+  pub signatures: u64,
+  pub compute_unit_limit: u32,
+  /// 计算单元价格（以微 lamports 为单位）
+  pub cu_price: u64,
+  /// 新建账户的租金
+  pub rent_lamports: u64,
+  /// 封装为 WSOL 的数量
+  pub wrap_lamports: u64,
+  pub tip_lamports: u64,
+  /// 交易后付款账户至少需要保留的余额（系统账户的免租金最低余额），不是交易的支出
+  pub rent_exempt_minimum: u64,
+}
+
+impl SolRequirement {
+  /// 签名费和优先费
+  pub fn fee_lamports(&self) -> u64 {
+    let priority_fee = (u128::from(self.cu_price) * u128::from(self.compute_unit_limit)).div_ceil(1_000_000) as u64;
+    self.signatures * LAMPORTS_PER_SIGNATURE + priority_fee
+  }
+
+  pub fn total(&self) -> u64 {
+    self.fee_lamports() + self.rent_lamports + self.wrap_lamports + self.tip_lamports
+  }
+
+  /// 交易前付款账户需要的余额：支出的 SOL（包括新建 ATA 的租金）加上交易后需要保留的免租金最低余额
+  /// 不需要支出时账户不会被扣款，不要求保留余额
+  pub fn required_balance(&self) -> u64 {
+    match self.total() {
+      0 => 0,
+      total => total + self.rent_exempt_minimum,
+    }
+  }
+}
+
+/// 交易前需要检查的 token 账户
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenAccountCheck {
+  pub account: Pubkey,
+  pub mint: Pubkey,
+  /// 交易前账户中至少需要的余额
+  pub required_amount: u64,
+  /// 作为转出账户时，账户需要由钱包持有
+  pub owner: Option<Pubkey>,
+  /// 账户在交易中创建（ATA），交易前可以不存在
+  pub created_if_missing: bool,
+}
+
+/// 检查 token 账户的代币、持有人、冻结状态和余额
+pub fn check_token_account(check: &TokenAccountCheck, account: Option<&Account>) -> Result<(), PreflightError> {
+  let account_key = check.account.to_string();
+  let Some(account) = account else {
+    if check.created_if_missing && check.required_amount == 0 {
+      return Ok(());
+    }
+    return Err(PreflightError::TokenAccountNotFound { account: account_key });
+  };
+  if account.owner != spl_token::id() && account.owner != spl_token_2022::id() {
+    return Err(PreflightError::InvalidTokenAccount { account: account_key });
+  }
+  let state = StateWithExtensions::<TokenAccount>::unpack(&account.data)
+    .map_err(|_| PreflightError::InvalidTokenAccount { account: account_key.clone() })?
+    .base;
+
+  if state.mint != check.mint {
+    return Err(PreflightError::TokenAccountMintMismatch {
+      account: account_key,
+      expected: check.mint.to_string(),
+      actual: state.mint.to_string(),
+    });
+  }
+  if let Some(owner) = check.owner.filter(|owner| *owner != state.owner) {
+    return Err(PreflightError::TokenAccountOwnerMismatch {
+      account: account_key,
+      expected: owner.to_string(),
+      actual: state.owner.to_string(),
+    });
+  }
+  if state.state == AccountState::Frozen {
+    return Err(PreflightError::TokenAccountFrozen { account: account_key });
+  }
+  if state.amount < check.required_amount {
+    return Err(PreflightError::InsufficientTokenBalance {
+      mint: check.mint.to_string(),
+      account: account_key,
+      required: check.required_amount,
+      available: state.amount,
+    });
+  }
+  Ok(())
+}
+
+/// 检查钱包的 SOL 是否足够支付交易，并且交易后仍满足免租金的最低余额
+pub fn check_sol_balance(payer: &Pubkey, available: u64, requirement: &SolRequirement) -> Result<(), PreflightError> {
+  let required = requirement.required_balance();
+  if available < required {
+    return Err(PreflightError::InsufficientSolBalance { account: payer.to_string(), required, available });
+  }
+  Ok(())
+}
+
+/// 构建交易前检查钱包的 SOL 和 token 账户，避免用户只看到模拟执行失败
+pub async fn check_balances(
+  account_puller: &AccountPuller<'_>,
+  payer: &Pubkey,
+  token_checks: &[TokenAccountCheck],
+  sol_requirement: &SolRequirement,
+) -> Result<()> {
+  let keys: Vec<Pubkey> = std::iter::once(*payer).chain(token_checks.iter().map(|check| check.account)).collect();
+  let accounts = account_puller.get_multi_accounts(&keys).await?;
+
+  for (check, (_, account)) in token_checks.iter().zip(accounts[1..].iter()) {
+    check_token_account(check, account.as_ref())?;
+  }
+  let available = accounts[0].1.as_ref().map_or(0, |account| account.lamports);
+  check_sol_balance(payer, available, sol_requirement)?;
+  Ok(())
+}
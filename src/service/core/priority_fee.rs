@@ -0,0 +1,66 @@
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+/// 请求中的计算单元价格为空或为该值时，自动估算优先费
+pub const AUTO_COMPUTE_UNIT_PRICE: &str = "auto";
+
+/// getRecentPrioritizationFees 单次最多查询的账户数量
+pub const MAX_PRIORITIZATION_FEE_ACCOUNTS: usize = 128;
+
+/// 默认使用最近 slot 优先费的 75 分位
+pub const DEFAULT_PRIORITY_FEE_PERCENTILE: u8 = 75;
+
+/// 默认的优先费上限（微 lamports）
+pub const DEFAULT_MAX_PRIORITY_FEE_MICRO_LAMPORTS: u64 = 1_000_000;
+
+/// 自动估算优先费的参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityFeeConfig {
+  /// 取最近各 slot 优先费的百分位，1..=100
+  pub percentile: u8,
+  /// 优先费下限（微 lamports）
+  pub min_micro_lamports: u64,
+  /// 优先费上限（微 lamports）
+  pub max_micro_lamports: u64,
+}
+
+impl Default for PriorityFeeConfig {
+  fn default() -> Self {
+    Self { percentile: DEFAULT_PRIORITY_FEE_PERCENTILE, min_micro_lamports: 0, max_micro_lamports: DEFAULT_MAX_PRIORITY_FEE_MICRO_LAMPORTS }
+  }
+}
+
+impl PriorityFeeConfig {
+  /// 按百分位从最近的优先费中选取价格，并限制在上下限之间
+  pub fn select(&self, recent_fees: &[u64]) -> u64 {
+    let mut fees = recent_fees.to_vec();
+    fees.sort_unstable();
+    let fee = match fees.len() {
+      0 => 0,
+      len => {
+        let percentile = usize::from(self.percentile.clamp(1, 100));
+        fees[(len * percentile).div_ceil(100) - 1]
+      }
+    };
+    fee.max(self.min_micro_lamports).min(self.max_micro_lamports)
+  }
+}
+
+/// 解析请求中的计算单元价格，为空或为 auto 时返回 None，表示需要自动估算
+pub fn parse_compute_unit_price(value: &str) -> Result<Option<u64>> {
+  let value = value.trim();
+  if value.is_empty() || value.eq_ignore_ascii_case(AUTO_COMPUTE_UNIT_PRICE) {
+    return Ok(None);
+  }
+  value.parse().map(Some).map_err(|e| anyhow::anyhow!("Invalid compute unit price {}: {}", value, e))
+}
+
+/// 根据交易写入的账户，使用 getRecentPrioritizationFees 估算计算单元价格
+/// 返回的每个 slot 的优先费是写入这些账户的交易中的最低价格
+pub async fn estimate_compute_unit_price(rpc_client: &RpcClient, writable_accounts: &[Pubkey], config: &PriorityFeeConfig) -> Result<u64> {
+  let accounts = &writable_accounts[..writable_accounts.len().min(MAX_PRIORITIZATION_FEE_ACCOUNTS)];
+  let recent_fees = rpc_client.get_recent_prioritization_fees(accounts).await?;
+  let fees: Vec<u64> = recent_fees.iter().map(|fee| fee.prioritization_fee).collect();
+  Ok(config.select(&fees))
+}
@@ -3,6 +3,8 @@ use core::result::Result;
 use anyhow;
 use tonic::{Response, Status};
 
+use crate::service::router_service::error::{PreflightError, QuoteError, SponsorError, SwapComputeError, TxCheckError};
+
 /// Converts a Result<T, anyhow::Error> into a Result<Response<T>, Status>.
 pub fn convert_result<T>(input: Result<T, anyhow::Error>) -> Result<Response<T>, Status> {
   match input {
@@ -10,9 +12,26 @@ pub fn convert_result<T>(input: Result<T, anyhow::Error>) -> Result<Response<T>,
     Err(err) => {
       println!("=========================================");
       println!("Error: {}", err);
-      // todo: 这里需要根据错误类型进行分类处理
-      let status = Status::internal(format!("Internal error: {}", err));
-      Err(status)
+      Err(error_status(&err))
     }
   }
 }
+
+/// Converts an anyhow::Error into a Status.
+pub fn error_status(err: &anyhow::Error) -> Status {
+  // swap 计算错误、询价校验错误、余额检查错误、交易检查错误、代付错误有明确的 gRPC 状态码，其他错误暂时归为 internal
+  // todo: 这里需要根据错误类型进行分类处理
+  if let Some(swap_err) = err.downcast_ref::<SwapComputeError>() {
+    Status::from(swap_err)
+  } else if let Some(quote_err) = err.downcast_ref::<QuoteError>() {
+    Status::from(quote_err)
+  } else if let Some(preflight_err) = err.downcast_ref::<PreflightError>() {
+    Status::from(preflight_err)
+  } else if let Some(tx_check_err) = err.downcast_ref::<TxCheckError>() {
+    Status::from(tx_check_err)
+  } else if let Some(sponsor_err) = err.downcast_ref::<SponsorError>() {
+    Status::from(sponsor_err)
+  } else {
+    Status::internal(format!("Internal error: {}", err))
+  }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use solana_account_decoder_client_types::{UiAccount, UiAccountData, UiAccountEncoding};
+use solana_client::{
+  nonblocking::rpc_client::RpcClient,
+  rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use solana_sdk::{
+  instruction::InstructionError, message::VersionedMessage, pubkey::Pubkey, transaction::TransactionError,
+  transaction::VersionedTransaction,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Account as TokenAccount};
+
+use super::nonce;
+
+/// 模拟执行失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationError {
+  /// 交易错误的描述
+  pub message: String,
+  /// 出错的指令序号，交易级别的错误（如余额不足以支付手续费）为空
+  pub instruction_index: Option<u8>,
+  /// 出错指令所属的程序
+  pub program_id: Option<Pubkey>,
+  /// 程序返回的自定义错误码
+  pub error_code: Option<u32>,
+  /// anchor 程序日志中的错误名，如 TooLittleOutputReceived
+  pub error_name: Option<String>,
+}
+
+/// 单个代币的余额变化，同一代币的多个账户合并计算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBalanceChange {
+  pub mint: Pubkey,
+  pub pre_amount: u64,
+  pub post_amount: u64,
+}
+
+impl TokenBalanceChange {
+  pub fn change(&self) -> i128 {
+    i128::from(self.post_amount) - i128::from(self.pre_amount)
+  }
+}
+
+/// 模拟执行的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationReport {
+  pub error: Option<SimulationError>,
+  pub logs: Vec<String>,
+  pub units_consumed: u64,
+  pub token_balance_changes: Vec<TokenBalanceChange>,
+  /// 钱包 SOL 余额的变化，包含交易费、租金和封装/解封的 SOL
+  pub sol_balance_change: i64,
+}
+
+/// 从交易错误和日志中解析出错的指令、程序和错误码
+pub fn parse_simulation_error(message: &VersionedMessage, err: &TransactionError, logs: &[String]) -> SimulationError {
+  let (instruction_index, error_code) = match err {
+    TransactionError::InstructionError(index, InstructionError::Custom(code)) => (Some(*index), Some(*code)),
+    TransactionError::InstructionError(index, _) => (Some(*index), None),
+    _ => (None, None),
+  };
+  // 程序地址一定在静态账户中
+  let program_id = instruction_index
+    .and_then(|index| message.instructions().get(usize::from(index)))
+    .and_then(|ix| message.static_account_keys().get(usize::from(ix.program_id_index)))
+    .copied();
+  let error_name = logs.iter().rev().find_map(|log| anchor_error_name(log));
+
+  SimulationError { message: err.to_string(), instruction_index, program_id, error_code, error_name }
+}
+
+/// 解析 anchor 的错误日志：`AnchorError ... Error Code: TooLittleOutputReceived. Error Number: 6022. ...`
+fn anchor_error_name(log: &str) -> Option<String> {
+  if !log.contains("AnchorError") {
+    return None;
+  }
+  let (_, rest) = log.split_once("Error Code: ")?;
+  let (name, _) = rest.split_once('.')?;
+  Some(name.to_string())
+}
+
+/// token 账户的余额，账户不存在或不是 token 账户时为空
+pub fn token_account_amount(data: &[u8]) -> Option<u64> {
+  StateWithExtensions::<TokenAccount>::unpack(data).ok().map(|state| state.base.amount)
+}
+
+/// 按代币合并 token 账户的余额变化，顺序与 token_accounts 中代币第一次出现的顺序一致
+/// 账户在交易前不存在（交易中创建）或交易后不存在（交易中关闭）时余额按 0 计算
+pub fn token_balance_changes(
+  token_accounts: &[(Pubkey, Pubkey)],
+  pre_amounts: &[Option<u64>],
+  post_amounts: &[Option<u64>],
+) -> Vec<TokenBalanceChange> {
+  let mut changes: Vec<TokenBalanceChange> = Vec::new();
+  for ((_, mint), (pre_amount, post_amount)) in token_accounts.iter().zip(pre_amounts.iter().zip(post_amounts.iter())) {
+    let index = match changes.iter().position(|change| change.mint == *mint) {
+      Some(index) => index,
+      None => {
+        changes.push(TokenBalanceChange { mint: *mint, pre_amount: 0, post_amount: 0 });
+        changes.len() - 1
+      }
+    };
+    changes[index].pre_amount += pre_amount.unwrap_or_default();
+    changes[index].post_amount += post_amount.unwrap_or_default();
+  }
+  changes
+}
+
+fn ui_account_data(account: &UiAccount) -> Option<Vec<u8>> {
+  match &account.data {
+    UiAccountData::Binary(data, UiAccountEncoding::Base64) => BASE64_STANDARD.decode(data).ok(),
+    _ => None,
+  }
+}
+
+/// 模拟执行交易，并返回钱包和 token 账户在交易前后的余额变化
+/// token_accounts: 钱包在交易中涉及的 token 账户及其代币
+pub async fn simulate_with_balances(
+  rpc_client: &RpcClient,
+  vtx: &VersionedTransaction,
+  wallet: &Pubkey,
+  token_accounts: &[(Pubkey, Pubkey)],
+) -> Result<SimulationReport> {
+  // 同一个账户只统计一次
+  let mut seen = HashSet::new();
+  let token_accounts: Vec<(Pubkey, Pubkey)> = token_accounts.iter().filter(|(account, _)| seen.insert(*account)).copied().collect();
+  let addresses: Vec<Pubkey> = std::iter::once(*wallet).chain(token_accounts.iter().map(|(account, _)| *account)).collect();
+
+  let pre_accounts = rpc_client.get_multiple_accounts(&addresses).await?;
+  let sim_config = RpcSimulateTransactionConfig {
+    sig_verify: false,
+    // durable nonce 交易必须使用 nonce 值作为 blockhash，不能替换
+    replace_recent_blockhash: nonce::durable_nonce_account(&vtx.message).is_none(),
+    accounts: Some(RpcSimulateTransactionAccountsConfig {
+      encoding: Some(UiAccountEncoding::Base64),
+      addresses: addresses.iter().map(|address| address.to_string()).collect(),
+    }),
+    ..Default::default()
+  };
+  let simulate_result = rpc_client.simulate_transaction_with_config(vtx, sim_config).await?.value;
+  let logs = simulate_result.logs.unwrap_or_default();
+  let error = simulate_result.err.map(|err| parse_simulation_error(&vtx.message, &err, &logs));
+
+  // 模拟失败时不返回账户状态，余额没有变化
+  let post_accounts = simulate_result.accounts.unwrap_or_default();
+  let pre_lamports = pre_accounts[0].as_ref().map_or(0, |account| account.lamports);
+  let post_lamports = match post_accounts.first() {
+    Some(account) => account.as_ref().map_or(0, |account| account.lamports),
+    None => pre_lamports,
+  };
+  let pre_amounts: Vec<Option<u64>> =
+    pre_accounts[1..].iter().map(|account| account.as_ref().and_then(|account| token_account_amount(&account.data))).collect();
+  let post_amounts: Vec<Option<u64>> = if post_accounts.is_empty() {
+    pre_amounts.clone()
+  } else {
+    post_accounts[1..]
+      .iter()
+      .map(|account| account.as_ref().and_then(ui_account_data).and_then(|data| token_account_amount(&data)))
+      .collect()
+  };
+
+  Ok(SimulationReport {
+    error,
+    logs,
+    units_consumed: simulate_result.units_consumed.unwrap_or_default(),
+    token_balance_changes: token_balance_changes(&token_accounts, &pre_amounts, &post_amounts),
+    sol_balance_change: post_lamports as i64 - pre_lamports as i64,
+  })
+}
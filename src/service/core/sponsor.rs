@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use solana_sdk::{
+  compute_budget,
+  message::VersionedMessage,
+  pubkey::Pubkey,
+  signature::{Keypair, read_keypair, read_keypair_file},
+  signer::Signer,
+};
+use tokio::sync::RwLock;
+
+use super::preflight::SolRequirement;
+use crate::service::router_service::error::SponsorError;
+
+/// 代付账户单笔交易默认最多支付的交易费（签名费和优先费），lamports
+pub const DEFAULT_SPONSOR_MAX_LAMPORTS_PER_TX: u64 = 5_000_000;
+/// 代付账户单笔交易默认最多支付的租金，lamports，约为一个 token 账户的租金
+/// 新建的账户归用户所有，关闭后租金退给用户，限制远低于交易费
+pub const DEFAULT_SPONSOR_MAX_RENT_LAMPORTS_PER_TX: u64 = 2_100_000;
+/// 代付交易默认允许的最高计算单元价格（微 lamports）
+pub const DEFAULT_SPONSOR_MAX_COMPUTE_UNIT_PRICE: u64 = 1_000_000;
+
+/// ComputeBudgetInstruction::SetComputeUnitLimit 的指令序号
+const SET_COMPUTE_UNIT_LIMIT_TAG: u8 = 2;
+
+lazy_static::lazy_static! {
+  /// 已加载的代付账户，按私钥来源缓存，配置变化时重新加载
+  static ref SPONSOR_KEYPAIR: Arc<RwLock<Option<(KeypairSource, Arc<Keypair>)>>> = Arc::new(RwLock::new(None));
+}
+
+/// 代付账户私钥的来源，私钥本身不放在 nacos 配置中
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeypairSource {
+  /// keypair 文件（solana-keygen 生成的 JSON 字节数组）
+  File(String),
+  /// 保存 JSON 字节数组格式私钥的环境变量名
+  Env(String),
+}
+
+/// 代付的限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SponsorLimits {
+  /// 单笔交易最多支付的交易费（签名费和优先费）
+  pub max_lamports_per_tx: u64,
+  /// 单笔交易最多支付的新建账户租金
+  pub max_rent_lamports_per_tx: u64,
+  /// 允许的最高计算单元价格（微 lamports）
+  pub max_compute_unit_price: u64,
+}
+
+impl Default for SponsorLimits {
+  fn default() -> Self {
+    Self {
+      max_lamports_per_tx: DEFAULT_SPONSOR_MAX_LAMPORTS_PER_TX,
+      max_rent_lamports_per_tx: DEFAULT_SPONSOR_MAX_RENT_LAMPORTS_PER_TX,
+      max_compute_unit_price: DEFAULT_SPONSOR_MAX_COMPUTE_UNIT_PRICE,
+    }
+  }
+}
+
+impl SponsorLimits {
+  /// 检查代付账户需要支付的 SOL 是否超过限制，交易费和租金分别限制
+  /// 小费由用户指定，不由代付账户支付
+  pub fn check(&self, requirement: &SolRequirement) -> Result<(), SponsorError> {
+    if requirement.tip_lamports > 0 || requirement.wrap_lamports > 0 {
+      return Err(SponsorError::Unsupported("paying tips or wrapped SOL"));
+    }
+    if requirement.cu_price > self.max_compute_unit_price {
+      return Err(SponsorError::ComputeUnitPriceExceedsLimit { price: requirement.cu_price, limit: self.max_compute_unit_price });
+    }
+    let fee = requirement.fee_lamports();
+    if fee > self.max_lamports_per_tx {
+      return Err(SponsorError::CostExceedsLimit { required: fee, limit: self.max_lamports_per_tx });
+    }
+    if requirement.rent_lamports > self.max_rent_lamports_per_tx {
+      return Err(SponsorError::RentExceedsLimit { required: requirement.rent_lamports, limit: self.max_rent_lamports_per_tx });
+    }
+    Ok(())
+  }
+}
+
+/// 代付配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SponsorConfig {
+  pub source: KeypairSource,
+  pub limits: SponsorLimits,
+}
+
+/// 代付账户：作为交易的 fee payer 支付交易费和租金，并对交易部分签名
+#[derive(Debug, Clone)]
+pub struct Sponsor {
+  pub keypair: Arc<Keypair>,
+  pub limits: SponsorLimits,
+}
+
+impl Sponsor {
+  pub fn pubkey(&self) -> Pubkey {
+    self.keypair.pubkey()
+  }
+}
+
+/// 从文件或环境变量读取私钥
+pub fn read_sponsor_keypair(source: &KeypairSource) -> Result<Keypair> {
+  match source {
+    KeypairSource::File(path) => {
+      read_keypair_file(path).map_err(|e| anyhow::anyhow!("Failed to read sponsor keypair file {}: {}", path, e))
+    }
+    KeypairSource::Env(name) => {
+      let secret = std::env::var(name).map_err(|e| anyhow::anyhow!("Failed to read sponsor keypair env {}: {}", name, e))?;
+      // 不在错误信息中输出私钥内容
+      read_keypair(&mut secret.trim().as_bytes()).map_err(|_| anyhow::anyhow!("Invalid sponsor keypair in env {}", name))
+    }
+  }
+}
+
+/// 获取代付账户，私钥只在第一次使用或来源变化时读取
+pub async fn load_sponsor(config: &SponsorConfig) -> Result<Sponsor> {
+  if let Some((_, keypair)) = SPONSOR_KEYPAIR.read().await.as_ref().filter(|(source, _)| *source == config.source) {
+    return Ok(Sponsor { keypair: keypair.clone(), limits: config.limits });
+  }
+  let keypair = Arc::new(read_sponsor_keypair(&config.source)?);
+  *SPONSOR_KEYPAIR.write().await = Some((config.source.clone(), keypair.clone()));
+  Ok(Sponsor { keypair, limits: config.limits })
+}
+
+/// 交易中设置的计算单元上限，没有设置时返回 None
+pub fn compute_unit_limit(message: &VersionedMessage) -> Option<u32> {
+  let account_keys = message.static_account_keys();
+  message.instructions().iter().find_map(|ix| {
+    if account_keys.get(usize::from(ix.program_id_index)) != Some(&compute_budget::ID) {
+      return None;
+    }
+    // borsh 编码：1 字节的指令序号，SetComputeUnitLimit 之后是 u32
+    match ix.data.as_slice() {
+      [SET_COMPUTE_UNIT_LIMIT_TAG, limit @ ..] => limit.try_into().ok().map(u32::from_le_bytes),
+      _ => None,
+    }
+  })
+}
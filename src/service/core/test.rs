@@ -0,0 +1,1215 @@
+/// core 模块测试共用的账户和交易构造
+#[cfg(test)]
+mod fixtures {
+  use litesvm::LiteSVM;
+  use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    message::{Message, VersionedMessage},
+    program_pack::Pack,
+    pubkey::Pubkey,
+  };
+  use spl_token::state::{Account as TokenAccount, Mint};
+
+  use crate::{
+    constants::WSOL_MINT,
+    service::{core::clmm_program::SwapRouteInfo, router_service::types::PoolInfo},
+  };
+
+  /// 在 SVM 中创建 WSOL 的 mint 账户，LiteSVM 默认只加载 SPL 程序
+  pub fn set_native_mint(svm: &mut LiteSVM) {
+    let mut data = vec![0u8; Mint::LEN];
+    Mint { decimals: 9, is_initialized: true, ..Default::default() }.pack_into_slice(&mut data);
+    let account = Account {
+      lamports: svm.minimum_balance_for_rent_exemption(Mint::LEN),
+      data,
+      owner: spl_token::id(),
+      executable: false,
+      rent_epoch: 0,
+    };
+    svm.set_account(WSOL_MINT, account).unwrap();
+  }
+
+  /// SPL Token 程序持有的 token 账户
+  pub fn token_account(state: TokenAccount, lamports: u64) -> Account {
+    let mut data = vec![0u8; TokenAccount::LEN];
+    state.pack_into_slice(&mut data);
+    Account { lamports, data, owner: spl_token::id(), executable: false, rent_epoch: 0 }
+  }
+
+  /// 单个池子的 swap 账户，tick_array_bitmap_extension 是池子的 PDA
+  pub fn swap_route_info(input_token_mint: Pubkey, output_token_mint: Pubkey) -> SwapRouteInfo {
+    let pool_state = Pubkey::new_unique();
+    SwapRouteInfo {
+      amm_config: Pubkey::new_unique(),
+      pool_state,
+      input_token_mint,
+      output_token_mint,
+      output_token_account: Pubkey::new_unique(),
+      input_vault: Pubkey::new_unique(),
+      output_vault: Pubkey::new_unique(),
+      observation_state: Pubkey::new_unique(),
+      tick_array_bitmap_extension: Some(PoolInfo::tick_array_bitmap_extension_key(&pool_state)),
+      tick_arrays: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+    }
+  }
+
+  /// 使用随机 blockhash 的 legacy 消息
+  pub fn legacy_message(payer: &Pubkey, instructions: &[Instruction]) -> VersionedMessage {
+    VersionedMessage::Legacy(Message::new_with_blockhash(instructions, Some(payer), &Hash::new_unique()))
+  }
+}
+
+/// 构建交易时对账户数量和交易大小的检查
+#[cfg(test)]
+mod transaction_limits {
+  use solana_sdk::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    message::{AddressLookupTableAccount, VersionedMessage},
+    pubkey::Pubkey,
+  };
+
+  use crate::service::core::build_tx::{MAX_TX_ACCOUNT_LOCKS, MessageVersion, TransactionBuilder};
+
+  fn build_with_accounts(account_count: usize, message_version: MessageVersion) -> anyhow::Result<()> {
+    let payer = Pubkey::new_unique();
+    let accounts = (0..account_count).map(|_| AccountMeta::new(Pubkey::new_unique(), false)).collect();
+    let ix = Instruction { program_id: Pubkey::new_unique(), accounts, data: vec![] };
+    TransactionBuilder::build_versioned_transaction_sync(Hash::default(), &payer, &[ix], &[], &vec![], message_version).map(|_| ())
+  }
+
+  #[test]
+  fn transactions_over_account_limit_are_rejected() {
+    // payer 和 program 也占用账户
+    for message_version in [MessageVersion::V0, MessageVersion::Legacy] {
+      assert!(build_with_accounts(20, message_version).is_ok());
+      assert!(build_with_accounts(MAX_TX_ACCOUNT_LOCKS, message_version).is_err());
+    }
+  }
+
+  #[test]
+  fn lookup_tables_covering_route_accounts_are_used() {
+    let payer = Pubkey::new_unique();
+    let accounts: Vec<AccountMeta> = (0..40).map(|_| AccountMeta::new(Pubkey::new_unique(), false)).collect();
+    let covering_table =
+      AddressLookupTableAccount { key: Pubkey::new_unique(), addresses: accounts.iter().map(|meta| meta.pubkey).collect() };
+    let unrelated_table = AddressLookupTableAccount { key: Pubkey::new_unique(), addresses: vec![Pubkey::new_unique(); 10] };
+    let ix = Instruction { program_id: Pubkey::new_unique(), accounts, data: vec![] };
+
+    // 不使用查找表时超过交易大小限制
+    assert!(
+      TransactionBuilder::build_versioned_transaction_sync(Hash::default(), &payer, &[ix.clone()], &[], &vec![], MessageVersion::V0)
+        .is_err()
+    );
+
+    let vtx = TransactionBuilder::build_versioned_transaction_sync(
+      Hash::default(),
+      &payer,
+      &[ix],
+      &[unrelated_table, covering_table.clone()],
+      &vec![],
+      MessageVersion::V0,
+    )
+    .unwrap();
+    let used_tables: Vec<Pubkey> = vtx.message.address_table_lookups().unwrap().iter().map(|lookup| lookup.account_key).collect();
+    assert_eq!(used_tables, vec![covering_table.key]);
+  }
+
+  #[test]
+  fn legacy_message_is_built_when_requested() {
+    let payer = Pubkey::new_unique();
+    let ix = Instruction { program_id: Pubkey::new_unique(), accounts: vec![], data: vec![] };
+    let vtx =
+      TransactionBuilder::build_versioned_transaction_sync(Hash::default(), &payer, &[ix], &[], &vec![], MessageVersion::Legacy).unwrap();
+    assert!(matches!(vtx.message, VersionedMessage::Legacy(_)));
+    assert_eq!(vtx.message.static_account_keys()[0], payer);
+  }
+}
+
+#[cfg(test)]
+mod lookup_table_maintenance {
+  use solana_address_lookup_table_interface::{instruction::derive_lookup_table_address, state::LOOKUP_TABLE_MAX_ADDRESSES};
+  use solana_sdk::pubkey::Pubkey;
+
+  use crate::service::{
+    core::lookup_table::{ExistingLookupTable, LookupTableAction, hot_pool_lookup_addresses, plan_lookup_table_maintenance},
+    router_service::types::{PoolBaseInfo, PoolInfo},
+  };
+
+  #[test]
+  fn missing_addresses_fill_existing_tables_before_creating_new_ones() {
+    let authority = Pubkey::new_unique();
+    let kept: Vec<Pubkey> = (0..250).map(|_| Pubkey::new_unique()).collect();
+    let stale = ExistingLookupTable {
+      key: Pubkey::new_unique(),
+      authority: Some(authority),
+      deactivated: false,
+      addresses: vec![Pubkey::new_unique()],
+    };
+    let writable =
+      ExistingLookupTable { key: Pubkey::new_unique(), authority: Some(authority), deactivated: false, addresses: kept.clone() };
+    let missing: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+    let desired: Vec<Pubkey> = kept.iter().chain(missing.iter()).copied().collect();
+
+    let actions = plan_lookup_table_maintenance(&desired, &[stale.clone(), writable.clone()], &authority, &[100]).unwrap();
+
+    assert_eq!(actions[0], LookupTableAction::Deactivate { table: stale.key });
+    assert_eq!(actions[1], LookupTableAction::Extend { table: writable.key, addresses: missing[..6].to_vec() });
+    let LookupTableAction::Create { table: created, recent_slot: 100 } = actions[2] else {
+      panic!("expected create, got {:?}", actions[2])
+    };
+    assert_eq!(actions[3], LookupTableAction::Extend { table: created, addresses: missing[6..].to_vec() });
+    assert_eq!(actions.len(), 4);
+
+    // 已覆盖的地址不会再生成动作
+    assert!(plan_lookup_table_maintenance(&kept, &[writable], &authority, &[]).unwrap().is_empty());
+  }
+
+  #[test]
+  fn new_tables_use_the_given_block_slots() {
+    let authority = Pubkey::new_unique();
+    let desired: Vec<Pubkey> = (0..LOOKUP_TABLE_MAX_ADDRESSES + 1).map(|_| Pubkey::new_unique()).collect();
+    // 104、102、101 被跳过，不能用于创建查找表
+    let recent_slots = [105, 103, 100];
+
+    let actions = plan_lookup_table_maintenance(&desired, &[], &authority, &recent_slots).unwrap();
+    let created: Vec<(Pubkey, u64)> = actions
+      .iter()
+      .filter_map(|action| match action {
+        LookupTableAction::Create { table, recent_slot } => Some((*table, *recent_slot)),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(created, vec![(derive_lookup_table_address(&authority, 105).0, 105), (derive_lookup_table_address(&authority, 103).0, 103)]);
+
+    // slot 不够新建所有查找表时不生成计划
+    assert!(plan_lookup_table_maintenance(&desired, &[], &authority, &recent_slots[..1]).is_err());
+  }
+
+  #[test]
+  fn only_hot_pools_are_written_to_lookup_tables() {
+    let amm_config = Pubkey::new_unique();
+    let pools: Vec<PoolInfo> = (0..3)
+      .map(|_| PoolInfo {
+        base_info: PoolBaseInfo { id: Pubkey::new_unique(), amm_config, tick_spacing: 10, ..Default::default() },
+        ..Default::default()
+      })
+      .collect();
+    let unknown_pool = Pubkey::new_unique();
+
+    let desired = hot_pool_lookup_addresses(&pools, &[pools[2].base_info.id, unknown_pool, pools[0].base_info.id], 2);
+
+    // 按热门池子的顺序写入，共用的 amm_config 只写入一次，不在配置中的池子和未知的池子被跳过
+    assert_eq!(desired[0], pools[2].base_info.id);
+    assert_eq!(desired[1], amm_config);
+    assert!(desired.contains(&pools[0].base_info.id));
+    assert!(!desired.contains(&pools[1].base_info.id));
+    assert_eq!(desired.iter().filter(|address| **address == amm_config).count(), 1);
+  }
+}
+
+#[cfg(test)]
+mod priority_fee {
+  use crate::service::core::priority_fee::{PriorityFeeConfig, parse_compute_unit_price};
+
+  #[test]
+  fn empty_or_auto_price_requests_estimation() {
+    assert_eq!(parse_compute_unit_price("").unwrap(), None);
+    assert_eq!(parse_compute_unit_price(" AUTO ").unwrap(), None);
+    assert_eq!(parse_compute_unit_price("1500").unwrap(), Some(1500));
+    assert!(parse_compute_unit_price("fast").is_err());
+  }
+
+  #[test]
+  fn estimated_price_uses_percentile_within_caps() {
+    let config = PriorityFeeConfig { percentile: 50, min_micro_lamports: 100, max_micro_lamports: 5_000 };
+    assert_eq!(config.select(&[]), 100);
+    assert_eq!(config.select(&[0, 0, 0, 300]), 100);
+    assert_eq!(config.select(&[400, 100, 300, 200]), 200);
+    assert_eq!(config.select(&[9_000, 8_000]), 5_000);
+    assert_eq!(PriorityFeeConfig { percentile: 100, ..config }.select(&[400, 100, 300, 200]), 400);
+  }
+}
+
+#[cfg(test)]
+mod cu_model {
+  use crate::service::core::cu_model::{CuCalibration, HOP_BASE_CU, HopCuFeatures, RouteCuFeatures, TICK_ARRAY_CROSSING_CU};
+
+  fn single_hop(tick_arrays: usize) -> RouteCuFeatures {
+    RouteCuFeatures { hops: vec![HopCuFeatures { tick_arrays, token_2022_mints: 0 }], ..Default::default() }
+  }
+
+  #[test]
+  fn uncalibrated_model_requires_simulation() {
+    let estimate = CuCalibration::default().estimate(&single_hop(2));
+    assert_eq!(estimate.units, HOP_BASE_CU + TICK_ARRAY_CROSSING_CU);
+    assert!(!estimate.confident);
+  }
+
+  #[test]
+  fn calibrated_model_scales_by_recorded_simulations() {
+    let mut calibration = CuCalibration::default();
+    for _ in 0..10 {
+      let features = single_hop(1);
+      calibration.record(&features, features.model_cu() * 11 / 10);
+    }
+
+    let estimate = calibration.estimate(&single_hop(2));
+    assert!(estimate.confident);
+    assert_eq!(estimate.units, ((HOP_BASE_CU + TICK_ARRAY_CROSSING_CU) as f64 * 1.1).ceil() as u64);
+
+    // 跨越很多 tick array 的 swap 仍然需要模拟
+    assert!(!calibration.estimate(&single_hop(6)).confident);
+  }
+}
+
+#[cfg(test)]
+mod tip {
+  use solana_sdk::pubkey::Pubkey;
+
+  use crate::service::core::tip::{MIN_TIP_LAMPORTS, pick_tip_account, tip_instruction};
+
+  #[test]
+  fn tip_account_is_picked_from_config() {
+    let tip_accounts: Vec<String> = (0..4).map(|_| Pubkey::new_unique().to_string()).collect();
+    let tip_account = pick_tip_account(&tip_accounts).unwrap();
+    assert!(tip_accounts.contains(&tip_account.to_string()));
+    assert!(pick_tip_account(&[]).is_err());
+    assert!(pick_tip_account(&["not-a-pubkey".to_string()]).is_err());
+  }
+
+  #[test]
+  fn tip_below_minimum_is_rejected() {
+    let (payer, tip_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+    assert!(tip_instruction(&payer, &tip_account, MIN_TIP_LAMPORTS - 1).is_err());
+    let ix = tip_instruction(&payer, &tip_account, MIN_TIP_LAMPORTS).unwrap();
+    assert_eq!(ix.accounts[1].pubkey, tip_account);
+  }
+}
+
+#[cfg(test)]
+mod durable_nonce {
+  use solana_client::nonblocking::rpc_client::RpcClient;
+  use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    nonce::state::{Data, DurableNonce as NonceValue, State, Versions},
+    pubkey::Pubkey,
+    system_program,
+  };
+
+  use crate::service::core::{
+    build_tx::TransactionBuilder,
+    nonce::{DurableNonce, durable_nonce_account, parse_nonce_account},
+  };
+
+  #[tokio::test]
+  async fn nonce_transaction_advances_nonce_first_and_uses_nonce_as_blockhash() {
+    let payer = Pubkey::new_unique();
+    let durable_nonce = DurableNonce { account: Pubkey::new_unique(), authority: payer, nonce: Hash::new_unique() };
+    let mut tx_builder = TransactionBuilder::default();
+    tx_builder.set_payer(payer);
+    tx_builder.set_durable_nonce(durable_nonce);
+    tx_builder.add_instruction(Instruction { program_id: Pubkey::new_unique(), accounts: vec![], data: vec![] });
+
+    // 使用 durable nonce 时不需要从 rpc 获取 blockhash
+    let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+    let vtx = tx_builder.build_versioned_transaction(&rpc_client, 0, 200_000).await.unwrap();
+
+    assert_eq!(*vtx.message.recent_blockhash(), durable_nonce.nonce);
+    let first_ix = &vtx.message.instructions()[0];
+    assert_eq!(vtx.message.static_account_keys()[usize::from(first_ix.program_id_index)], system_program::id());
+    assert_eq!(durable_nonce_account(&vtx.message), Some(durable_nonce.account));
+  }
+
+  #[test]
+  fn nonce_account_data_is_parsed() {
+    let (account, authority, blockhash) = (Pubkey::new_unique(), Pubkey::new_unique(), Hash::new_unique());
+    let state = State::Initialized(Data::new(authority, NonceValue::from_blockhash(&blockhash), 5_000));
+    let data = bincode::serialize(&Versions::new(state)).unwrap();
+
+    let (parsed_authority, nonce) = parse_nonce_account(&account, &data).unwrap();
+    assert_eq!(parsed_authority, authority);
+    assert_eq!(nonce, *NonceValue::from_blockhash(&blockhash).as_hash());
+    assert!(parse_nonce_account(&account, &bincode::serialize(&Versions::new(State::Uninitialized)).unwrap()).is_err());
+  }
+}
+
+#[cfg(test)]
+mod platform_fee {
+  use solana_sdk::pubkey::Pubkey;
+
+  use crate::service::core::{
+    platform_fee::{FeeSide, PlatformFeeConfig, fee_transfers, swap_amounts},
+    types::MintAccountBaseInfo,
+  };
+
+  fn config(side: FeeSide) -> PlatformFeeConfig {
+    PlatformFeeConfig { fee_bps: 100, side, fee_owner: Pubkey::new_unique(), referral_share_bps: 2_500 }
+  }
+
+  #[test]
+  fn fee_on_input_is_taken_before_the_swap() {
+    let fee = config(FeeSide::Input);
+    // 指定输入 10000，平台费 100，swap 使用 9900
+    assert_eq!(fee.route_amount(10_000, true), 9_900);
+    let amounts = fee.quote_amounts(10_000, true, 9_900, 5_000);
+    assert_eq!((amounts.input_amount, amounts.output_amount, amounts.fee_amount), (10_000, 5_000, 100));
+    assert_eq!(swap_amounts(FeeSide::Input, 100, true, 10_000, 5_000, 4_950).unwrap(), (9_900, 4_950));
+
+    // 指定输出，swap 需要输入 9900，用户额外支付平台费
+    let amounts = fee.quote_amounts(5_000, false, 9_900, 5_000);
+    assert_eq!((amounts.input_amount, amounts.fee_amount), (9_999, 99));
+    assert_eq!(swap_amounts(FeeSide::Input, 99, false, 9_999, 5_000, 10_049).unwrap(), (5_000, 9_950));
+  }
+
+  #[test]
+  fn fee_on_output_is_taken_after_the_swap() {
+    let fee = config(FeeSide::Output);
+    let amounts = fee.quote_amounts(10_000, true, 10_000, 5_000);
+    assert_eq!((amounts.output_amount, amounts.fee_amount), (4_950, 50));
+    // 用户最少收到 4900 时，swap 最少需要输出 4950
+    assert_eq!(swap_amounts(FeeSide::Output, 50, true, 10_000, 4_950, 4_900).unwrap(), (10_000, 4_950));
+
+    // 指定输出 4950，swap 需要输出 5000，扣除平台费后用户收到 4950
+    assert_eq!(fee.route_amount(4_950, false), 5_000);
+    let amounts = fee.quote_amounts(4_950, false, 10_000, 5_000);
+    assert_eq!((amounts.output_amount, amounts.fee_amount), (4_950, 50));
+    assert_eq!(swap_amounts(FeeSide::Output, 50, false, 10_000, 4_950, 10_050).unwrap(), (5_000, 10_050));
+  }
+
+  #[test]
+  fn referral_receives_its_share_of_the_fee() {
+    let fee = config(FeeSide::Output);
+    let mint = MintAccountBaseInfo { mint: Pubkey::new_unique(), program_id: spl_token_2022::id(), decimal: 6, ..Default::default() };
+    let referral = Pubkey::new_unique();
+
+    let transfers = fee_transfers(&fee, &mint, 1_000, Some(&referral));
+    assert_eq!(transfers.iter().map(|transfer| transfer.amount).collect::<Vec<_>>(), vec![750, 250]);
+    assert_eq!(
+      transfers[1].destination,
+      spl_associated_token_account::get_associated_token_address_with_program_id(&referral, &mint.mint, &spl_token_2022::id())
+    );
+    assert_eq!(fee_transfers(&fee, &mint, 1_000, None).len(), 1);
+  }
+}
+
+#[cfg(test)]
+mod swap_instructions {
+  use solana_sdk::pubkey::Pubkey;
+
+  use super::fixtures;
+  use crate::{
+    constants::{BYREAL_CLMM_PROGRAM_ID, BYREAL_CLMM_ROUTING_PROGRAM_ID},
+    service::core::clmm_program::{MAX_ROUTE_HOPS, SwapRouteInfo, route_instruction, swap_v2_instruction},
+  };
+
+  #[test]
+  fn swap_v2_instruction_appends_tick_arrays_after_fixed_accounts() {
+    let (payer, input_token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let swap_info = fixtures::swap_route_info(Pubkey::new_unique(), Pubkey::new_unique());
+    let ix = swap_v2_instruction(&payer, &input_token_account, &swap_info, 100, 90, 0, true);
+
+    assert_eq!(ix.program_id, BYREAL_CLMM_PROGRAM_ID);
+    assert_eq!(ix.accounts[0].pubkey, payer);
+    assert!(ix.accounts[0].is_signer);
+    let tail: Vec<Pubkey> = ix.accounts[ix.accounts.len() - 3..].iter().map(|meta| meta.pubkey).collect();
+    assert_eq!(tail, [vec![swap_info.tick_array_bitmap_extension.unwrap()], swap_info.tick_arrays.clone()].concat());
+  }
+
+  #[test]
+  fn route_instruction_uses_routing_program_and_checks_hops() {
+    let (payer, input_token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let (mint_a, mint_b, mint_c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let swap_infos = vec![fixtures::swap_route_info(mint_a, mint_b), fixtures::swap_route_info(mint_b, mint_c)];
+    let ix = route_instruction(&payer, &mint_a, &input_token_account, 100, 90, &swap_infos).unwrap();
+
+    assert_eq!(ix.program_id, BYREAL_CLMM_ROUTING_PROGRAM_ID);
+    assert!(ix.accounts.iter().any(|meta| meta.pubkey == payer && meta.is_signer));
+    assert!(route_instruction(&payer, &mint_a, &input_token_account, 100, 90, &[]).is_err());
+  }
+
+  #[test]
+  fn route_instruction_rejects_more_than_max_hops() {
+    let (payer, input_token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mints: Vec<Pubkey> = (0..=MAX_ROUTE_HOPS + 1).map(|_| Pubkey::new_unique()).collect();
+    let swap_infos: Vec<SwapRouteInfo> = mints.windows(2).map(|pair| fixtures::swap_route_info(pair[0], pair[1])).collect();
+
+    assert!(route_instruction(&payer, &mints[0], &input_token_account, 100, 90, &swap_infos[..MAX_ROUTE_HOPS]).is_ok());
+    assert!(route_instruction(&payer, &mints[0], &input_token_account, 100, 90, &swap_infos).is_err());
+  }
+}
+
+#[cfg(test)]
+mod simulation {
+  use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    transaction::TransactionError,
+  };
+
+  use super::fixtures;
+  use crate::service::core::simulation::{TokenBalanceChange, parse_simulation_error, token_balance_changes};
+
+  #[test]
+  fn program_error_is_parsed_from_error_and_logs() {
+    let (payer, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let message = fixtures::legacy_message(&payer, &[Instruction::new_with_bytes(program_id, &[], vec![])]);
+    let logs = vec![
+      format!("Program {} invoke [1]", program_id),
+      "Program log: AnchorError occurred. Error Code: TooLittleOutputReceived. Error Number: 6022. Error Message: Too little output."
+        .to_string(),
+      format!("Program {} failed: custom program error: 0x1786", program_id),
+    ];
+    let err = TransactionError::InstructionError(0, InstructionError::Custom(6022));
+
+    let error = parse_simulation_error(&message, &err, &logs);
+    assert_eq!(error.instruction_index, Some(0));
+    assert_eq!(error.program_id, Some(program_id));
+    assert_eq!(error.error_code, Some(6022));
+    assert_eq!(error.error_name.as_deref(), Some("TooLittleOutputReceived"));
+
+    let error = parse_simulation_error(&message, &TransactionError::InsufficientFundsForFee, &[]);
+    assert_eq!((error.instruction_index, error.program_id, error.error_code, error.error_name), (None, None, None, None));
+  }
+
+  #[test]
+  fn balance_changes_are_merged_per_mint() {
+    let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let token_accounts = [(Pubkey::new_unique(), mint_a), (Pubkey::new_unique(), mint_b), (Pubkey::new_unique(), mint_a)];
+    // 第二个账户在交易中创建，第三个账户在交易中关闭
+    let changes = token_balance_changes(&token_accounts, &[Some(100), None, Some(5)], &[Some(40), Some(70), None]);
+
+    assert_eq!(
+      changes,
+      vec![
+        TokenBalanceChange { mint: mint_a, pre_amount: 105, post_amount: 40 },
+        TokenBalanceChange { mint: mint_b, pre_amount: 0, post_amount: 70 },
+      ]
+    );
+    assert_eq!(changes[0].change(), -65);
+  }
+}
+
+#[cfg(test)]
+mod preflight {
+  use solana_sdk::{account::Account, pubkey::Pubkey};
+  use spl_token::state::{Account as TokenAccount, AccountState};
+  use tonic::{Code, Status};
+
+  use super::fixtures;
+  use crate::service::{
+    core::preflight::{SolRequirement, TokenAccountCheck, check_sol_balance, check_token_account},
+    router_service::error::PreflightError,
+  };
+
+  fn token_account(mint: Pubkey, owner: Pubkey, amount: u64, state: AccountState) -> Account {
+    fixtures::token_account(TokenAccount { mint, owner, amount, state, ..Default::default() }, 2_039_280)
+  }
+
+  #[test]
+  fn token_account_checks_report_required_and_available() {
+    let (account, mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let check = TokenAccountCheck { account, mint, required_amount: 100, owner: Some(owner), created_if_missing: false };
+
+    assert!(check_token_account(&check, Some(&token_account(mint, owner, 100, AccountState::Initialized))).is_ok());
+    assert_eq!(
+      check_token_account(&check, Some(&token_account(mint, owner, 60, AccountState::Initialized))),
+      Err(PreflightError::InsufficientTokenBalance { mint: mint.to_string(), account: account.to_string(), required: 100, available: 60 })
+    );
+    assert_eq!(
+      check_token_account(&check, Some(&token_account(mint, owner, 100, AccountState::Frozen))),
+      Err(PreflightError::TokenAccountFrozen { account: account.to_string() })
+    );
+    assert!(matches!(
+      check_token_account(&check, Some(&token_account(mint, Pubkey::new_unique(), 100, AccountState::Initialized))),
+      Err(PreflightError::TokenAccountOwnerMismatch { .. })
+    ));
+    assert!(matches!(
+      check_token_account(&check, Some(&token_account(Pubkey::new_unique(), owner, 100, AccountState::Initialized))),
+      Err(PreflightError::TokenAccountMintMismatch { .. })
+    ));
+    assert_eq!(check_token_account(&check, None), Err(PreflightError::TokenAccountNotFound { account: account.to_string() }));
+
+    // 交易中创建的 ATA 交易前可以不存在
+    let created = TokenAccountCheck { required_amount: 0, owner: None, created_if_missing: true, ..check };
+    assert!(check_token_account(&created, None).is_ok());
+  }
+
+  #[test]
+  fn sol_requirement_includes_fees_rent_wrap_and_tip() {
+    let requirement = SolRequirement {
+      signatures: 2,
+      compute_unit_limit: 200_000,
+      cu_price: 1_000,
+      rent_lamports: 2_039_280,
+      wrap_lamports: 1_000_000,
+      tip_lamports: 10_000,
+      rent_exempt_minimum: 890_880,
+    };
+    assert_eq!(requirement.fee_lamports(), 10_000 + 200);
+    assert_eq!(requirement.total(), 10_200 + 2_039_280 + 1_000_000 + 10_000);
+    assert_eq!(requirement.required_balance(), requirement.total() + 890_880);
+    // 不需要支出时不要求保留余额
+    assert_eq!(SolRequirement { rent_exempt_minimum: 890_880, ..Default::default() }.required_balance(), 0);
+
+    let payer = Pubkey::new_unique();
+    assert!(check_sol_balance(&payer, requirement.required_balance(), &requirement).is_ok());
+    assert!(check_sol_balance(&payer, requirement.total(), &requirement).is_err());
+    assert_eq!(
+      check_sol_balance(&payer, 1_000, &requirement),
+      Err(PreflightError::InsufficientSolBalance {
+        account: payer.to_string(),
+        required: requirement.required_balance(),
+        available: 1_000
+      })
+    );
+  }
+
+  #[test]
+  fn preflight_status_carries_structured_details() {
+    let err = PreflightError::InsufficientSolBalance { account: Pubkey::new_unique().to_string(), required: 3_000_000, available: 1_000 };
+    let status = Status::from(&err);
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(status.metadata().get("preflight-required").and_then(|value| value.to_str().ok()), Some("3000000"));
+    assert_eq!(status.metadata().get("preflight-available").and_then(|value| value.to_str().ok()), Some("1000"));
+  }
+}
+
+#[cfg(test)]
+mod tx_sender {
+  use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+  use solana_sdk::{
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::VersionedTransaction,
+  };
+  use tokio::sync::watch;
+
+  use super::fixtures;
+  use crate::service::core::tx_sender::{SendOptions, TransactionRpc, TxState, TxStatus, TxTracker, send_and_track};
+
+  /// 本地的 rpc 替身：按顺序返回预设的交易状态，记录发送次数
+  #[derive(Default)]
+  struct LocalRpc {
+    states: Mutex<VecDeque<Option<(TxState, u64)>>>,
+    sent: Mutex<u32>,
+    blockhash_expired: bool,
+  }
+
+  impl LocalRpc {
+    fn new(states: Vec<Option<(TxState, u64)>>, blockhash_expired: bool) -> Self {
+      Self { states: Mutex::new(states.into()), sent: Mutex::new(0), blockhash_expired }
+    }
+  }
+
+  #[tonic::async_trait]
+  impl TransactionRpc for LocalRpc {
+    async fn send_transaction_once(&self, _vtx: &VersionedTransaction) -> anyhow::Result<()> {
+      *self.sent.lock().unwrap() += 1;
+      Ok(())
+    }
+
+    async fn get_tx_state(&self, _signature: &Signature) -> anyhow::Result<Option<(TxState, u64)>> {
+      // 预设状态用完后保持最后一个状态
+      let mut states = self.states.lock().unwrap();
+      Ok(if states.len() > 1 { states.pop_front().flatten() } else { states.front().cloned().flatten() })
+    }
+
+    async fn check_blockhash_valid(&self, _blockhash: &Hash) -> anyhow::Result<bool> {
+      Ok(!self.blockhash_expired)
+    }
+
+    async fn get_current_nonce(&self, _nonce_account: &Pubkey) -> anyhow::Result<Hash> {
+      Ok(Hash::default())
+    }
+  }
+
+  fn signed_tx() -> VersionedTransaction {
+    let payer = Keypair::new();
+    let ix = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+    VersionedTransaction::try_new(fixtures::legacy_message(&payer.pubkey(), &[ix]), &[&payer]).unwrap()
+  }
+
+  fn options() -> SendOptions {
+    SendOptions { resend_interval: Duration::from_millis(1), track_timeout: Duration::from_secs(5) }
+  }
+
+  #[tokio::test]
+  async fn resends_until_finalized_and_publishes_each_state() {
+    let rpc = LocalRpc::new(
+      vec![None, None, Some((TxState::Processed, 10)), Some((TxState::Confirmed, 10)), Some((TxState::Finalized, 10))],
+      false,
+    );
+    let (status, mut receiver) = watch::channel(TxStatus::default());
+
+    let final_status = send_and_track(std::slice::from_ref(&rpc), &signed_tx(), &status, options()).await;
+    assert_eq!(final_status, TxStatus { state: TxState::Finalized, slot: 10, send_attempts: 2 });
+    assert_eq!(*rpc.sent.lock().unwrap(), 2);
+    assert!(receiver.has_changed().unwrap());
+    assert_eq!(*receiver.borrow_and_update(), final_status);
+  }
+
+  #[tokio::test]
+  async fn stops_when_blockhash_expires_or_transaction_fails() {
+    let expired = LocalRpc::new(vec![None], true);
+    let (status, _) = watch::channel(TxStatus::default());
+    let final_status = send_and_track(std::slice::from_ref(&expired), &signed_tx(), &status, options()).await;
+    assert_eq!(final_status.state, TxState::Expired);
+    assert_eq!(*expired.sent.lock().unwrap(), 0);
+
+    let failed = LocalRpc::new(vec![None, Some((TxState::Failed("custom program error: 0x1".to_string()), 7))], false);
+    let final_status = send_and_track(std::slice::from_ref(&failed), &signed_tx(), &status, options()).await;
+    assert_eq!(final_status, TxStatus { state: TxState::Failed("custom program error: 0x1".to_string()), slot: 7, send_attempts: 1 });
+  }
+
+  #[tokio::test]
+  async fn times_out_transactions_that_never_finalize() {
+    let rpc = LocalRpc::new(vec![Some((TxState::Confirmed, 5))], false);
+    let (status, _) = watch::channel(TxStatus::default());
+    let options = SendOptions { track_timeout: Duration::ZERO, ..options() };
+
+    let final_status = send_and_track(std::slice::from_ref(&rpc), &signed_tx(), &status, options).await;
+    assert_eq!(final_status, TxStatus { state: TxState::TimedOut, slot: 5, send_attempts: 0 });
+  }
+
+  #[tokio::test]
+  async fn tracker_rejects_duplicate_submission() {
+    let tracker = TxTracker::default();
+    let signature = Signature::new_unique();
+    let status = tracker.track(signature).await.unwrap().unwrap();
+    assert!(tracker.track(signature).await.unwrap().is_none());
+
+    status.send_replace(TxStatus { state: TxState::Confirmed, slot: 3, send_attempts: 1 });
+    assert_eq!(tracker.get(&signature).await.map(|status| status.state), Some(TxState::Confirmed));
+    assert!(tracker.subscribe(&signature).await.is_some());
+    assert!(tracker.get(&Signature::new_unique()).await.is_none());
+  }
+
+  #[tokio::test]
+  async fn full_tracker_evicts_finished_transactions_first() {
+    let tracker = TxTracker::new(2);
+    let (first, second) = (Signature::new_unique(), Signature::new_unique());
+    tracker.track(first).await.unwrap().unwrap();
+    tracker.track(second).await.unwrap().unwrap();
+
+    // 都未进入终态时不能淘汰
+    assert!(tracker.track(Signature::new_unique()).await.is_err());
+
+    tracker.finish(&first).await;
+    let third = Signature::new_unique();
+    assert!(tracker.track(third).await.unwrap().is_some());
+    assert!(tracker.get(&first).await.is_none());
+    assert!(tracker.get(&second).await.is_some());
+    assert!(tracker.get(&third).await.is_some());
+  }
+}
+
+#[cfg(test)]
+mod tx_check {
+  use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::VersionedTransaction,
+  };
+  use tonic::Code;
+
+  use super::fixtures;
+  use crate::service::{
+    core::tx_check::{check_programs, check_system_instructions, verify_signatures},
+    router_service::error::TxCheckError,
+  };
+
+  #[test]
+  fn every_required_signer_must_sign_the_message() {
+    let (payer, recipient) = (Keypair::new(), Keypair::new());
+    // 两个签名者：手续费支付者和转出 SOL 的账户
+    let ix = system_instruction::transfer(&recipient.pubkey(), &payer.pubkey(), 1);
+    let message = fixtures::legacy_message(&payer.pubkey(), &[ix]);
+
+    let signed = VersionedTransaction::try_new(message.clone(), &[&payer, &recipient]).unwrap();
+    assert_eq!(verify_signatures(&signed), Ok(()));
+
+    let mut missing = signed.clone();
+    missing.signatures[1] = Signature::default();
+    assert_eq!(verify_signatures(&missing), Err(TxCheckError::MissingSignature { signer: recipient.pubkey().to_string() }));
+
+    // 签名来自其他钱包
+    let mut forged = signed.clone();
+    forged.signatures[0] = Keypair::new().sign_message(&message.serialize());
+    assert_eq!(verify_signatures(&forged), Err(TxCheckError::InvalidSignature { signer: payer.pubkey().to_string() }));
+
+    let mut truncated = signed;
+    truncated.signatures.pop();
+    assert_eq!(verify_signatures(&truncated), Err(TxCheckError::SignatureCountMismatch { required: 2, actual: 1 }));
+  }
+
+  #[test]
+  fn instructions_must_target_allowlisted_programs() {
+    let payer = Pubkey::new_unique();
+    let allowed =
+      [ComputeBudgetInstruction::set_compute_unit_limit(200_000), system_instruction::transfer(&payer, &Pubkey::new_unique(), 1)];
+    assert_eq!(check_programs(&fixtures::legacy_message(&payer, &allowed)), Ok(()));
+
+    let unknown_program = Pubkey::new_unique();
+    let instructions = [allowed[0].clone(), Instruction { program_id: unknown_program, accounts: vec![], data: vec![] }];
+    let err = check_programs(&fixtures::legacy_message(&payer, &instructions)).unwrap_err();
+    assert_eq!(err, TxCheckError::ProgramNotAllowed { index: 1, program_id: unknown_program.to_string() });
+
+    // 错误信息说明失败的检查项
+    let status = tonic::Status::from(&err);
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(status.message().starts_with("Transaction check `program` failed"));
+  }
+
+  #[test]
+  fn system_instructions_are_limited_to_tips_wrapping_and_nonce() {
+    let (payer, tip_account, nonce_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let wsol_account = Pubkey::new_unique();
+    let check = |instructions: &[Instruction]| {
+      let message = fixtures::legacy_message(&payer, instructions);
+      check_system_instructions(&message, message.static_account_keys(), &[tip_account])
+    };
+
+    let allowed = [
+      system_instruction::advance_nonce_account(&nonce_account, &payer),
+      system_instruction::transfer(&payer, &wsol_account, 1_000),
+      spl_token::instruction::sync_native(&spl_token::id(), &wsol_account).unwrap(),
+      system_instruction::transfer(&payer, &tip_account, 1_000),
+    ];
+    assert_eq!(check(&allowed), Ok(()));
+
+    // 转给其他账户，或转入后没有同步 WSOL 余额
+    let err = check(&[system_instruction::transfer(&payer, &Pubkey::new_unique(), 1_000)]).unwrap_err();
+    assert!(matches!(err, TxCheckError::SystemInstructionNotAllowed { index: 0, .. }));
+    assert_eq!(tonic::Status::from(&err).code(), Code::PermissionDenied);
+    assert!(check(&allowed[1..2]).is_err());
+
+    // 构建交易时不会生成的 system 指令
+    let assign = system_instruction::assign(&payer, &Pubkey::new_unique());
+    assert!(matches!(check(&[assign]), Err(TxCheckError::SystemInstructionNotAllowed { index: 0, .. })));
+    let nonce_later = [allowed[3].clone(), allowed[0].clone()];
+    assert!(matches!(check(&nonce_later), Err(TxCheckError::SystemInstructionNotAllowed { index: 1, .. })));
+  }
+}
+
+#[cfg(test)]
+mod instruction_decoder {
+  use solana_sdk::{
+    hash::Hash,
+    message::{AddressLookupTableAccount, VersionedMessage, v0},
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::VersionedTransaction,
+  };
+
+  use super::fixtures;
+  use crate::{
+    constants::BYREAL_CLMM_PROGRAM_ID,
+    service::{
+      core::{
+        clmm_program::{SwapRouteInfo, route_instruction, swap_v2_instruction},
+        instruction_decoder::{ClmmInstruction, decode_transaction, resolve_account_keys},
+        tx_check::check_swap_instructions,
+      },
+      router_service::error::TxCheckError,
+    },
+  };
+
+  fn hop(input_token_mint: Pubkey, with_bitmap_extension: bool) -> SwapRouteInfo {
+    let hop = fixtures::swap_route_info(input_token_mint, Pubkey::new_unique());
+    SwapRouteInfo { tick_array_bitmap_extension: hop.tick_array_bitmap_extension.filter(|_| with_bitmap_extension), ..hop }
+  }
+
+  /// 池子账户放入查找表，解析时需要从查找表还原
+  fn v0_transaction(
+    payer: &Pubkey,
+    ix: solana_sdk::instruction::Instruction,
+    hops: &[SwapRouteInfo],
+  ) -> (VersionedTransaction, AddressLookupTableAccount) {
+    let addresses = hops.iter().flat_map(|hop| [hop.pool_state, hop.observation_state, hop.tick_arrays[0]]).collect();
+    let alt = AddressLookupTableAccount { key: Pubkey::new_unique(), addresses };
+    let message = v0::Message::try_compile(payer, &[ix], std::slice::from_ref(&alt), Hash::new_unique()).unwrap();
+    assert!(!message.address_table_lookups.is_empty());
+    (VersionedTransaction { signatures: vec![Signature::default()], message: VersionedMessage::V0(message) }, alt)
+  }
+
+  #[test]
+  fn decodes_routing_instruction_with_lookup_table_accounts() {
+    let (payer, input_mint, input_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let first = hop(input_mint, true);
+    let second = hop(first.output_token_mint, false);
+    let hops = vec![first, second];
+    let ix = route_instruction(&payer, &input_mint, &input_account, 1_000, 990, &hops).unwrap();
+    let (vtx, alt) = v0_transaction(&payer, ix, &hops);
+
+    let decoded = decode_transaction(&vtx, &[alt]).unwrap();
+    assert_eq!(decoded.len(), 1);
+    let expected = ClmmInstruction::Routing {
+      payer,
+      input_token_account: input_account,
+      input_token_mint: input_mint,
+      amount_in: 1_000,
+      amount_out_minimum: 990,
+      hops: hops.clone(),
+    };
+    assert_eq!(decoded[0].instruction, expected);
+    assert_eq!(decoded[0].instruction.min_amount_out(), Some(990));
+    assert_eq!(decoded[0].instruction.pools(), vec![hops[0].pool_state, hops[1].pool_state]);
+
+    // 未提供查找表时无法还原账户
+    assert!(decode_transaction(&vtx, &[]).is_err());
+  }
+
+  #[test]
+  fn decodes_swap_v2_instruction() {
+    let (payer, input_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let swap_hop = hop(Pubkey::new_unique(), false);
+    let ix = swap_v2_instruction(&payer, &input_account, &swap_hop, 500, 600, 0, false);
+    let (vtx, alt) = v0_transaction(&payer, ix, std::slice::from_ref(&swap_hop));
+
+    let decoded = decode_transaction(&vtx, &[alt]).unwrap();
+    assert_eq!(decoded[0].index, 0);
+    assert_eq!(
+      decoded[0].instruction,
+      ClmmInstruction::SwapV2 {
+        payer,
+        input_token_account: input_account,
+        hop: swap_hop,
+        amount: 500,
+        other_amount_threshold: 600,
+        sqrt_price_limit_x64: 0,
+        is_base_input: false,
+      }
+    );
+    // 指定输出时阈值是最多输入
+    assert_eq!(decoded[0].instruction.min_amount_out(), None);
+  }
+
+  #[test]
+  fn tx_check_requires_decodable_swaps_with_minimum_output() {
+    let (payer, input_mint, input_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let hops = vec![hop(input_mint, false), hop(Pubkey::new_unique(), false)];
+    let check = |amount_out_minimum| {
+      let ix = route_instruction(&payer, &input_mint, &input_account, 1_000, amount_out_minimum, &hops).unwrap();
+      let (vtx, alt) = v0_transaction(&payer, ix, &hops);
+      check_swap_instructions(&vtx.message, &resolve_account_keys(&vtx.message, &[alt]).unwrap())
+    };
+    assert_eq!(check(990), Ok(()));
+    assert_eq!(check(0), Err(TxCheckError::SwapInstructionNotAllowed { index: 0, reason: "minimum output amount is zero" }));
+
+    // clmm 合约的其他指令不允许出现在 swap 交易中
+    let ix = solana_sdk::instruction::Instruction::new_with_bytes(BYREAL_CLMM_PROGRAM_ID, &[0; 8], vec![]);
+    let message = fixtures::legacy_message(&payer, &[ix]);
+    assert!(matches!(
+      check_swap_instructions(&message, message.static_account_keys()),
+      Err(TxCheckError::SwapInstructionNotAllowed { index: 0, .. })
+    ));
+  }
+}
+
+#[cfg(test)]
+mod sponsor {
+  use litesvm::LiteSVM;
+  use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    signature::{Keypair, Signature, Signer, write_keypair_file},
+    system_instruction,
+  };
+  use spl_associated_token_account::{
+    get_associated_token_address_with_program_id, instruction::create_associated_token_account_idempotent,
+  };
+  use spl_token::state::Account as TokenAccount;
+
+  use super::fixtures;
+  use crate::{
+    constants::WSOL_MINT,
+    service::{
+      core::{
+        build_tx::{MessageVersion, TransactionBuilder},
+        preflight::SolRequirement,
+        sponsor::{KeypairSource, SponsorLimits, compute_unit_limit, read_sponsor_keypair},
+        token_account::wrap_sol_instructions,
+        tx_check::verify_signatures,
+      },
+      router_service::error::SponsorError,
+    },
+  };
+
+  #[test]
+  fn sponsor_partially_signs_as_fee_payer() {
+    let (sponsor, wallet) = (Keypair::new(), Keypair::new());
+    let ixs =
+      [ComputeBudgetInstruction::set_compute_unit_limit(150_000), system_instruction::transfer(&wallet.pubkey(), &sponsor.pubkey(), 1)];
+    let vtx = TransactionBuilder::build_versioned_transaction_sync(
+      Hash::new_unique(),
+      &sponsor.pubkey(),
+      &ixs,
+      &[],
+      &vec![sponsor.insecure_clone()],
+      MessageVersion::V0,
+    )
+    .unwrap();
+
+    // 代付账户是第一个签名者，钱包的签名留空由用户补上
+    assert_eq!(vtx.message.static_account_keys()[0], sponsor.pubkey());
+    assert_ne!(vtx.signatures[0], Signature::default());
+    assert_eq!(vtx.signatures[1], Signature::default());
+    assert!(verify_signatures(&vtx).is_err());
+
+    let mut signed = vtx.clone();
+    signed.signatures[1] = wallet.sign_message(&vtx.message.serialize());
+    assert_eq!(verify_signatures(&signed), Ok(()));
+    assert_eq!(compute_unit_limit(&vtx.message), Some(150_000));
+  }
+
+  #[test]
+  fn sponsored_cost_is_limited() {
+    let limits = SponsorLimits { max_lamports_per_tx: 20_000, max_rent_lamports_per_tx: 2_100_000, max_compute_unit_price: 10_000 };
+    let cost = SolRequirement { signatures: 2, compute_unit_limit: 200_000, cu_price: 10, rent_lamports: 2_039_280, ..Default::default() };
+    assert_eq!(limits.check(&cost), Ok(()));
+
+    // 交易费和租金分别限制
+    let high_fee = SolRequirement { cu_price: 1_000, ..cost };
+    assert_eq!(limits.check(&high_fee), Err(SponsorError::CostExceedsLimit { required: high_fee.fee_lamports(), limit: 20_000 }));
+    assert_eq!(
+      limits.check(&SolRequirement { rent_lamports: 2 * 2_039_280, ..cost }),
+      Err(SponsorError::RentExceedsLimit { required: 2 * 2_039_280, limit: 2_100_000 })
+    );
+    assert_eq!(
+      limits.check(&SolRequirement { cu_price: 20_000, ..cost }),
+      Err(SponsorError::ComputeUnitPriceExceedsLimit { price: 20_000, limit: 10_000 })
+    );
+    // 小费不由代付账户支付
+    assert!(matches!(limits.check(&SolRequirement { tip_lamports: 1, ..cost }), Err(SponsorError::Unsupported(_))));
+  }
+
+  /// 输出为 SOL 的代付交易：代付账户为钱包创建 WSOL 的 ATA，swap 的输出留在 WSOL 账户中
+  /// 代付账户的支出只有交易费和 ATA 的租金
+  #[test]
+  fn sponsor_outflow_of_sol_output_swap() {
+    let mut svm = LiteSVM::new();
+    let (sponsor, wallet) = (Keypair::new(), Keypair::new());
+    svm.airdrop(&sponsor.pubkey(), LAMPORTS_PER_SOL).unwrap();
+    svm.airdrop(&wallet.pubkey(), LAMPORTS_PER_SOL).unwrap();
+    fixtures::set_native_mint(&mut svm);
+
+    // 新建 ATA 由代付账户支付租金；swap 的输出用钱包转入 WSOL 账户代替
+    let output_amount = 100_000_000;
+    let mut ixs = vec![create_associated_token_account_idempotent(&sponsor.pubkey(), &wallet.pubkey(), &WSOL_MINT, &spl_token::id())];
+    let wsol_account = get_associated_token_address_with_program_id(&wallet.pubkey(), &WSOL_MINT, &spl_token::id());
+    ixs.extend(wrap_sol_instructions(&wallet.pubkey(), &wsol_account, output_amount).unwrap());
+    let vtx = TransactionBuilder::build_versioned_transaction_sync(
+      svm.latest_blockhash(),
+      &sponsor.pubkey(),
+      &ixs,
+      &[],
+      &vec![sponsor.insecure_clone()],
+      MessageVersion::V0,
+    )
+    .unwrap();
+    let mut signed = vtx.clone();
+    signed.signatures[1] = wallet.sign_message(&vtx.message.serialize());
+
+    let rent = svm.minimum_balance_for_rent_exemption(TokenAccount::LEN);
+    let cost = SolRequirement { signatures: 2, rent_lamports: rent, ..Default::default() };
+    assert_eq!(SponsorLimits::default().check(&cost), Ok(()));
+
+    let sponsor_before = svm.get_account(&sponsor.pubkey()).unwrap().lamports;
+    svm.send_transaction(signed).unwrap();
+    let sponsor_after = svm.get_account(&sponsor.pubkey()).unwrap().lamports;
+    assert_eq!(sponsor_before - sponsor_after, cost.total());
+    assert_eq!(TokenAccount::unpack(&svm.get_account(&wsol_account).unwrap().data).unwrap().amount, output_amount);
+  }
+
+  #[test]
+  fn keypair_is_read_from_file() {
+    let keypair = Keypair::new();
+    let path = std::env::temp_dir().join(format!("sponsor-{}.json", keypair.pubkey()));
+    write_keypair_file(&keypair, &path).unwrap();
+
+    let source = KeypairSource::File(path.to_string_lossy().to_string());
+    assert_eq!(read_sponsor_keypair(&source).unwrap().pubkey(), keypair.pubkey());
+    std::fs::remove_file(&path).unwrap();
+    assert!(read_sponsor_keypair(&source).is_err());
+  }
+}
+
+/// 封装和解封 SOL 使用调用方指定的 WSOL 账户
+#[cfg(test)]
+mod token_account {
+  use base64::{Engine, prelude::BASE64_STANDARD};
+  use litesvm::LiteSVM;
+  use serde_json::{Value, json};
+  use solana_account_decoder_client_types::{UiAccount, UiAccountData, UiAccountEncoding};
+  use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_request::RpcRequest,
+    rpc_response::{Response, RpcResponseContext},
+  };
+  use solana_rpc_client::mock_sender::MocksMap;
+  use solana_sdk::{
+    account::Account,
+    native_token::LAMPORTS_PER_SOL,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+  };
+  use spl_associated_token_account::get_associated_token_address_with_program_id;
+  use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+  use spl_token_2022::extension::{BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut, transfer_fee::TransferFeeConfig};
+
+  use super::fixtures;
+  use crate::{
+    constants::WSOL_MINT,
+    service::core::{
+      account_puller::AccountPuller,
+      token_account::{prepare_ata_setup, unwrap_sol_instructions, wrap_sol_instructions},
+      types::MintAccountBaseInfo,
+    },
+  };
+
+  /// SPL Token 的 mint，或带 TransferFeeConfig 扩展的 Token-2022 mint
+  fn mint_account(token_2022: bool) -> Account {
+    let base = Mint { decimals: 6, is_initialized: true, ..Default::default() };
+    if !token_2022 {
+      let mut data = vec![0u8; Mint::LEN];
+      base.pack_into_slice(&mut data);
+      return Account { lamports: 1_461_600, data, owner: spl_token::id(), executable: false, rent_epoch: 0 };
+    }
+
+    let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+    let mut data = vec![0u8; len];
+    let mut state = StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data).unwrap();
+    state.init_extension::<TransferFeeConfig>(true).unwrap();
+    state.base = base;
+    state.pack_base();
+    state.init_account_type().unwrap();
+    Account { lamports: 1_461_600, data, owner: spl_token_2022::id(), executable: false, rent_epoch: 0 }
+  }
+
+  /// getMultipleAccounts 的 base64 编码返回值
+  fn multiple_accounts(accounts: &[Option<&Account>]) -> Value {
+    let value: Vec<Option<UiAccount>> = accounts
+      .iter()
+      .map(|account| {
+        account.map(|account| UiAccount {
+          lamports: account.lamports,
+          data: UiAccountData::Binary(BASE64_STANDARD.encode(&account.data), UiAccountEncoding::Base64),
+          owner: account.owner.to_string(),
+          executable: account.executable,
+          rent_epoch: account.rent_epoch,
+          space: Some(account.data.len() as u64),
+        })
+      })
+      .collect();
+    json!(Response { context: RpcResponseContext { slot: 1, api_version: None }, value })
+  }
+
+  #[test]
+  fn wraps_and_unwraps_with_custom_wsol_account() {
+    let mut svm = LiteSVM::new();
+    let wallet = Keypair::new();
+    svm.airdrop(&wallet.pubkey(), LAMPORTS_PER_SOL).unwrap();
+    fixtures::set_native_mint(&mut svm);
+
+    // 钱包持有的非 ATA 的 WSOL 账户
+    let custom_account = Pubkey::new_unique();
+    let rent = svm.minimum_balance_for_rent_exemption(TokenAccount::LEN);
+    let state = TokenAccount {
+      mint: WSOL_MINT,
+      owner: wallet.pubkey(),
+      state: AccountState::Initialized,
+      is_native: COption::Some(rent),
+      ..Default::default()
+    };
+    svm.set_account(custom_account, fixtures::token_account(state, rent)).unwrap();
+
+    let amount = 1_000_000;
+    let ixs = wrap_sol_instructions(&wallet.pubkey(), &custom_account, amount).unwrap();
+    let tx = Transaction::new_signed_with_payer(&ixs, Some(&wallet.pubkey()), &[&wallet], svm.latest_blockhash());
+    svm.send_transaction(tx).unwrap();
+    assert_eq!(TokenAccount::unpack(&svm.get_account(&custom_account).unwrap().data).unwrap().amount, amount);
+    // 钱包的 WSOL ATA 没有被使用
+    let wsol_ata = get_associated_token_address_with_program_id(&wallet.pubkey(), &WSOL_MINT, &spl_token::id());
+    assert!(svm.get_account(&wsol_ata).is_none_or(|account| account.lamports == 0));
+
+    let wallet_before = svm.get_account(&wallet.pubkey()).unwrap().lamports;
+    let ixs = unwrap_sol_instructions(&wallet.pubkey(), &custom_account).unwrap();
+    let tx = Transaction::new_signed_with_payer(&ixs, Some(&wallet.pubkey()), &[&wallet], svm.latest_blockhash());
+    svm.send_transaction(tx).unwrap();
+    assert!(svm.get_account(&custom_account).is_none_or(|account| account.lamports == 0));
+    assert_eq!(svm.get_account(&wallet.pubkey()).unwrap().lamports, wallet_before + rent + amount - 5_000);
+  }
+
+  #[tokio::test]
+  async fn creates_missing_atas_with_rent_for_their_account_size() {
+    let (payer, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let (spl_mint, token_2022_mint, existing_mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let existing_ata = TokenAccount { mint: existing_mint, owner, state: AccountState::Initialized, ..Default::default() };
+    let existing_ata = fixtures::token_account(existing_ata, 2_039_280);
+
+    // Token-2022 的 ATA 带有 mint 扩展要求的 TransferFeeAmount 和 ATA 程序加上的 ImmutableOwner
+    let token_2022_account_len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[
+      ExtensionType::TransferFeeAmount,
+      ExtensionType::ImmutableOwner,
+    ])
+    .unwrap();
+    assert!(token_2022_account_len > TokenAccount::LEN);
+    let rent = Rent::default();
+    let spl_rent = rent.minimum_balance(TokenAccount::LEN);
+    let token_2022_rent = rent.minimum_balance(token_2022_account_len);
+
+    let mut mocks = MocksMap::default();
+    let (spl_mint_account, token_2022_mint_account) = (mint_account(false), mint_account(true));
+    mocks.insert(
+      RpcRequest::GetMultipleAccounts,
+      multiple_accounts(&[Some(&spl_mint_account), Some(&token_2022_mint_account), Some(&spl_mint_account)]),
+    );
+    // existing_mint 的 ATA 已经存在
+    mocks.insert(RpcRequest::GetMultipleAccounts, multiple_accounts(&[None, None, Some(&existing_ata)]));
+    mocks.insert(RpcRequest::GetMinimumBalanceForRentExemption, json!(spl_rent));
+    mocks.insert(RpcRequest::GetMinimumBalanceForRentExemption, json!(token_2022_rent));
+    let rpc_client = RpcClient::new_mock_with_mocks_map("succeeds".to_string(), mocks);
+    let account_puller = AccountPuller::new(&rpc_client);
+
+    let mints: Vec<MintAccountBaseInfo> = account_puller
+      .get_multi_mint_account_with_extension_info(&[spl_mint, token_2022_mint, existing_mint])
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|(_, mint)| mint.unwrap())
+      .collect();
+    assert_eq!(
+      mints.iter().map(|mint| mint.token_account_len).collect::<Vec<_>>(),
+      [TokenAccount::LEN, token_2022_account_len, TokenAccount::LEN]
+    );
+
+    // 重复的 mint 只创建一次
+    let ata_setup = prepare_ata_setup(&account_puller, &payer, &owner, &[mints[0], mints[1], mints[2], mints[0]]).await.unwrap();
+    let expected_atas = [(spl_mint, spl_token::id()), (token_2022_mint, spl_token_2022::id())]
+      .map(|(mint, program_id)| get_associated_token_address_with_program_id(&owner, &mint, &program_id));
+    assert_eq!(ata_setup.created_accounts, expected_atas);
+    assert_eq!(ata_setup.instructions.len(), 2);
+    for (ix, ata) in ata_setup.instructions.iter().zip(expected_atas) {
+      assert_eq!(ix.program_id, spl_associated_token_account::id());
+      assert_eq!(ix.accounts[0].pubkey, payer);
+      assert_eq!(ix.accounts[1].pubkey, ata);
+    }
+    assert_eq!(ata_setup.rent_lamports, spl_rent + token_2022_rent);
+  }
+}
@@ -0,0 +1,33 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use solana_system_interface::instruction as system_instruction;
+
+/// 区块引擎接受的最小小费
+pub const MIN_TIP_LAMPORTS: u64 = 1_000;
+
+/// 从配置的小费账户中随机选择一个，分散对同一账户的写锁竞争
+pub fn pick_tip_account(tip_accounts: &[String]) -> Result<Pubkey> {
+  if tip_accounts.is_empty() {
+    return Err(anyhow::anyhow!("No tip accounts configured"));
+  }
+  let tip_account = &tip_accounts[rand::random::<u32>() as usize % tip_accounts.len()];
+  Pubkey::from_str(tip_account).map_err(|e| anyhow::anyhow!("Invalid tip account in config: {}, {}", tip_account, e))
+}
+
+/// 解析配置的小费账户
+pub fn parse_tip_accounts(tip_accounts: &[String]) -> Result<Vec<Pubkey>> {
+  tip_accounts
+    .iter()
+    .map(|tip_account| Pubkey::from_str(tip_account).map_err(|e| anyhow::anyhow!("Invalid tip account in config: {}, {}", tip_account, e)))
+    .collect()
+}
+
+/// 由 payer 向小费账户转账的指令
+pub fn tip_instruction(payer: &Pubkey, tip_account: &Pubkey, tip_lamports: u64) -> Result<Instruction> {
+  if tip_lamports < MIN_TIP_LAMPORTS {
+    return Err(anyhow::anyhow!("Tip {} lamports is less than the minimum {}", tip_lamports, MIN_TIP_LAMPORTS));
+  }
+  Ok(system_instruction::transfer(payer, tip_account, tip_lamports))
+}
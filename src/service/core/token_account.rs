@@ -0,0 +1,69 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use solana_system_interface::instruction as system_instruction;
+use spl_associated_token_account::{get_associated_token_address_with_program_id, instruction::create_associated_token_account_idempotent};
+
+use super::{account_puller::AccountPuller, types::MintAccountBaseInfo};
+
+/// 交易中需要新建的 token 账户（ATA）
+#[derive(Debug, Default, Clone)]
+pub struct AtaSetup {
+  /// create_associated_token_account_idempotent 指令，放在 swap 指令之前
+  pub instructions: Vec<Instruction>,
+  /// 新建的 ATA 地址
+  pub created_accounts: Vec<Pubkey>,
+  /// 新建 ATA 需要的租金
+  pub rent_lamports: u64,
+}
+
+/// 检查 owner 持有 mints 的 ATA 是否已经存在，为不存在的 ATA 生成幂等的创建指令，由 payer 支付租金
+pub async fn prepare_ata_setup(
+  account_puller: &AccountPuller<'_>,
+  payer: &Pubkey,
+  owner: &Pubkey,
+  mints: &[MintAccountBaseInfo],
+) -> Result<AtaSetup> {
+  // 同一个 mint 只需要创建一次
+  let mut seen = HashSet::new();
+  let mints: Vec<&MintAccountBaseInfo> = mints.iter().filter(|mint| seen.insert(mint.mint)).collect();
+
+  let ata_keys: Vec<Pubkey> =
+    mints.iter().map(|mint| get_associated_token_address_with_program_id(owner, &mint.mint, &mint.program_id)).collect();
+  let ata_accounts = account_puller.get_multi_accounts(&ata_keys).await?;
+
+  let mut ata_setup = AtaSetup::default();
+  // 相同大小的账户租金相同，避免重复请求
+  let mut rent_by_len: HashMap<usize, u64> = HashMap::new();
+  for (mint, (ata_key, ata_account)) in mints.into_iter().zip(ata_accounts) {
+    if ata_account.is_some() {
+      continue;
+    }
+
+    let rent = match rent_by_len.get(&mint.token_account_len) {
+      Some(rent) => *rent,
+      None => {
+        let rent = account_puller.rpc_client.get_minimum_balance_for_rent_exemption(mint.token_account_len).await?;
+        rent_by_len.insert(mint.token_account_len, rent);
+        rent
+      }
+    };
+
+    ata_setup.instructions.push(create_associated_token_account_idempotent(payer, owner, &mint.mint, &mint.program_id));
+    ata_setup.created_accounts.push(ata_key);
+    ata_setup.rent_lamports += rent;
+  }
+
+  Ok(ata_setup)
+}
+
+/// 将 amount 个 SOL 从 owner 转入 WSOL 账户并同步余额，WSOL 账户需要已经存在或在之前的指令中创建
+pub fn wrap_sol_instructions(owner: &Pubkey, wsol_account: &Pubkey, amount: u64) -> Result<Vec<Instruction>> {
+  Ok(vec![system_instruction::transfer(owner, wsol_account, amount), spl_token::instruction::sync_native(&spl_token::id(), wsol_account)?])
+}
+
+/// 关闭 owner 持有的 WSOL 账户，将其中的 SOL（包括租金）返还给 owner
+pub fn unwrap_sol_instructions(owner: &Pubkey, wsol_account: &Pubkey) -> Result<Vec<Instruction>> {
+  Ok(vec![spl_token::instruction::close_account(&spl_token::id(), wsol_account, owner, owner, &[])?])
+}
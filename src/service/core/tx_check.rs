@@ -0,0 +1,161 @@
+use anyhow::Result;
+use solana_sdk::{
+  compute_budget,
+  message::{AddressLookupTableAccount, VersionedMessage},
+  pubkey::Pubkey,
+  signature::Signature,
+  system_program,
+  transaction::VersionedTransaction,
+};
+use solana_system_interface::instruction::SystemInstruction;
+use spl_token::instruction::TokenInstruction;
+
+use super::{instruction_decoder, nonce, tx_sender::TransactionRpc};
+use crate::{
+  constants::{BYREAL_CLMM_PROGRAM_ID, BYREAL_CLMM_ROUTING_PROGRAM_ID},
+  service::router_service::error::TxCheckError,
+};
+
+/// 交易指令允许调用的程序
+/// system program 只能用于封装 SOL、小费转账和推进 durable nonce，见 check_system_instructions
+pub const ALLOWED_PROGRAM_IDS: [Pubkey; 7] = [
+  compute_budget::ID,
+  system_program::ID,
+  spl_associated_token_account::ID,
+  spl_token::ID,
+  spl_token_2022::ID,
+  BYREAL_CLMM_PROGRAM_ID,
+  BYREAL_CLMM_ROUTING_PROGRAM_ID,
+];
+
+/// 检查签名数量与消息要求的一致，且每个签名都是对应签名者对消息的有效签名
+pub fn verify_signatures(vtx: &VersionedTransaction) -> Result<(), TxCheckError> {
+  let required = usize::from(vtx.message.header().num_required_signatures);
+  if vtx.signatures.len() != required {
+    return Err(TxCheckError::SignatureCountMismatch { required, actual: vtx.signatures.len() });
+  }
+  let message_data = vtx.message.serialize();
+  // 第一个签名者是手续费支付者
+  for (signature, signer) in vtx.signatures.iter().zip(vtx.message.static_account_keys()) {
+    if *signature == Signature::default() {
+      return Err(TxCheckError::MissingSignature { signer: signer.to_string() });
+    }
+    if !signature.verify(signer.as_ref(), &message_data) {
+      return Err(TxCheckError::InvalidSignature { signer: signer.to_string() });
+    }
+  }
+  Ok(())
+}
+
+/// 检查所有指令只调用允许的程序，程序地址不能来自地址查找表，只需要检查静态账户
+pub fn check_programs(message: &VersionedMessage) -> Result<(), TxCheckError> {
+  let account_keys = message.static_account_keys();
+  for (index, ix) in message.instructions().iter().enumerate() {
+    let program_id = account_keys
+      .get(usize::from(ix.program_id_index))
+      .ok_or_else(|| TxCheckError::InvalidMessage(format!("Instruction {} has invalid program index", index)))?;
+    if !ALLOWED_PROGRAM_IDS.contains(program_id) {
+      return Err(TxCheckError::ProgramNotAllowed { index, program_id: program_id.to_string() });
+    }
+  }
+  Ok(())
+}
+
+/// 检查 system program 的指令只有构建交易时生成的几种，并检查其账户：
+/// - 第一条指令推进 durable nonce，nonce authority 需要签名
+/// - 向配置的小费账户转账
+/// - 向 WSOL 账户转账封装 SOL，下一条指令同步该账户的余额
+///
+/// account_keys 为包含地址查找表账户的完整账户列表
+pub fn check_system_instructions(message: &VersionedMessage, account_keys: &[Pubkey], tip_accounts: &[Pubkey]) -> Result<(), TxCheckError> {
+  let instructions = message.instructions();
+  let key = |index: &u8| account_keys.get(usize::from(*index));
+  for (index, ix) in instructions.iter().enumerate() {
+    if key(&ix.program_id_index) != Some(&system_program::ID) {
+      continue;
+    }
+    let not_allowed = |reason| TxCheckError::SystemInstructionNotAllowed { index, reason };
+    match bincode::deserialize::<SystemInstruction>(&ix.data) {
+      Ok(SystemInstruction::AdvanceNonceAccount) => {
+        if index != 0 {
+          return Err(not_allowed("advance nonce must be the first instruction"));
+        }
+        // 账户依次为 nonce 账户、RecentBlockhashes sysvar、nonce authority
+        if !ix.accounts.get(2).is_some_and(|authority| message.is_signer(usize::from(*authority))) {
+          return Err(not_allowed("nonce authority must sign"));
+        }
+      }
+      Ok(SystemInstruction::Transfer { .. }) => {
+        let destination = ix.accounts.get(1).and_then(key).ok_or(not_allowed("transfer has no destination"))?;
+        if tip_accounts.contains(destination) {
+          continue;
+        }
+        let synced = instructions.get(index + 1).is_some_and(|next| {
+          key(&next.program_id_index) == Some(&spl_token::ID)
+            && matches!(TokenInstruction::unpack(&next.data), Ok(TokenInstruction::SyncNative))
+            && next.accounts.first().and_then(key) == Some(destination)
+        });
+        if !synced {
+          return Err(not_allowed("transfer destination is neither a tip account nor a synced WSOL account"));
+        }
+      }
+      _ => return Err(not_allowed("only transfer and advance nonce are allowed")),
+    }
+  }
+  Ok(())
+}
+
+/// 用 instruction_decoder 解析 clmm 和路由合约的指令，检查只有 swap_v2 和 routing 指令，且指定输入的 swap 设置了最少输出
+/// account_keys 为包含地址查找表账户的完整账户列表
+pub fn check_swap_instructions(message: &VersionedMessage, account_keys: &[Pubkey]) -> Result<(), TxCheckError> {
+  for (index, ix) in message.instructions().iter().enumerate() {
+    let program_id = account_keys.get(usize::from(ix.program_id_index));
+    if program_id != Some(&BYREAL_CLMM_PROGRAM_ID) && program_id != Some(&BYREAL_CLMM_ROUTING_PROGRAM_ID) {
+      continue;
+    }
+    let not_allowed = |reason| TxCheckError::SwapInstructionNotAllowed { index, reason };
+    let instruction = instruction_decoder::decode_instruction(account_keys, ix)
+      .map_err(|e| TxCheckError::InvalidMessage(format!("Failed to decode instruction {}: {}", index, e)))?
+      .ok_or(not_allowed("only swap_v2 and routing are allowed"))?;
+    if instruction.min_amount_out() == Some(0) {
+      return Err(not_allowed("minimum output amount is zero"));
+    }
+  }
+  Ok(())
+}
+
+/// 检查交易仍可上链：durable nonce 交易的 nonce 未被推进，其他交易的 blockhash 未过期
+pub async fn check_freshness<R: TransactionRpc>(rpc: &R, message: &VersionedMessage) -> Result<()> {
+  let blockhash = message.recent_blockhash();
+  if let Some(nonce_account) = nonce::durable_nonce_account(message) {
+    if rpc.get_current_nonce(&nonce_account).await? != *blockhash {
+      return Err(TxCheckError::NonceAdvanced { nonce_account: nonce_account.to_string() }.into());
+    }
+  } else if !rpc.check_blockhash_valid(blockhash).await? {
+    return Err(TxCheckError::BlockhashExpired { blockhash: blockhash.to_string() }.into());
+  }
+  Ok(())
+}
+
+/// 依次检查签名交易未被篡改、消息合法、签名有效、只调用允许的程序、system 指令和 swap 指令、仍可上链
+/// lookup_tables 为交易使用的地址查找表，tip_accounts 为配置的小费账户
+/// 失败时返回 TxCheckError 说明失败的检查项，rpc 出错时返回其他错误
+pub async fn check_signed_transaction<R: TransactionRpc>(
+  rpc: &R,
+  pre_tx: &VersionedTransaction,
+  signed_tx: &VersionedTransaction,
+  lookup_tables: &[AddressLookupTableAccount],
+  tip_accounts: &[Pubkey],
+) -> Result<()> {
+  if pre_tx.message != signed_tx.message {
+    return Err(TxCheckError::MessageMismatch.into());
+  }
+  signed_tx.message.sanitize().map_err(|e| TxCheckError::InvalidMessage(e.to_string()))?;
+  verify_signatures(signed_tx)?;
+  check_programs(&signed_tx.message)?;
+  let account_keys = instruction_decoder::resolve_account_keys(&signed_tx.message, lookup_tables)
+    .map_err(|e| TxCheckError::InvalidMessage(e.to_string()))?;
+  check_system_instructions(&signed_tx.message, &account_keys, tip_accounts)?;
+  check_swap_instructions(&signed_tx.message, &account_keys)?;
+  check_freshness(rpc, &signed_tx.message).await
+}
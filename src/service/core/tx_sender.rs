@@ -0,0 +1,304 @@
+use std::{
+  collections::HashMap,
+  time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_sdk::{
+  commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction,
+};
+use tokio::sync::{RwLock, watch};
+
+use super::nonce;
+
+/// 默认的重发间隔
+pub const DEFAULT_RESEND_INTERVAL_MS: u64 = 2_000;
+/// 默认的最长跟踪时间，blockhash 约 60~90 秒过期
+pub const DEFAULT_TRACK_TIMEOUT_SECS: u64 = 120;
+/// 进入终态的交易状态保留的时间
+const FINISHED_STATUS_RETENTION: Duration = Duration::from_secs(600);
+/// 未进入终态的交易最长保留的时间，跟踪任务异常退出时由此清理
+const UNFINISHED_STATUS_RETENTION: Duration = Duration::from_secs(3600);
+/// 默认最多同时保留的交易数量
+pub const DEFAULT_MAX_TRACKED_TXS: usize = 10_000;
+
+/// 交易的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxState {
+  /// 已发送，尚未上链
+  Pending,
+  Processed,
+  Confirmed,
+  Finalized,
+  /// 已上链但执行失败
+  Failed(String),
+  /// blockhash 已过期或 nonce 已被推进，交易不会再上链
+  Expired,
+  /// 超过跟踪时间仍未最终确认，交易仍有可能上链或被确认（如 durable nonce 交易）
+  TimedOut,
+}
+
+impl TxState {
+  /// 是否为终态，进入终态后不再发送和查询
+  pub fn is_finished(&self) -> bool {
+    matches!(self, TxState::Finalized | TxState::Failed(_) | TxState::Expired | TxState::TimedOut)
+  }
+}
+
+/// 交易的状态和发送情况
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxStatus {
+  pub state: TxState,
+  /// 交易所在的 slot，未上链时为 0
+  pub slot: u64,
+  /// 发送的次数
+  pub send_attempts: u32,
+}
+
+impl Default for TxStatus {
+  fn default() -> Self {
+    Self { state: TxState::Pending, slot: 0, send_attempts: 0 }
+  }
+}
+
+/// 发送和跟踪交易使用的 rpc 接口，测试时替换为本地的实现
+#[tonic::async_trait]
+pub trait TransactionRpc: Send + Sync {
+  /// 发送交易，不做预检查，也不由 rpc 节点重发
+  async fn send_transaction_once(&self, vtx: &VersionedTransaction) -> Result<()>;
+
+  /// 查询交易的状态和所在 slot，交易未上链时返回 None
+  async fn get_tx_state(&self, signature: &Signature) -> Result<Option<(TxState, u64)>>;
+
+  async fn check_blockhash_valid(&self, blockhash: &Hash) -> Result<bool>;
+
+  /// nonce 账户当前保存的 nonce 值
+  async fn get_current_nonce(&self, nonce_account: &Pubkey) -> Result<Hash>;
+}
+
+#[tonic::async_trait]
+impl TransactionRpc for RpcClient {
+  async fn send_transaction_once(&self, vtx: &VersionedTransaction) -> Result<()> {
+    let config = RpcSendTransactionConfig { skip_preflight: true, max_retries: Some(0), ..Default::default() };
+    self.send_transaction_with_config(vtx, config).await?;
+    Ok(())
+  }
+
+  async fn get_tx_state(&self, signature: &Signature) -> Result<Option<(TxState, u64)>> {
+    let status = self.get_signature_statuses(&[*signature]).await?.value.into_iter().next().flatten();
+    Ok(status.map(|status| {
+      let state = if let Some(err) = &status.err {
+        TxState::Failed(err.to_string())
+      } else if status.satisfies_commitment(CommitmentConfig::finalized()) {
+        TxState::Finalized
+      } else if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+        TxState::Confirmed
+      } else {
+        TxState::Processed
+      };
+      (state, status.slot)
+    }))
+  }
+
+  async fn check_blockhash_valid(&self, blockhash: &Hash) -> Result<bool> {
+    Ok(self.is_blockhash_valid(blockhash, CommitmentConfig::processed()).await?)
+  }
+
+  async fn get_current_nonce(&self, nonce_account: &Pubkey) -> Result<Hash> {
+    let (_, nonce) = nonce::fetch_nonce_account(self, nonce_account).await?;
+    Ok(nonce)
+  }
+}
+
+/// 发送和跟踪的参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendOptions {
+  /// 交易未上链时的重发间隔，同时也是查询状态的间隔
+  pub resend_interval: Duration,
+  /// 最长跟踪时间，超过后未进入终态的交易（包括已 processed、confirmed 的交易）记为超时
+  pub track_timeout: Duration,
+}
+
+impl Default for SendOptions {
+  fn default() -> Self {
+    Self {
+      resend_interval: Duration::from_millis(DEFAULT_RESEND_INTERVAL_MS),
+      track_timeout: Duration::from_secs(DEFAULT_TRACK_TIMEOUT_SECS),
+    }
+  }
+}
+
+#[derive(Debug)]
+struct TrackedTx {
+  status: watch::Sender<TxStatus>,
+  /// 开始跟踪的时间
+  tracked_at: Instant,
+  /// 进入终态的时间
+  finished_at: Option<Instant>,
+}
+
+impl TrackedTx {
+  /// 保留时间是否已过
+  fn is_stale(&self, now: Instant) -> bool {
+    match self.finished_at {
+      Some(finished_at) => now.duration_since(finished_at) >= FINISHED_STATUS_RETENTION,
+      None => now.duration_since(self.tracked_at) >= UNFINISHED_STATUS_RETENTION,
+    }
+  }
+}
+
+/// 已提交交易的状态，按签名查询和订阅
+#[derive(Debug)]
+pub struct TxTracker {
+  txs: RwLock<HashMap<Signature, TrackedTx>>,
+  /// 最多同时保留的交易数量
+  max_txs: usize,
+}
+
+impl Default for TxTracker {
+  fn default() -> Self {
+    Self::new(DEFAULT_MAX_TRACKED_TXS)
+  }
+}
+
+impl TxTracker {
+  pub fn new(max_txs: usize) -> Self {
+    Self { txs: RwLock::new(HashMap::new()), max_txs }
+  }
+
+  /// 开始跟踪交易，交易已经在跟踪中时返回 None
+  /// 数量达到上限时先淘汰最早进入终态的交易，仍然没有空间时返回错误
+  pub async fn track(&self, signature: Signature) -> Result<Option<watch::Sender<TxStatus>>> {
+    let now = Instant::now();
+    let mut txs = self.txs.write().await;
+    // 写入时顺便清理保留时间已过的交易
+    txs.retain(|_, tx| !tx.is_stale(now));
+    if txs.contains_key(&signature) {
+      return Ok(None);
+    }
+    if txs.len() >= self.max_txs {
+      let oldest_finished = txs.iter().filter_map(|(signature, tx)| tx.finished_at.map(|at| (*signature, at))).min_by_key(|(_, at)| *at);
+      match oldest_finished {
+        Some((signature, _)) => {
+          txs.remove(&signature);
+        }
+        None => anyhow::bail!("Too many transactions are being tracked: {}", txs.len()),
+      }
+    }
+    let (status, _) = watch::channel(TxStatus::default());
+    txs.insert(signature, TrackedTx { status: status.clone(), tracked_at: now, finished_at: None });
+    Ok(Some(status))
+  }
+
+  /// 记录交易进入终态的时间
+  pub async fn finish(&self, signature: &Signature) {
+    if let Some(tx) = self.txs.write().await.get_mut(signature) {
+      tx.finished_at = Some(Instant::now());
+    }
+  }
+
+  pub async fn get(&self, signature: &Signature) -> Option<TxStatus> {
+    self.txs.read().await.get(signature).map(|tx| tx.status.borrow().clone())
+  }
+
+  /// 订阅交易状态的变化
+  pub async fn subscribe(&self, signature: &Signature) -> Option<watch::Receiver<TxStatus>> {
+    self.txs.read().await.get(signature).map(|tx| tx.status.subscribe())
+  }
+}
+
+/// 按顺序查询，返回第一个成功的结果
+async fn query_status<R: TransactionRpc>(rpcs: &[R], signature: &Signature) -> Result<Option<(TxState, u64)>> {
+  let mut last_err = anyhow::anyhow!("No rpc available");
+  for rpc in rpcs {
+    match rpc.get_tx_state(signature).await {
+      Ok(status) => return Ok(status),
+      Err(e) => last_err = e,
+    }
+  }
+  Err(last_err)
+}
+
+/// 交易是否已经不可能上链：durable nonce 交易看 nonce 是否被推进，其他交易看 blockhash 是否过期
+async fn is_expired<R: TransactionRpc>(rpcs: &[R], vtx: &VersionedTransaction) -> Result<bool> {
+  let blockhash = vtx.message.recent_blockhash();
+  let nonce_account = nonce::durable_nonce_account(&vtx.message);
+  let mut last_err = anyhow::anyhow!("No rpc available");
+  for rpc in rpcs {
+    let expired = match &nonce_account {
+      Some(nonce_account) => rpc.get_current_nonce(nonce_account).await.map(|nonce| nonce != *blockhash),
+      None => rpc.check_blockhash_valid(blockhash).await.map(|valid| !valid),
+    };
+    match expired {
+      Ok(expired) => return Ok(expired),
+      Err(e) => last_err = e,
+    }
+  }
+  Err(last_err)
+}
+
+/// 通过所有 rpc 节点发送交易并跟踪状态，直到交易最终确认、失败、过期或超过跟踪时间
+/// 交易未上链时按间隔重发，rpc 出错时只记录日志，下一轮重试
+pub async fn send_and_track<R: TransactionRpc>(
+  rpcs: &[R],
+  vtx: &VersionedTransaction,
+  status: &watch::Sender<TxStatus>,
+  options: SendOptions,
+) -> TxStatus {
+  let signature = vtx.signatures[0];
+  let started_at = Instant::now();
+  let mut send_attempts = 0;
+  loop {
+    let timed_out = started_at.elapsed() >= options.track_timeout;
+    let chain_status = match query_status(rpcs, &signature).await {
+      Ok(chain_status) => chain_status,
+      Err(e) => {
+        log::warn!("get status of {} failed: {}", signature, e);
+        None
+      }
+    };
+
+    let next = match chain_status {
+      Some((state, slot)) => TxStatus { state, slot, send_attempts },
+      // 未上链：过期前先再查询一次状态，避免交易恰好在两次查询之间上链
+      None => match is_expired(rpcs, vtx).await {
+        Ok(true) => match query_status(rpcs, &signature).await {
+          Ok(Some((state, slot))) => TxStatus { state, slot, send_attempts },
+          _ => TxStatus { state: TxState::Expired, slot: 0, send_attempts },
+        },
+        _ if timed_out => TxStatus { state: TxState::TimedOut, slot: 0, send_attempts },
+        expired => {
+          if let Err(e) = expired {
+            log::warn!("check expiry of {} failed: {}", signature, e);
+          }
+          let mut sent = false;
+          for rpc in rpcs {
+            match rpc.send_transaction_once(vtx).await {
+              Ok(()) => sent = true,
+              Err(e) => log::warn!("send {} failed: {}", signature, e),
+            }
+          }
+          send_attempts += u32::from(sent);
+          TxStatus { state: TxState::Pending, slot: 0, send_attempts }
+        }
+      },
+    };
+    // 超时后不再等待 processed、confirmed 的交易最终确认
+    let next = match next.state {
+      TxState::Processed | TxState::Confirmed if timed_out => TxStatus { state: TxState::TimedOut, ..next },
+      _ => next,
+    };
+
+    // 状态没有变化时不通知订阅方
+    status.send_if_modified(|current| {
+      let modified = *current != next;
+      *current = next.clone();
+      modified
+    });
+    if next.state.is_finished() {
+      return next;
+    }
+    tokio::time::sleep(options.resend_interval).await;
+  }
+}
@@ -7,6 +7,8 @@ pub struct MintAccountBaseInfo {
   pub mint: Pubkey,       // Token mint address
   pub program_id: Pubkey, // Program ID of the token
   pub decimal: u8,        // Decimal precision of the token
+  /// 持有该代币的 token 账户（ATA）的大小，Token-2022 的账户大小取决于 mint 的扩展
+  pub token_account_len: usize,
 
   pub transfer_fee_config: Option<TransferFeeConfig>, // Optional transfer fee configuration
 }
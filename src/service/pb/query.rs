@@ -74,6 +74,111 @@ pub struct PointData {
     #[prost(int64, tag = "3")]
     pub tick: i64,
 }
+/// SubmitTransactionRequest 包含提交交易所需的数据，与 CheckTxRequest 相同的检查通过后才会发送
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubmitTransactionRequest {
+    /// base64 of unsigned transactions
+    #[prost(string, repeated, tag = "1")]
+    pub pre_data: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// base64 of signed transactions
+    #[prost(string, repeated, tag = "2")]
+    pub data: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// SubmitTransactionResponse 返回提交的交易签名
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubmitTransactionResponse {
+    #[prost(message, optional, tag = "1")]
+    pub result: ::core::option::Option<super::base::CommonResult>,
+    /// 交易签名，用于查询交易状态
+    #[prost(string, repeated, tag = "2")]
+    pub signatures: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// GetTransactionStatusRequest 包含查询交易状态所需的数据
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetTransactionStatusRequest {
+    /// 交易签名
+    #[prost(string, tag = "1")]
+    pub signature: ::prost::alloc::string::String,
+}
+/// GetTransactionStatusResponse 返回交易的状态
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetTransactionStatusResponse {
+    #[prost(message, optional, tag = "1")]
+    pub result: ::core::option::Option<super::base::CommonResult>,
+    #[prost(string, tag = "2")]
+    pub signature: ::prost::alloc::string::String,
+    #[prost(enumeration = "TransactionState", tag = "3")]
+    pub state: i32,
+    /// 交易所在的 slot，未上链时为 0
+    #[prost(uint64, tag = "4")]
+    pub slot: u64,
+    /// 执行失败的原因
+    #[prost(string, tag = "5")]
+    pub error: ::prost::alloc::string::String,
+    /// 发送的次数，未通过本服务提交时为 0
+    #[prost(uint32, tag = "6")]
+    pub send_attempts: u32,
+}
+/// TransactionState 定义了交易的状态
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TransactionState {
+    /// 未通过本服务提交，链上也未找到
+    Unknown = 0,
+    /// 已发送，尚未上链
+    Pending = 1,
+    Processed = 2,
+    Confirmed = 3,
+    Finalized = 4,
+    /// 已上链但执行失败
+    Failed = 5,
+    /// blockhash 已过期或 nonce 已被推进，交易不会再上链
+    Expired = 6,
+    /// 超过跟踪时间仍未最终确认，交易仍有可能上链或被确认
+    TimedOut = 7,
+}
+impl TransactionState {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unknown => "UNKNOWN",
+            Self::Pending => "PENDING",
+            Self::Processed => "PROCESSED",
+            Self::Confirmed => "CONFIRMED",
+            Self::Finalized => "FINALIZED",
+            Self::Failed => "FAILED",
+            Self::Expired => "EXPIRED",
+            Self::TimedOut => "TIMED_OUT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "UNKNOWN" => Some(Self::Unknown),
+            "PENDING" => Some(Self::Pending),
+            "PROCESSED" => Some(Self::Processed),
+            "CONFIRMED" => Some(Self::Confirmed),
+            "FINALIZED" => Some(Self::Finalized),
+            "FAILED" => Some(Self::Failed),
+            "EXPIRED" => Some(Self::Expired),
+            "TIMED_OUT" => Some(Self::TimedOut),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod query_service_client {
     #![allow(
@@ -241,6 +346,83 @@ pub mod query_service_client {
                 .insert(GrpcMethod::new("query.QueryService", "ListLinePosition"));
             self.inner.unary(req, path, codec).await
         }
+        /// 提交交易：检查交易未被篡改后通过 rpc 节点发送，并跟踪确认状态
+        pub async fn submit_transaction(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SubmitTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SubmitTransactionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/query.QueryService/SubmitTransaction",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("query.QueryService", "SubmitTransaction"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// 查询交易状态
+        pub async fn get_transaction_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetTransactionStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetTransactionStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/query.QueryService/GetTransactionStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("query.QueryService", "GetTransactionStatus"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// 订阅交易状态：状态变化时推送，交易进入终态后结束
+        pub async fn watch_transaction_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetTransactionStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<
+                tonic::codec::Streaming<super::GetTransactionStatusResponse>,
+            >,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/query.QueryService/WatchTransactionStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("query.QueryService", "WatchTransactionStatus"));
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -277,6 +459,39 @@ pub mod query_service_server {
             tonic::Response<super::ListLinePositionResponse>,
             tonic::Status,
         >;
+        /// 提交交易：检查交易未被篡改后通过 rpc 节点发送，并跟踪确认状态
+        async fn submit_transaction(
+            &self,
+            request: tonic::Request<super::SubmitTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SubmitTransactionResponse>,
+            tonic::Status,
+        >;
+        /// 查询交易状态
+        async fn get_transaction_status(
+            &self,
+            request: tonic::Request<super::GetTransactionStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetTransactionStatusResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the WatchTransactionStatus method.
+        type WatchTransactionStatusStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<
+                    super::GetTransactionStatusResponse,
+                    tonic::Status,
+                >,
+            >
+            + std::marker::Send
+            + 'static;
+        /// 订阅交易状态：状态变化时推送，交易进入终态后结束
+        async fn watch_transaction_status(
+            &self,
+            request: tonic::Request<super::GetTransactionStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::WatchTransactionStatusStream>,
+            tonic::Status,
+        >;
     }
     /// SwapService 定义了与交换相关的服务
     #[derive(Debug)]
@@ -492,6 +707,146 @@ pub mod query_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/query.QueryService/SubmitTransaction" => {
+                    #[allow(non_camel_case_types)]
+                    struct SubmitTransactionSvc<T: QueryService>(pub Arc<T>);
+                    impl<
+                        T: QueryService,
+                    > tonic::server::UnaryService<super::SubmitTransactionRequest>
+                    for SubmitTransactionSvc<T> {
+                        type Response = super::SubmitTransactionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubmitTransactionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as QueryService>::submit_transaction(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SubmitTransactionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/query.QueryService/GetTransactionStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetTransactionStatusSvc<T: QueryService>(pub Arc<T>);
+                    impl<
+                        T: QueryService,
+                    > tonic::server::UnaryService<super::GetTransactionStatusRequest>
+                    for GetTransactionStatusSvc<T> {
+                        type Response = super::GetTransactionStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetTransactionStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as QueryService>::get_transaction_status(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetTransactionStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/query.QueryService/WatchTransactionStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchTransactionStatusSvc<T: QueryService>(pub Arc<T>);
+                    impl<
+                        T: QueryService,
+                    > tonic::server::ServerStreamingService<
+                        super::GetTransactionStatusRequest,
+                    > for WatchTransactionStatusSvc<T> {
+                        type Response = super::GetTransactionStatusResponse;
+                        type ResponseStream = T::WatchTransactionStatusStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetTransactionStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as QueryService>::watch_transaction_status(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = WatchTransactionStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
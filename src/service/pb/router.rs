@@ -22,6 +22,21 @@ pub struct QuotePriceRequest {
     /// 滑点，以 0.01% 为基点
     #[prost(int64, tag = "6")]
     pub slippage_bps: i64,
+    /// 可选 只使用单池的直接路由
+    #[prost(bool, tag = "7")]
+    pub only_direct_routes: bool,
+    /// 可选 路由最多经过的池子数量, 0 表示不限制, 1 表示只使用直接路由
+    #[prost(int32, tag = "8")]
+    pub max_hops: i32,
+    /// 可选 不参与路由的池子地址
+    #[prost(string, repeated, tag = "9")]
+    pub exclude_pools: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// 可选 不能作为中转代币的 mint 地址
+    #[prost(string, repeated, tag = "10")]
+    pub exclude_mints: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// 可选 允许使用的池子类型(如 "clmm"), 为空表示不限制
+    #[prost(string, repeated, tag = "11")]
+    pub allowed_venues: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// ApiSwapV1OutSuccess 表示成功的交换响应
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -69,6 +84,30 @@ pub struct SwapV1Out {
     /// 数组类型使用 repeated
     #[prost(message, repeated, tag = "9")]
     pub route_plan: ::prost::alloc::vec::Vec<swap_v1_out::RoutePlan>,
+    /// 询价时的 slot
+    #[prost(uint64, tag = "10")]
+    pub context_slot: u64,
+    /// 从该 slot 开始询价结果失效，取 TTL 和 transfer-fee 费率切换中较早的那个
+    #[prost(uint64, tag = "11")]
+    pub expire_slot: u64,
+    /// 预估的失效时间，毫秒时间戳
+    #[prost(uint64, tag = "12")]
+    pub expire_time: u64,
+    /// 服务端保存的询价 id，构建交易时传入
+    #[prost(string, tag = "13")]
+    pub quote_id: ::prost::alloc::string::String,
+    /// 按 cu 模型估算的交易消耗的计算单元
+    #[prost(uint64, tag = "14")]
+    pub estimated_compute_units: u64,
+    /// 平台费数量，已计入 input_amount/output_amount 和 other_amount_threshold
+    #[prost(string, tag = "15")]
+    pub platform_fee_amount: ::prost::alloc::string::String,
+    /// 平台费，基点
+    #[prost(uint32, tag = "16")]
+    pub platform_fee_bps: u32,
+    /// 收取平台费的代币，为输入或输出代币
+    #[prost(string, tag = "17")]
+    pub platform_fee_mint: ::prost::alloc::string::String,
 }
 /// Nested message and enum types in `SwapV1Out`.
 pub mod swap_v1_out {
@@ -150,7 +189,7 @@ pub struct CreateSwapTransactionRequest {
     /// 钱包地址
     #[prost(string, tag = "1")]
     pub wallet: ::prost::alloc::string::String,
-    /// 交易优先费 计算单元价格（以微 lamports 为单位）
+    /// 交易优先费 计算单元价格（以微 lamports 为单位），为空或 "auto" 时自动估算
     #[prost(string, tag = "2")]
     pub compute_unit_price_micro_lamports: ::prost::alloc::string::String,
     /// 询价的返回
@@ -171,6 +210,27 @@ pub struct CreateSwapTransactionRequest {
     /// 可选 接收的 token 账户
     #[prost(string, tag = "8")]
     pub output_account: ::prost::alloc::string::String,
+    /// 询价 id，必传，使用服务端保存的询价结果构建交易，swap_response 可以不传；服务端开启 accept_client_quotes 时可以只传 swap_response
+    #[prost(string, tag = "9")]
+    pub quote_id: ::prost::alloc::string::String,
+    /// 可选 支付给区块引擎的小费（lamports），为 0 时不支付
+    #[prost(uint64, tag = "10")]
+    pub tip_lamports: u64,
+    /// 可选 是否将小费放在单独的交易中，按 bundle 格式返回（swap 交易，小费交易）
+    #[prost(bool, tag = "11")]
+    pub tip_as_bundle: bool,
+    /// 可选 durable nonce 账户，传入时交易使用 nonce 代替 recent blockhash，在 nonce 被推进前一直有效
+    #[prost(string, tag = "12")]
+    pub nonce_account: ::prost::alloc::string::String,
+    /// 可选 nonce 账户的 authority，默认为钱包地址
+    #[prost(string, tag = "13")]
+    pub nonce_authority: ::prost::alloc::string::String,
+    /// 可选 推荐人钱包地址，按配置的比例分得平台费
+    #[prost(string, tag = "14")]
+    pub referral_account: ::prost::alloc::string::String,
+    /// 可选 由服务端的代付账户支付交易费和租金，返回代付账户部分签名的交易，钱包签名后即可提交；不支持小费和 unwrap_sol
+    #[prost(bool, tag = "15")]
+    pub sponsor_fee: bool,
 }
 /// CreateSwapTransactionResponse 返回生成的交易数据
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -179,9 +239,24 @@ pub struct CreateSwapTransactionRequest {
 pub struct CreateSwapTransactionResponse {
     #[prost(message, optional, tag = "1")]
     pub result: ::core::option::Option<super::base::CommonResult>,
-    /// 交易数据列表å
+    /// 交易数据列表å，bundle 格式时依次为 swap 交易和小费交易
     #[prost(message, repeated, tag = "2")]
     pub data: ::prost::alloc::vec::Vec<TransactionData>,
+    /// 交易中新建 token 账户（ATA）需要的租金
+    #[prost(uint64, tag = "3")]
+    pub ata_rent_lamports: u64,
+    /// 交易中使用的地址查找表
+    #[prost(string, repeated, tag = "4")]
+    pub address_lookup_tables: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// 交易实际使用的计算单元价格（以微 lamports 为单位）
+    #[prost(uint64, tag = "5")]
+    pub compute_unit_price_micro_lamports: u64,
+    /// 接收小费的账户，未支付小费时为空
+    #[prost(string, tag = "6")]
+    pub tip_account: ::prost::alloc::string::String,
+    /// 交易的手续费支付者，代付时为服务端的代付账户
+    #[prost(string, tag = "7")]
+    pub fee_payer: ::prost::alloc::string::String,
 }
 /// TransactionData 表示单个交易数据
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -192,6 +267,139 @@ pub struct TransactionData {
     #[prost(string, tag = "1")]
     pub transaction: ::prost::alloc::string::String,
 }
+/// GetSwapInstructionsResponse 返回 swap 交易的各部分指令
+/// 组装顺序：advance_nonce_instruction（如有）、compute_budget_instructions、setup_instructions、swap_instruction、cleanup_instructions
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetSwapInstructionsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub result: ::core::option::Option<super::base::CommonResult>,
+    /// 设置计算单元上限和价格的指令
+    #[prost(message, repeated, tag = "2")]
+    pub compute_budget_instructions: ::prost::alloc::vec::Vec<InstructionInfo>,
+    /// 创建 ATA、封装 SOL 等前置指令
+    #[prost(message, repeated, tag = "3")]
+    pub setup_instructions: ::prost::alloc::vec::Vec<InstructionInfo>,
+    /// 单池 swap 指令或多跳路由指令
+    #[prost(message, optional, tag = "4")]
+    pub swap_instruction: ::core::option::Option<InstructionInfo>,
+    /// 解封 SOL 等后置指令
+    #[prost(message, repeated, tag = "5")]
+    pub cleanup_instructions: ::prost::alloc::vec::Vec<InstructionInfo>,
+    /// 组装交易时需要使用的地址查找表
+    #[prost(string, repeated, tag = "6")]
+    pub address_lookup_tables: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// 新建 token 账户（ATA）需要的租金
+    #[prost(uint64, tag = "7")]
+    pub ata_rent_lamports: u64,
+    /// 计算单元上限
+    #[prost(uint32, tag = "8")]
+    pub compute_unit_limit: u32,
+    /// 计算单元价格（以微 lamports 为单位）
+    #[prost(uint64, tag = "9")]
+    pub compute_unit_price_micro_lamports: u64,
+    /// 接收小费的账户，未支付小费时为空
+    #[prost(string, tag = "10")]
+    pub tip_account: ::prost::alloc::string::String,
+    /// 使用 durable nonce 时必须作为交易的第一条指令
+    #[prost(message, optional, tag = "11")]
+    pub advance_nonce_instruction: ::core::option::Option<InstructionInfo>,
+}
+/// InstructionInfo 表示单条指令
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InstructionInfo {
+    /// 程序地址
+    #[prost(string, tag = "1")]
+    pub program_id: ::prost::alloc::string::String,
+    /// 指令使用的账户
+    #[prost(message, repeated, tag = "2")]
+    pub accounts: ::prost::alloc::vec::Vec<AccountMetaInfo>,
+    /// base64 编码的指令数据
+    #[prost(string, tag = "3")]
+    pub data: ::prost::alloc::string::String,
+}
+/// AccountMetaInfo 表示指令中的单个账户
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AccountMetaInfo {
+    #[prost(string, tag = "1")]
+    pub pubkey: ::prost::alloc::string::String,
+    #[prost(bool, tag = "2")]
+    pub is_signer: bool,
+    #[prost(bool, tag = "3")]
+    pub is_writable: bool,
+}
+/// SimulateSwapResponse 返回 swap 交易的模拟执行结果
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SimulateSwapResponse {
+    #[prost(message, optional, tag = "1")]
+    pub result: ::core::option::Option<super::base::CommonResult>,
+    /// 模拟执行是否成功
+    #[prost(bool, tag = "2")]
+    pub success: bool,
+    /// 模拟执行失败的原因
+    #[prost(message, optional, tag = "3")]
+    pub error: ::core::option::Option<SimulationError>,
+    /// 程序日志
+    #[prost(string, repeated, tag = "4")]
+    pub logs: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// 消耗的计算单元
+    #[prost(uint64, tag = "5")]
+    pub units_consumed: u64,
+    /// 钱包各代币的余额变化
+    #[prost(message, repeated, tag = "6")]
+    pub token_balance_changes: ::prost::alloc::vec::Vec<TokenBalanceChange>,
+    /// 钱包 SOL 余额的变化（lamports），包含交易费、租金和封装/解封的 SOL
+    #[prost(int64, tag = "7")]
+    pub sol_balance_change: i64,
+    /// base64 编码的模拟执行的交易
+    #[prost(string, tag = "8")]
+    pub transaction: ::prost::alloc::string::String,
+}
+/// SimulationError 表示模拟执行失败的原因
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SimulationError {
+    /// 交易错误的描述
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+    /// 出错的指令序号，交易级别的错误为空
+    #[prost(uint32, optional, tag = "2")]
+    pub instruction_index: ::core::option::Option<u32>,
+    /// 出错指令所属的程序
+    #[prost(string, tag = "3")]
+    pub program_id: ::prost::alloc::string::String,
+    /// 程序返回的自定义错误码
+    #[prost(uint32, optional, tag = "4")]
+    pub error_code: ::core::option::Option<u32>,
+    /// anchor 程序日志中的错误名
+    #[prost(string, tag = "5")]
+    pub error_name: ::prost::alloc::string::String,
+}
+/// TokenBalanceChange 表示单个代币的余额变化
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenBalanceChange {
+    #[prost(string, tag = "1")]
+    pub mint: ::prost::alloc::string::String,
+    /// 交易前的余额
+    #[prost(string, tag = "2")]
+    pub pre_amount: ::prost::alloc::string::String,
+    /// 交易后的余额
+    #[prost(string, tag = "3")]
+    pub post_amount: ::prost::alloc::string::String,
+    /// 余额变化，减少时为负数
+    #[prost(string, tag = "4")]
+    pub change: ::prost::alloc::string::String,
+}
 /// TxVersion 定义了交易的版本类型
 #[derive(serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -366,6 +574,60 @@ pub mod router_service_client {
                 );
             self.inner.unary(req, path, codec).await
         }
+        /// 构建指令：返回 swap 交易的各部分指令，由调用方自行组装交易
+        pub async fn get_swap_instructions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateSwapTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetSwapInstructionsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/router.RouterService/GetSwapInstructions",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("router.RouterService", "GetSwapInstructions"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// 模拟执行：构建交易并模拟执行，返回日志、cu 消耗和余额变化
+        pub async fn simulate_swap(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateSwapTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SimulateSwapResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/router.RouterService/SimulateSwap",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("router.RouterService", "SimulateSwap"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -397,6 +659,22 @@ pub mod router_service_server {
             tonic::Response<super::CreateSwapTransactionResponse>,
             tonic::Status,
         >;
+        /// 构建指令：返回 swap 交易的各部分指令，由调用方自行组装交易
+        async fn get_swap_instructions(
+            &self,
+            request: tonic::Request<super::CreateSwapTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetSwapInstructionsResponse>,
+            tonic::Status,
+        >;
+        /// 模拟执行：构建交易并模拟执行，返回日志、cu 消耗和余额变化
+        async fn simulate_swap(
+            &self,
+            request: tonic::Request<super::CreateSwapTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SimulateSwapResponse>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct RouterServiceServer<T> {
@@ -568,6 +846,104 @@ pub mod router_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/router.RouterService/GetSwapInstructions" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSwapInstructionsSvc<T: RouterService>(pub Arc<T>);
+                    impl<
+                        T: RouterService,
+                    > tonic::server::UnaryService<super::CreateSwapTransactionRequest>
+                    for GetSwapInstructionsSvc<T> {
+                        type Response = super::GetSwapInstructionsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateSwapTransactionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as RouterService>::get_swap_instructions(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetSwapInstructionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/router.RouterService/SimulateSwap" => {
+                    #[allow(non_camel_case_types)]
+                    struct SimulateSwapSvc<T: RouterService>(pub Arc<T>);
+                    impl<
+                        T: RouterService,
+                    > tonic::server::UnaryService<super::CreateSwapTransactionRequest>
+                    for SimulateSwapSvc<T> {
+                        type Response = super::SimulateSwapResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateSwapTransactionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as RouterService>::simulate_swap(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SimulateSwapSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -1,22 +1,30 @@
 use crate::service::core::account_puller;
 use crate::service::core::account_puller::AccountPuller;
+use crate::service::core::{lookup_table, result_utils, tip, tx_check};
+use crate::service::core::tx_sender::{self, TransactionRpc, TxState, TxStatus, TxTracker};
 use crate::service::pb::base::CommonResult;
 use crate::service::pb::query::query_service_server::QueryService;
 use crate::service::pb::query::{
-  CheckTxRequest, CheckTxResponse, GetContractInfoRequest, GetContractInfoResponse, ListLinePositionRequest, ListLinePositionResponse,
-  PointData,
+  CheckTxRequest, CheckTxResponse, GetContractInfoRequest, GetContractInfoResponse, GetTransactionStatusRequest,
+  GetTransactionStatusResponse, ListLinePositionRequest, ListLinePositionResponse, PointData, SubmitTransactionRequest,
+  SubmitTransactionResponse, TransactionState,
 };
 use anchor_lang::AccountDeserialize;
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
 use log::{error, info, log};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
 use solana_sdk::transaction::VersionedTransaction;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
 #[derive(Default, Debug)]
 pub struct DexQueryService {
-
+  /// 通过 SubmitTransaction 提交的交易的状态
+  tx_tracker: Arc<TxTracker>,
 }
 
 #[tonic::async_trait]
@@ -27,31 +35,96 @@ impl QueryService for DexQueryService {
     let req = request.into_inner();
     info!("check_tx request: {:?}", req);
 
-    if req.pre_data.len() != req.data.len() {
-      return Err(Status::invalid_argument("pre_data and data length mismatch"));
-    }
+    check_transactions(&req.pre_data, &req.data).await?;
+    Ok(Response::new(CheckTxResponse { result: Some(CommonResult { ret_code: 0, ret_msg: "success".to_string() }) }))
+  }
+
+  /// 提交交易：检查交易未被篡改后通过 rpc 节点发送，并跟踪确认状态
+  async fn submit_transaction(&self, request: Request<SubmitTransactionRequest>) -> Result<Response<SubmitTransactionResponse>, Status> {
+    let req = request.into_inner();
+    info!("submit_transaction request: {:?}", req);
+
+    // 检查时已确认所有签名者都已签名，手续费支付者的签名即交易签名
+    let signed_txs = check_transactions(&req.pre_data, &req.data).await?;
+
+    let nacos_config = crate::nacos_config::entrance::get_nacos_config().await;
+    let rpcs: Arc<Vec<RpcClient>> = Arc::new(nacos_config.rpcs.iter().map(|url| RpcClient::new(url.clone())).collect());
+    let send_options = nacos_config.get_send_options();
 
-    for (pre_b64, signed_b64) in req.pre_data.iter().zip(req.data.iter()) {
-      // 解析未签名交易
-      let pre_tx: VersionedTransaction = {
-        let decoded =
-          BASE64_STANDARD.decode(pre_b64).map_err(|e| Status::invalid_argument(format!("Failed to decode pre_data base64: {}", e)))?;
-        bincode::deserialize(&decoded).map_err(|e| Status::invalid_argument(format!("Failed to deserialize pre_data: {}", e)))?
-      };
-
-      // 解析签名交易
-      let signed_tx: VersionedTransaction = {
-        let decoded =
-          BASE64_STANDARD.decode(signed_b64).map_err(|e| Status::invalid_argument(format!("Failed to decode data base64: {}", e)))?;
-        bincode::deserialize(&decoded).map_err(|e| Status::invalid_argument(format!("Failed to deserialize data: {}", e)))?
-      };
-
-      // 比较 message 是否相等
-      if pre_tx.message != signed_tx.message {
-        return Err(Status::permission_denied("Transaction message mismatch — possible tampering"));
+    let mut signatures = Vec::with_capacity(signed_txs.len());
+    for signed_tx in signed_txs {
+      let signature = signed_tx.signatures[0];
+      // 同一笔交易重复提交时不重复发送
+      let tracked = self.tx_tracker.track(signature).await.map_err(|e| Status::resource_exhausted(e.to_string()))?;
+      if let Some(status) = tracked {
+        let (rpcs, tx_tracker) = (rpcs.clone(), self.tx_tracker.clone());
+        tokio::spawn(async move {
+          let final_status = tx_sender::send_and_track(rpcs.as_slice(), &signed_tx, &status, send_options).await;
+          tx_tracker.finish(&signature).await;
+          info!("transaction {} finished: {:?}", signature, final_status);
+        });
       }
+      signatures.push(signature.to_string());
     }
-    Ok(Response::new(CheckTxResponse { result: Some(CommonResult { ret_code: 0, ret_msg: "success".to_string() }) }))
+
+    Ok(Response::new(SubmitTransactionResponse {
+      result: Some(CommonResult { ret_code: 0, ret_msg: "success".to_string() }),
+      signatures,
+    }))
+  }
+
+  /// 查询交易状态
+  async fn get_transaction_status(
+    &self,
+    request: Request<GetTransactionStatusRequest>,
+  ) -> Result<Response<GetTransactionStatusResponse>, Status> {
+    let req = request.into_inner();
+    let signature = parse_signature(&req.signature)?;
+
+    let status = match self.tx_tracker.get(&signature).await {
+      Some(status) => Some(status),
+      // 未通过本服务提交的交易，直接查询链上状态
+      None => {
+        let rpc_client = crate::nacos_config::entrance::get_nacos_config().await.get_rand_rpc();
+        let chain_status = rpc_client
+          .get_tx_state(&signature)
+          .await
+          .map_err(|e| Status::internal(format!("Failed to get status of {}: {}", signature, e)))?;
+        chain_status.map(|(state, slot)| TxStatus { state, slot, send_attempts: 0 })
+      }
+    };
+    Ok(Response::new(transaction_status_response(&signature, status.as_ref())))
+  }
+
+  type WatchTransactionStatusStream = ReceiverStream<Result<GetTransactionStatusResponse, Status>>;
+
+  /// 订阅交易状态：状态变化时推送，交易进入终态后结束
+  async fn watch_transaction_status(
+    &self,
+    request: Request<GetTransactionStatusRequest>,
+  ) -> Result<Response<Self::WatchTransactionStatusStream>, Status> {
+    let req = request.into_inner();
+    let signature = parse_signature(&req.signature)?;
+    let mut receiver = self
+      .tx_tracker
+      .subscribe(&signature)
+      .await
+      .ok_or_else(|| Status::not_found(format!("Transaction {} is not submitted through this service", signature)))?;
+
+    let (sender, stream) = tokio::sync::mpsc::channel(16);
+    tokio::spawn(async move {
+      loop {
+        let status = receiver.borrow_and_update().clone();
+        // 客户端断开时停止推送
+        if sender.send(Ok(transaction_status_response(&signature, Some(&status)))).await.is_err() || status.state.is_finished() {
+          break;
+        }
+        if receiver.changed().await.is_err() {
+          break;
+        }
+      }
+    });
+    Ok(Response::new(ReceiverStream::new(stream)))
   }
 
   /// 获取代币的基本信息
@@ -118,3 +191,71 @@ impl QueryService for DexQueryService {
     Ok(Response::new(response))
   }
 }
+
+/// 检查签名后的交易与未签名的交易一致（未被篡改）、所有签名有效、只调用允许的程序，且 blockhash 未过期或 nonce 未被推进
+/// 返回解析后的签名交易
+async fn check_transactions(pre_data: &[String], data: &[String]) -> Result<Vec<VersionedTransaction>, Status> {
+  if pre_data.len() != data.len() {
+    return Err(Status::invalid_argument("pre_data and data length mismatch"));
+  }
+
+  let nacos_config = crate::nacos_config::entrance::get_nacos_config().await;
+  let rpc_client = nacos_config.get_rand_rpc();
+  let tip_accounts = tip::parse_tip_accounts(&nacos_config.tip_accounts).map_err(|e| Status::internal(e.to_string()))?;
+  let mut signed_txs = Vec::with_capacity(data.len());
+  for (pre_b64, signed_b64) in pre_data.iter().zip(data.iter()) {
+    // 解析未签名交易
+    let pre_tx: VersionedTransaction = {
+      let decoded =
+        BASE64_STANDARD.decode(pre_b64).map_err(|e| Status::invalid_argument(format!("Failed to decode pre_data base64: {}", e)))?;
+      bincode::deserialize(&decoded).map_err(|e| Status::invalid_argument(format!("Failed to deserialize pre_data: {}", e)))?
+    };
+
+    // 解析签名交易
+    let signed_tx: VersionedTransaction = {
+      let decoded =
+        BASE64_STANDARD.decode(signed_b64).map_err(|e| Status::invalid_argument(format!("Failed to decode data base64: {}", e)))?;
+      bincode::deserialize(&decoded).map_err(|e| Status::invalid_argument(format!("Failed to deserialize data: {}", e)))?
+    };
+
+    // 还原通过地址查找表加载的账户需要查找表的内容
+    let table_keys: Vec<Pubkey> =
+      signed_tx.message.address_table_lookups().unwrap_or_default().iter().map(|lookup| lookup.account_key).collect();
+    let lookup_tables = lookup_table::load_lookup_tables_for_decoding(&rpc_client, &table_keys)
+      .await
+      .map_err(|e| Status::unavailable(format!("Failed to load address lookup tables: {}", e)))?;
+
+    // 检查未被篡改、签名、调用的程序、system 指令和 swap 指令、blockhash，失败时说明是哪一项
+    tx_check::check_signed_transaction(&rpc_client, &pre_tx, &signed_tx, &lookup_tables, &tip_accounts)
+      .await
+      .map_err(|e| result_utils::error_status(&e))?;
+    signed_txs.push(signed_tx);
+  }
+  Ok(signed_txs)
+}
+
+fn parse_signature(signature: &str) -> Result<Signature, Status> {
+  Signature::from_str(signature).map_err(|e| Status::invalid_argument(format!("Invalid signature {}: {}", signature, e)))
+}
+
+/// 交易状态转换为接口返回的格式，status 为空时表示未找到交易
+fn transaction_status_response(signature: &Signature, status: Option<&TxStatus>) -> GetTransactionStatusResponse {
+  let (state, error) = match status.map(|status| &status.state) {
+    None => (TransactionState::Unknown, String::new()),
+    Some(TxState::Pending) => (TransactionState::Pending, String::new()),
+    Some(TxState::Processed) => (TransactionState::Processed, String::new()),
+    Some(TxState::Confirmed) => (TransactionState::Confirmed, String::new()),
+    Some(TxState::Finalized) => (TransactionState::Finalized, String::new()),
+    Some(TxState::Failed(err)) => (TransactionState::Failed, err.clone()),
+    Some(TxState::Expired) => (TransactionState::Expired, String::new()),
+    Some(TxState::TimedOut) => (TransactionState::TimedOut, String::new()),
+  };
+  GetTransactionStatusResponse {
+    result: Some(CommonResult { ret_code: 0, ret_msg: "success".to_string() }),
+    signature: signature.to_string(),
+    state: state as i32,
+    slot: status.map_or(0, |status| status.slot),
+    error,
+    send_attempts: status.map_or(0, |status| status.send_attempts),
+  }
+}
@@ -1,12 +1,10 @@
 use std::fmt;
 
-use anyhow::Result;
-
 use raydium_amm_v3::libraries::{MAX_SQRT_PRICE_X64, MIN_SQRT_PRICE_X64, MulDiv};
 use solana_sdk::{epoch_info::EpochInfo, pubkey::Pubkey};
-use spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use spl_token_2022::extension::transfer_fee::{MAX_FEE_BASIS_POINTS, TransferFeeConfig};
 
-use super::types::PoolInfo;
+use super::{error::SwapComputeError, types::PoolInfo};
 
 /// 计算 swap 的结果集合
 #[derive(Debug, Default)]
@@ -42,6 +40,9 @@ pub struct OneStepSwapResult {
 
   /// 计算时涉及的 tick-array 的 pubkey
   pub tick_array_keys: Vec<Pubkey>,
+
+  /// 从该 slot 开始计算结果失效（transfer-fee 切换到新的费率），None 表示不会因 transfer-fee 失效
+  pub expiration_slot: Option<u64>,
 }
 
 impl fmt::Display for OneStepSwapResult {
@@ -66,7 +67,6 @@ impl fmt::Display for OneStepSwapResult {
   }
 }
 
-// todo: 可能还需要expiration_time
 /// 根据输入的mint和数量计算输出的mint和数量
 /// `base_input` specified_amount 是 input-amount 还是 output-amount
 pub fn compute_another_amount(
@@ -76,14 +76,18 @@ pub fn compute_another_amount(
   specified_amount: u64,
   epoch_info: &EpochInfo,
   sqrt_price_x64_limit: Option<u128>,
-) -> Result<OneStepSwapResult> {
+) -> Result<OneStepSwapResult, SwapComputeError> {
   // Check if the input mint is either mint_a or mint_b
   let zero_for_one = pool.base_info.mint_a_info.mint == *input_mint;
-  let (base_fee_config, out_fee_config) = if zero_for_one {
+  let (input_fee_config, output_fee_config) = if zero_for_one {
     (&pool.base_info.mint_a_info.transfer_fee_config, &pool.base_info.mint_b_info.transfer_fee_config)
   } else {
     (&pool.base_info.mint_b_info.transfer_fee_config, &pool.base_info.mint_a_info.transfer_fee_config)
   };
+  // 指定 input-amount 时，指定的是 input 代币，计算得到的是 output 代币
+  // 指定 output-amount 时，指定的是 output 代币，计算得到的是 input 代币
+  let (specified_fee_config, calculated_fee_config) =
+    if base_input { (input_fee_config, output_fee_config) } else { (output_fee_config, input_fee_config) };
 
   // 调整价格限制值
   let sqrt_price_x64_limit =
@@ -92,8 +96,8 @@ pub fn compute_another_amount(
   // 指定 input-amount时，需要扣除 transfer-fee, 成为实际的 input_amount
   // 指定 output-amount时，需要添加 transfer-fee, 成为实际的 output_amount
   let add_fee = !base_input;
-  let GetTransferAmountFeeResult { amount: real_amount_specified, .. } =
-    get_transfer_amount_fee(specified_amount, base_fee_config, epoch_info, add_fee);
+  let GetTransferAmountFeeResult { amount: real_amount_specified, expiration_slot: specified_expiration_slot, .. } =
+    get_transfer_amount_fee(specified_amount, specified_fee_config, epoch_info, add_fee)?;
 
   // 真正的计算
   // 获取第一个初始化的tickArray, 根据swap方向
@@ -109,7 +113,7 @@ pub fn compute_another_amount(
   // swap-compute
   let (swap_state, tick_array_start_index_vec) = pool.swap_compute(
     zero_for_one,
-    true,
+    base_input,
     is_exist,
     real_amount_specified,
     first_tick_array_start_index,
@@ -118,10 +122,10 @@ pub fn compute_another_amount(
     &mut tick_array_dequeue,
   )?;
 
-  // 指定 input-amount时，需要扣除 transfer-fee, 成为实际的 input_amount
-  // 指定 output-amount时，需要添加 transfer-fee, 成为实际的 output_amount
-  let GetTransferAmountFeeResult { amount: real_amount_out, .. } =
-    get_transfer_amount_fee(swap_state.amount_calculated, out_fee_config, epoch_info, add_fee);
+  // 指定 input-amount时，output 需要扣除 transfer-fee, 成为用户实际收到的 output_amount
+  // 指定 output-amount时，input 需要添加 transfer-fee, 成为用户实际支付的 input_amount
+  let GetTransferAmountFeeResult { amount: real_amount_out, expiration_slot: calculated_expiration_slot, .. } =
+    get_transfer_amount_fee(swap_state.amount_calculated, calculated_fee_config, epoch_info, add_fee)?;
 
   let tick_array_keys =
     tick_array_start_index_vec.into_iter().map(|index| PoolInfo::get_pda_tick_array_address(&pool.base_info.id, index)).collect();
@@ -141,6 +145,7 @@ pub fn compute_another_amount(
     after_liquidity: swap_state.liquidity,
     fee_amount: swap_state.fee_amount,
     tick_array_keys: tick_array_keys,
+    expiration_slot: earliest_slot(specified_expiration_slot, calculated_expiration_slot),
   })
 }
 
@@ -149,36 +154,56 @@ pub struct GetTransferAmountFeeResult {
   /// 考虑了手续费的amount
   /// 如果是添加手续费，则是 amount + fee
   /// 如果是扣除手续费，则是 amount - fee
-  amount: u64,
+  pub amount: u64,
   /// 转账手续费
-  fee: u64,
-  /// 超过该时间，则计算无效， 主要取决于 transfer_fee_config 的 epoch信息
-  expiration_time: Option<u64>,
+  pub fee: u64,
+  /// 从该 slot 开始计算无效， 取决于 transfer_fee_config 中新费率生效的 epoch
+  pub expiration_slot: Option<u64>,
+}
+
+/// 两个失效 slot 中较早的那个，None 表示不会失效
+pub fn earliest_slot(first: Option<u64>, second: Option<u64>) -> Option<u64> {
+  first.into_iter().chain(second).min()
 }
 
 /// 计算添加或扣除转账手续费之后的转账金额
+/// 添加手续费时按链上程序的方式计算反向手续费，保证扣除手续费后恰好是 amount
 pub fn get_transfer_amount_fee(
   amount: u64,
   fee_config: &Option<TransferFeeConfig>,
   epoch_info: &EpochInfo,
   add_fee: bool,
-) -> GetTransferAmountFeeResult {
+) -> Result<GetTransferAmountFeeResult, SwapComputeError> {
   match fee_config {
     Some(config) => {
       let newer_epoch: u64 = config.newer_transfer_fee.epoch.into();
-      let expiration_time = if epoch_info.epoch < newer_epoch {
-        // 按 400ms 一个 slot 计算
-        Some((newer_epoch * epoch_info.slots_in_epoch - epoch_info.absolute_slot) * 400 / 1000)
+      let expiration_slot = if epoch_info.epoch < newer_epoch {
+        // 新费率在 newer_epoch 的第一个 slot 生效
+        let current_epoch_start_slot = epoch_info.absolute_slot.saturating_sub(epoch_info.slot_index);
+        Some(current_epoch_start_slot.saturating_add((newer_epoch - epoch_info.epoch).saturating_mul(epoch_info.slots_in_epoch)))
       } else {
         None
       };
 
-      let fee = config.calculate_epoch_fee(epoch_info.epoch, amount).unwrap_or(0);
+      let fee = if add_fee {
+        let transfer_fee = config.get_epoch_fee(epoch_info.epoch);
+        if u16::from(transfer_fee.transfer_fee_basis_points) == MAX_FEE_BASIS_POINTS {
+          u64::from(transfer_fee.maximum_fee)
+        } else {
+          config.calculate_inverse_epoch_fee(epoch_info.epoch, amount).ok_or(SwapComputeError::Overflow("transfer fee"))?
+        }
+      } else {
+        config.calculate_epoch_fee(epoch_info.epoch, amount).ok_or(SwapComputeError::Overflow("transfer fee"))?
+      };
 
-      let amount = if add_fee { amount + fee } else { amount - fee };
+      let amount = if add_fee {
+        amount.checked_add(fee).ok_or(SwapComputeError::Overflow("transfer amount + transfer fee"))?
+      } else {
+        amount.checked_sub(fee).ok_or(SwapComputeError::Underflow("transfer amount - transfer fee"))?
+      };
 
-      GetTransferAmountFeeResult { amount, fee, expiration_time }
+      Ok(GetTransferAmountFeeResult { amount, fee, expiration_slot })
     }
-    None => GetTransferAmountFeeResult { amount: amount, ..Default::default() },
+    None => Ok(GetTransferAmountFeeResult { amount: amount, ..Default::default() }),
   }
 }
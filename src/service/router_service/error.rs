@@ -0,0 +1,268 @@
+use thiserror::Error;
+use tonic::{Code, Status, metadata::MetadataMap};
+
+/// swap 计算过程中的错误
+/// 询价路径上的数学计算都返回该错误，而不是 panic
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SwapComputeError {
+  #[error("Invalid swap amount: {0}")]
+  InvalidAmount(&'static str),
+
+  #[error("Arithmetic overflow: {0}")]
+  Overflow(&'static str),
+
+  #[error("Arithmetic underflow: {0}")]
+  Underflow(&'static str),
+
+  #[error("Missing tick array: {0}")]
+  MissingTickArray(String),
+
+  #[error("Tick array mismatch, expected start index {expected}, got {actual}")]
+  TickArrayMismatch { expected: i32, actual: i32 },
+
+  #[error("Tick arrays are not strictly increasing: start index {current} after {last}")]
+  InvalidTickArrayOrder { last: i32, current: i32 },
+
+  #[error("Price limit error: {0}")]
+  PriceLimit(&'static str),
+
+  #[error("Liquidity exhausted: {0}")]
+  LiquidityExhausted(&'static str),
+
+  #[error("Swap step count exceeds limit: {0}")]
+  StepLimitExceeded(u32),
+
+  #[error("Tick math error: {0}")]
+  TickMath(String),
+}
+
+/// raydium-clmm 库函数返回的错误，统一归为 tick 数学错误
+impl From<anchor_lang::error::Error> for SwapComputeError {
+  fn from(err: anchor_lang::error::Error) -> Self {
+    SwapComputeError::TickMath(err.to_string())
+  }
+}
+
+/// 转换为 gRPC 的 tonic::Status
+impl From<&SwapComputeError> for Status {
+  fn from(err: &SwapComputeError) -> Self {
+    let code = match err {
+      SwapComputeError::InvalidAmount(_) | SwapComputeError::PriceLimit(_) => Code::InvalidArgument,
+      SwapComputeError::Overflow(_) | SwapComputeError::Underflow(_) => Code::OutOfRange,
+      // 池子数据不完整或已过期，重试可能成功
+      SwapComputeError::MissingTickArray(_)
+      | SwapComputeError::TickArrayMismatch { .. }
+      | SwapComputeError::InvalidTickArrayOrder { .. } => Code::Unavailable,
+      SwapComputeError::LiquidityExhausted(_) => Code::FailedPrecondition,
+      SwapComputeError::StepLimitExceeded(_) => Code::ResourceExhausted,
+      SwapComputeError::TickMath(_) => Code::Internal,
+    };
+    Status::new(code, format!("Swap compute error: {}", err))
+  }
+}
+
+/// 询价结果在构建交易时校验失败的错误
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuoteError {
+  #[error("Quote has no expiry, request a new quote")]
+  MissingExpiry,
+
+  #[error("Quote expired at slot {expire_slot}, current slot is {current_slot}, request a new quote")]
+  Expired { expire_slot: u64, current_slot: u64 },
+
+  #[error("Quote not found or expired: {0}")]
+  NotFound(String),
+
+  #[error("Quote does not match the stored quote: {0}")]
+  Tampered(String),
+
+  #[error("quote_id is required when {0}")]
+  QuoteIdRequired(&'static str),
+}
+
+/// 转换为 gRPC 的 tonic::Status
+impl From<&QuoteError> for Status {
+  fn from(err: &QuoteError) -> Self {
+    let code = match err {
+      QuoteError::MissingExpiry => Code::InvalidArgument,
+      QuoteError::Expired { .. } => Code::FailedPrecondition,
+      QuoteError::NotFound(_) => Code::NotFound,
+      QuoteError::Tampered(_) => Code::PermissionDenied,
+      QuoteError::QuoteIdRequired(_) => Code::InvalidArgument,
+    };
+    Status::new(code, format!("Quote error: {}", err))
+  }
+}
+
+/// 构建交易前检查钱包余额和账户状态的错误
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PreflightError {
+  #[error("Token account {account} does not exist")]
+  TokenAccountNotFound { account: String },
+
+  #[error("Account {account} is not a token account")]
+  InvalidTokenAccount { account: String },
+
+  #[error("Token account {account} holds mint {actual}, expected {expected}")]
+  TokenAccountMintMismatch { account: String, expected: String, actual: String },
+
+  #[error("Token account {account} is owned by {actual}, expected {expected}")]
+  TokenAccountOwnerMismatch { account: String, expected: String, actual: String },
+
+  #[error("Token account {account} is frozen")]
+  TokenAccountFrozen { account: String },
+
+  #[error("Insufficient {mint} balance in {account}: required {required}, available {available}")]
+  InsufficientTokenBalance { mint: String, account: String, required: u64, available: u64 },
+
+  #[error("Insufficient SOL balance in {account}: required {required} lamports, available {available} lamports")]
+  InsufficientSolBalance { account: String, required: u64, available: u64 },
+}
+
+impl PreflightError {
+  /// 结构化的错误详情，调用方不需要解析错误信息即可得到账户、需要和可用的余额
+  pub fn details(&self) -> Vec<(&'static str, String)> {
+    match self {
+      PreflightError::TokenAccountNotFound { account }
+      | PreflightError::InvalidTokenAccount { account }
+      | PreflightError::TokenAccountFrozen { account } => vec![("preflight-account", account.clone())],
+      PreflightError::TokenAccountMintMismatch { account, expected, actual }
+      | PreflightError::TokenAccountOwnerMismatch { account, expected, actual } => {
+        vec![("preflight-account", account.clone()), ("preflight-expected", expected.clone()), ("preflight-actual", actual.clone())]
+      }
+      PreflightError::InsufficientTokenBalance { mint, account, required, available } => vec![
+        ("preflight-account", account.clone()),
+        ("preflight-mint", mint.clone()),
+        ("preflight-required", required.to_string()),
+        ("preflight-available", available.to_string()),
+      ],
+      PreflightError::InsufficientSolBalance { account, required, available } => vec![
+        ("preflight-account", account.clone()),
+        ("preflight-required", required.to_string()),
+        ("preflight-available", available.to_string()),
+      ],
+    }
+  }
+}
+
+/// 转换为 gRPC 的 tonic::Status，错误详情放在 metadata 中
+impl From<&PreflightError> for Status {
+  fn from(err: &PreflightError) -> Self {
+    let code = match err {
+      PreflightError::TokenAccountNotFound { .. } => Code::NotFound,
+      PreflightError::InvalidTokenAccount { .. }
+      | PreflightError::TokenAccountMintMismatch { .. }
+      | PreflightError::TokenAccountOwnerMismatch { .. } => Code::InvalidArgument,
+      PreflightError::TokenAccountFrozen { .. }
+      | PreflightError::InsufficientTokenBalance { .. }
+      | PreflightError::InsufficientSolBalance { .. } => Code::FailedPrecondition,
+    };
+    let mut metadata = MetadataMap::new();
+    for (key, value) in err.details() {
+      // 地址和数字都是 ASCII，不会转换失败
+      if let Ok(value) = value.parse() {
+        metadata.insert(key, value);
+      }
+    }
+    Status::with_metadata(code, format!("Preflight error: {}", err), metadata)
+  }
+}
+
+/// 检查签名交易时失败的项
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TxCheckError {
+  #[error("Transaction message mismatch — possible tampering")]
+  MessageMismatch,
+
+  #[error("Invalid transaction message: {0}")]
+  InvalidMessage(String),
+
+  #[error("Transaction requires {required} signatures, got {actual}")]
+  SignatureCountMismatch { required: usize, actual: usize },
+
+  #[error("Required signer {signer} has not signed")]
+  MissingSignature { signer: String },
+
+  #[error("Signature of {signer} does not match the message")]
+  InvalidSignature { signer: String },
+
+  #[error("Recent blockhash {blockhash} has expired")]
+  BlockhashExpired { blockhash: String },
+
+  #[error("Durable nonce of {nonce_account} has been advanced")]
+  NonceAdvanced { nonce_account: String },
+
+  #[error("Instruction {index} targets program {program_id} which is not allowed")]
+  ProgramNotAllowed { index: usize, program_id: String },
+
+  #[error("System instruction {index} is not allowed: {reason}")]
+  SystemInstructionNotAllowed { index: usize, reason: &'static str },
+
+  #[error("Swap instruction {index} is not allowed: {reason}")]
+  SwapInstructionNotAllowed { index: usize, reason: &'static str },
+}
+
+impl TxCheckError {
+  /// 失败的检查项名称
+  pub fn check(&self) -> &'static str {
+    match self {
+      TxCheckError::MessageMismatch => "message",
+      TxCheckError::InvalidMessage(_) => "sanitize",
+      TxCheckError::SignatureCountMismatch { .. } | TxCheckError::MissingSignature { .. } => "signers",
+      TxCheckError::InvalidSignature { .. } => "signature",
+      TxCheckError::BlockhashExpired { .. } | TxCheckError::NonceAdvanced { .. } => "blockhash",
+      TxCheckError::ProgramNotAllowed { .. } => "program",
+      TxCheckError::SystemInstructionNotAllowed { .. } => "system_instruction",
+      TxCheckError::SwapInstructionNotAllowed { .. } => "swap_instruction",
+    }
+  }
+}
+
+/// 转换为 gRPC 的 tonic::Status
+impl From<&TxCheckError> for Status {
+  fn from(err: &TxCheckError) -> Self {
+    let code = match err {
+      TxCheckError::MessageMismatch
+      | TxCheckError::InvalidSignature { .. }
+      | TxCheckError::ProgramNotAllowed { .. }
+      | TxCheckError::SystemInstructionNotAllowed { .. }
+      | TxCheckError::SwapInstructionNotAllowed { .. } => Code::PermissionDenied,
+      TxCheckError::InvalidMessage(_) | TxCheckError::SignatureCountMismatch { .. } | TxCheckError::MissingSignature { .. } => {
+        Code::InvalidArgument
+      }
+      TxCheckError::BlockhashExpired { .. } | TxCheckError::NonceAdvanced { .. } => Code::FailedPrecondition,
+    };
+    Status::new(code, format!("Transaction check `{}` failed: {}", err.check(), err))
+  }
+}
+
+/// 服务端代付手续费时的错误
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SponsorError {
+  #[error("Fee sponsorship is not configured")]
+  NotConfigured,
+
+  #[error("Fee sponsorship does not support {0}")]
+  Unsupported(&'static str),
+
+  #[error("Compute unit price {price} exceeds the sponsorship limit {limit}")]
+  ComputeUnitPriceExceedsLimit { price: u64, limit: u64 },
+
+  #[error("Sponsored fee {required} lamports exceeds the per-transaction limit {limit} lamports")]
+  CostExceedsLimit { required: u64, limit: u64 },
+
+  #[error("Sponsored rent {required} lamports exceeds the per-transaction limit {limit} lamports")]
+  RentExceedsLimit { required: u64, limit: u64 },
+}
+
+/// 转换为 gRPC 的 tonic::Status
+impl From<&SponsorError> for Status {
+  fn from(err: &SponsorError) -> Self {
+    let code = match err {
+      SponsorError::NotConfigured => Code::Unimplemented,
+      SponsorError::Unsupported(_) | SponsorError::ComputeUnitPriceExceedsLimit { .. } => Code::InvalidArgument,
+      SponsorError::CostExceedsLimit { .. } | SponsorError::RentExceedsLimit { .. } => Code::FailedPrecondition,
+    };
+    Status::new(code, format!("Sponsor error: {}", err))
+  }
+}
@@ -0,0 +1,91 @@
+//! 测试和 fuzz 共用的池子数据构造，只用于询价计算，不涉及链上账户
+
+use std::collections::BTreeMap;
+
+use raydium_amm_v3::{
+  libraries::{tick_array_bit_map, tick_math},
+  states::{TickArrayBitmapExtension, TickArrayState},
+};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::service::{
+  core::types::MintAccountBaseInfo,
+  router_service::types::{POOL_VERSION_CLMM, PoolBaseInfo, PoolDynamicInfo, PoolInfo},
+};
+
+/// 在 tick 处生效的流动性，positions 为 (tick_lower, tick_upper, liquidity)
+pub fn active_liquidity(positions: &[(i32, i32, u128)], tick: i32) -> u128 {
+  positions.iter().filter(|(tick_lower, tick_upper, _)| *tick_lower <= tick && tick < *tick_upper).map(|(_, _, liquidity)| *liquidity).sum()
+}
+
+/// 根据仓位生成 tick-array，按 start_tick_index 升序
+pub fn tick_arrays_from_positions(pool_id: Pubkey, tick_spacing: u16, positions: &[(i32, i32, u128)]) -> Vec<TickArrayState> {
+  let mut tick_arrays: BTreeMap<i32, TickArrayState> = BTreeMap::new();
+
+  for (tick_lower, tick_upper, liquidity) in positions.iter().copied() {
+    for (tick, liquidity_net) in [(tick_lower, liquidity as i128), (tick_upper, -(liquidity as i128))] {
+      let start_tick_index = TickArrayState::get_array_start_index(tick, tick_spacing);
+      let tick_array =
+        tick_arrays.entry(start_tick_index).or_insert_with(|| TickArrayState { pool_id, start_tick_index, ..Default::default() });
+
+      let offset = ((tick - start_tick_index) / i32::from(tick_spacing)) as usize;
+      if tick_array.ticks[offset].liquidity_gross == 0 {
+        tick_array.initialized_tick_count += 1;
+      }
+      let tick_state = &mut tick_array.ticks[offset];
+      tick_state.tick = tick;
+      tick_state.liquidity_net += liquidity_net;
+      tick_state.liquidity_gross += liquidity;
+    }
+  }
+
+  tick_arrays.into_values().collect()
+}
+
+/// 根据已初始化的 tick-array 生成池子的 bitmap 和 bitmap 扩展
+pub fn tick_array_bitmaps(pool_id: Pubkey, tick_spacing: u16, start_indexes: &[i32]) -> ([u64; 16], TickArrayBitmapExtension) {
+  let max_tick_in_bitmap = tick_array_bit_map::max_tick_in_tickarray_bitmap(tick_spacing);
+  let mut tick_array_bitmap = [0u64; 16];
+  let mut tick_array_bitmap_extension = TickArrayBitmapExtension { pool_id, ..Default::default() };
+
+  for start_index in start_indexes.iter().copied() {
+    if start_index >= -max_tick_in_bitmap && start_index < max_tick_in_bitmap {
+      let offset = (start_index / TickArrayState::tick_count(tick_spacing) + 512) as usize;
+      tick_array_bitmap[offset / 64] |= 1 << (offset % 64);
+    } else {
+      tick_array_bitmap_extension.flip_tick_array_bit(start_index, tick_spacing).unwrap();
+    }
+  }
+
+  (tick_array_bitmap, tick_array_bitmap_extension)
+}
+
+/// 只用于询价计算的池子，不涉及链上账户
+pub fn synthetic_pool(tick_spacing: u16, tick_current: i32, trade_fee_rate: u32, positions: &[(i32, i32, u128)]) -> PoolInfo {
+  let pool_id = Pubkey::new_unique();
+  let tick_arrays = tick_arrays_from_positions(pool_id, tick_spacing, positions);
+  let start_indexes: Vec<i32> = tick_arrays.iter().map(|tick_array| tick_array.start_tick_index).collect();
+  let (tick_array_bitmap, tick_array_bitmap_extension) = tick_array_bitmaps(pool_id, tick_spacing, &start_indexes);
+
+  PoolInfo {
+    base_info: PoolBaseInfo {
+      id: pool_id,
+      version: POOL_VERSION_CLMM,
+      tick_spacing,
+      mint_a_info: MintAccountBaseInfo { mint: Pubkey::new_unique(), program_id: spl_token::id(), ..Default::default() },
+      mint_b_info: MintAccountBaseInfo { mint: Pubkey::new_unique(), program_id: spl_token::id(), ..Default::default() },
+      tick_array_bitmap_extension_key: PoolInfo::tick_array_bitmap_extension_key(&pool_id),
+      trade_fee_rate,
+      ..Default::default()
+    },
+    dynamic_info: PoolDynamicInfo {
+      id: pool_id,
+      liquidity: active_liquidity(positions, tick_current),
+      sqrt_price_x64: tick_math::get_sqrt_price_at_tick(tick_current).unwrap(),
+      tick_current,
+      tick_array_bitmap,
+      tick_array_bitmap_extension,
+      all_tick_array_state: tick_arrays,
+    },
+  }
+}
@@ -1,8 +1,12 @@
 pub mod clmm_pool_utils;
+pub mod error;
+#[cfg(any(test, fuzzing))]
+pub mod fixtures;
 pub mod pool_info;
 pub mod quote;
 pub mod route_utils;
 pub mod router_service;
+mod test;
 pub mod types;
 
 pub use router_service::*;
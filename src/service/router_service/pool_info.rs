@@ -1,9 +1,5 @@
-use std::{
-  collections::VecDeque,
-  ops::{DerefMut, Neg},
-};
+use std::{collections::VecDeque, ops::DerefMut};
 
-use anyhow::{Result, anyhow};
 use raydium_amm_v3::{
   libraries::{TICK_ARRAY_BITMAP_SIZE, U512, U1024, liquidity_math, swap_math, tick_array_bit_map, tick_math},
   states::{
@@ -14,10 +10,13 @@ use solana_sdk::pubkey::Pubkey;
 
 use crate::constants::BYREAL_CLMM_PROGRAM_ID;
 
-use super::types::PoolInfo;
+use super::{error::SwapComputeError, types::PoolInfo};
 
 const EXTENSION_TICKARRAY_BITMAP_SIZE: usize = 14;
 
+/// 单次 swap 计算允许的最大步数
+const MAX_SWAP_STEP_COUNT: u32 = 10;
+
 // the top level state of the swap, the results of which are recorded in storage at the end
 #[derive(Debug)]
 pub struct SwapState {
@@ -181,9 +180,9 @@ impl PoolInfo {
     sqrt_price_limit_x64: u128,
     tickarray_bitmap_extension: &TickArrayBitmapExtension,
     tick_arrays: &mut VecDeque<TickArrayState>,
-  ) -> Result<(SwapState, VecDeque<i32>)> {
+  ) -> Result<(SwapState, VecDeque<i32>), SwapComputeError> {
     if amount_specified == 0 {
-      return Err(anyhow!("amountSpecified must not be 0"));
+      return Err(SwapComputeError::InvalidAmount("amountSpecified must not be 0"));
     }
     let sqrt_price_limit_x64 = if sqrt_price_limit_x64 == 0 {
       if zero_for_one { tick_math::MIN_SQRT_PRICE_X64 + 1 } else { tick_math::MAX_SQRT_PRICE_X64 - 1 }
@@ -192,17 +191,17 @@ impl PoolInfo {
     };
     if zero_for_one {
       if sqrt_price_limit_x64 < tick_math::MIN_SQRT_PRICE_X64 {
-        return Err(anyhow!("sqrt_price_limit_x64 must greater than MIN_SQRT_PRICE_X64"));
+        return Err(SwapComputeError::PriceLimit("sqrt_price_limit_x64 must greater than MIN_SQRT_PRICE_X64"));
       }
       if sqrt_price_limit_x64 >= self.dynamic_info.sqrt_price_x64 {
-        return Err(anyhow!("sqrt_price_limit_x64 must smaller than current"));
+        return Err(SwapComputeError::PriceLimit("sqrt_price_limit_x64 must smaller than current"));
       }
     } else {
       if sqrt_price_limit_x64 > tick_math::MAX_SQRT_PRICE_X64 {
-        return Err(anyhow!("sqrt_price_limit_x64 must less than MAX_SQRT_PRICE_X64"));
+        return Err(SwapComputeError::PriceLimit("sqrt_price_limit_x64 must less than MAX_SQRT_PRICE_X64"));
       }
       if sqrt_price_limit_x64 <= self.dynamic_info.sqrt_price_x64 {
-        return Err(anyhow!("sqrt_price_limit_x64 must greater than current"));
+        return Err(SwapComputeError::PriceLimit("sqrt_price_limit_x64 must greater than current"));
       }
     }
     let mut tick_match_current_tick_array = is_pool_current_tick_array;
@@ -216,10 +215,17 @@ impl PoolInfo {
       fee_amount: 0,
     };
 
-    let mut tick_array_current = tick_arrays.pop_front().unwrap();
+    let mut tick_array_current = tick_arrays
+      .pop_front()
+      .ok_or_else(|| SwapComputeError::MissingTickArray(format!("start index {}", current_vaild_tick_array_start_index)))?;
     if tick_array_current.start_tick_index != current_vaild_tick_array_start_index {
-      return Err(anyhow!("tick array start tick index does not match"));
+      return Err(SwapComputeError::TickArrayMismatch {
+        expected: current_vaild_tick_array_start_index,
+        actual: tick_array_current.start_tick_index,
+      });
     }
+    // 每次切换到下一个 tick-array 时更新
+    let mut current_vaild_tick_array_start_index = current_vaild_tick_array_start_index;
     let mut tick_array_start_index_vec = VecDeque::new();
     tick_array_start_index_vec.push_back(tick_array_current.start_tick_index);
     let mut loop_count = 0;
@@ -230,38 +236,39 @@ impl PoolInfo {
       && state.tick > tick_math::MIN_TICK
     {
       //todo, 移除这个限制 或者 调整这个限制
-      if loop_count > 10 {
-        return Err(anyhow!("loop count exceed limit"));
+      if loop_count > MAX_SWAP_STEP_COUNT {
+        return Err(SwapComputeError::StepLimitExceeded(MAX_SWAP_STEP_COUNT));
       }
       let mut step = StepComputations::default();
       step.sqrt_price_start_x64 = state.sqrt_price_x64;
       // save the bitmap, and the tick account if it is initialized
-      let mut next_initialized_tick = if let Some(tick_state) =
-        tick_array_current.next_initialized_tick(state.tick, self.base_info.tick_spacing, zero_for_one).unwrap()
-      {
-        Box::new(*tick_state)
-      } else {
-        // todo, 这个逻辑需要仔细考量
-        if !tick_match_current_tick_array {
-          tick_match_current_tick_array = true;
-          Box::new(*tick_array_current.first_initialized_tick(zero_for_one).unwrap())
+      let mut next_initialized_tick =
+        if let Some(tick_state) = tick_array_current.next_initialized_tick(state.tick, self.base_info.tick_spacing, zero_for_one)? {
+          Box::new(*tick_state)
         } else {
-          Box::new(TickState::default())
-        }
-      };
+          // todo, 这个逻辑需要仔细考量
+          if !tick_match_current_tick_array {
+            tick_match_current_tick_array = true;
+            Box::new(*tick_array_current.first_initialized_tick(zero_for_one)?)
+          } else {
+            Box::new(TickState::default())
+          }
+        };
       if !next_initialized_tick.is_initialized() {
-        let current_vaild_tick_array_start_index = self
-          .next_initialized_tick_array_start_index(tickarray_bitmap_extension, current_vaild_tick_array_start_index, zero_for_one)
-          .unwrap();
-        tick_array_current = tick_arrays.pop_front().unwrap();
-        if current_vaild_tick_array_start_index.is_none() {
-          return Err(anyhow!("tick array start tick index out of range limit"));
-        }
-        if tick_array_current.start_tick_index != current_vaild_tick_array_start_index.unwrap() {
-          return Err(anyhow!("tick array start tick index does not match"));
+        current_vaild_tick_array_start_index = self
+          .next_initialized_tick_array_start_index(tickarray_bitmap_extension, current_vaild_tick_array_start_index, zero_for_one)?
+          .ok_or(SwapComputeError::LiquidityExhausted("no initialized tick array left in swap direction"))?;
+        tick_array_current = tick_arrays
+          .pop_front()
+          .ok_or_else(|| SwapComputeError::MissingTickArray(format!("start index {}", current_vaild_tick_array_start_index)))?;
+        if tick_array_current.start_tick_index != current_vaild_tick_array_start_index {
+          return Err(SwapComputeError::TickArrayMismatch {
+            expected: current_vaild_tick_array_start_index,
+            actual: tick_array_current.start_tick_index,
+          });
         }
         tick_array_start_index_vec.push_back(tick_array_current.start_tick_index);
-        let mut first_initialized_tick = tick_array_current.first_initialized_tick(zero_for_one).unwrap();
+        let mut first_initialized_tick = tick_array_current.first_initialized_tick(zero_for_one)?;
 
         next_initialized_tick = Box::new(*first_initialized_tick.deref_mut());
       }
@@ -273,7 +280,7 @@ impl PoolInfo {
         step.tick_next = tick_math::MAX_TICK;
       }
 
-      step.sqrt_price_next_x64 = tick_math::get_sqrt_price_at_tick(step.tick_next).unwrap();
+      step.sqrt_price_next_x64 = tick_math::get_sqrt_price_at_tick(step.tick_next)?;
 
       let target_price = if (zero_for_one && step.sqrt_price_next_x64 < sqrt_price_limit_x64)
         || (!zero_for_one && step.sqrt_price_next_x64 > sqrt_price_limit_x64)
@@ -291,21 +298,27 @@ impl PoolInfo {
         is_base_input,
         zero_for_one,
         1,
-      )
-      .unwrap();
+      )?;
       state.sqrt_price_x64 = swap_step.sqrt_price_next_x64;
-      state.fee_amount += swap_step.fee_amount;
+      state.fee_amount = state.fee_amount.checked_add(swap_step.fee_amount).ok_or(SwapComputeError::Overflow("fee_amount"))?;
 
       step.amount_in = swap_step.amount_in;
       step.amount_out = swap_step.amount_out;
       step.fee_amount = swap_step.fee_amount;
 
+      let amount_in_with_fee = step.amount_in.checked_add(step.fee_amount).ok_or(SwapComputeError::Overflow("amount_in + fee_amount"))?;
       if is_base_input {
-        state.amount_specified_remaining = state.amount_specified_remaining.checked_sub(step.amount_in + step.fee_amount).unwrap();
-        state.amount_calculated = state.amount_calculated.checked_add(step.amount_out).unwrap();
+        state.amount_specified_remaining = state
+          .amount_specified_remaining
+          .checked_sub(amount_in_with_fee)
+          .ok_or(SwapComputeError::Underflow("amount_specified_remaining"))?;
+        state.amount_calculated =
+          state.amount_calculated.checked_add(step.amount_out).ok_or(SwapComputeError::Overflow("amount_calculated"))?;
       } else {
-        state.amount_specified_remaining = state.amount_specified_remaining.checked_sub(step.amount_out).unwrap();
-        state.amount_calculated = state.amount_calculated.checked_add(step.amount_in + step.fee_amount).unwrap();
+        state.amount_specified_remaining =
+          state.amount_specified_remaining.checked_sub(step.amount_out).ok_or(SwapComputeError::Underflow("amount_specified_remaining"))?;
+        state.amount_calculated =
+          state.amount_calculated.checked_add(amount_in_with_fee).ok_or(SwapComputeError::Overflow("amount_calculated"))?;
       }
 
       if state.sqrt_price_x64 == step.sqrt_price_next_x64 {
@@ -313,15 +326,15 @@ impl PoolInfo {
         if step.initialized {
           let mut liquidity_net = next_initialized_tick.liquidity_net;
           if zero_for_one {
-            liquidity_net = liquidity_net.neg();
+            liquidity_net = liquidity_net.checked_neg().ok_or(SwapComputeError::Overflow("liquidity_net"))?;
           }
-          state.liquidity = liquidity_math::add_delta(state.liquidity, liquidity_net).unwrap();
+          state.liquidity = liquidity_math::add_delta(state.liquidity, liquidity_net)?;
         }
 
         state.tick = if zero_for_one { step.tick_next - 1 } else { step.tick_next };
       } else if state.sqrt_price_x64 != step.sqrt_price_start_x64 {
         // recompute unless we're on a lower tick boundary (i.e. already transitioned ticks), and haven't moved
-        state.tick = tick_math::get_tick_at_sqrt_price(state.sqrt_price_x64).unwrap();
+        state.tick = tick_math::get_tick_at_sqrt_price(state.sqrt_price_x64)?;
       }
       loop_count += 1;
     }
@@ -337,22 +350,28 @@ impl PoolInfo {
     .0
   }
 
-  pub fn get_tick_array_dequeue(&self, first_tick_array_start_index: i32, zero_for_one: bool) -> Result<VecDeque<TickArrayState>> {
+  pub fn get_tick_array_dequeue(
+    &self,
+    first_tick_array_start_index: i32,
+    zero_for_one: bool,
+  ) -> Result<VecDeque<TickArrayState>, SwapComputeError> {
     let mut tick_array_dequeue = VecDeque::new();
 
+    // all_tick_array_state 按 start_tick_index 升序排列
+    // zero_for_one 时价格下降，tick 向左移动
     if zero_for_one {
-      // 正序遍历 tick-array
-      for i in 0..self.dynamic_info.all_tick_array_state.len() {
+      // 反序遍历 tick-array
+      for i in (0..self.dynamic_info.all_tick_array_state.len()).rev() {
         let tick_array = &self.dynamic_info.all_tick_array_state[i];
-        if tick_array.start_tick_index >= first_tick_array_start_index {
+        if tick_array.start_tick_index <= first_tick_array_start_index {
           tick_array_dequeue.push_back(tick_array.clone());
         }
       }
     } else {
-      // 反序遍历 tick-array
-      for i in (0..self.dynamic_info.all_tick_array_state.len()).rev() {
+      // 正序遍历 tick-array
+      for i in 0..self.dynamic_info.all_tick_array_state.len() {
         let tick_array = &self.dynamic_info.all_tick_array_state[i];
-        if tick_array.start_tick_index <= first_tick_array_start_index {
+        if tick_array.start_tick_index >= first_tick_array_start_index {
           tick_array_dequeue.push_back(tick_array.clone());
         }
       }
@@ -393,10 +412,15 @@ impl PoolInfo {
     tickarray_bitmap_extension: &TickArrayBitmapExtension,
     mut last_tick_array_start_index: i32,
     zero_for_one: bool,
-  ) -> Result<Option<i32>> {
+  ) -> Result<Option<i32>, SwapComputeError> {
     last_tick_array_start_index = TickArrayState::get_array_start_index(last_tick_array_start_index, self.base_info.tick_spacing);
 
     loop {
+      // 库函数内部对非法的 start index 直接 assert，这里提前校验
+      if !TickArrayState::check_is_valid_start_index(last_tick_array_start_index, self.base_info.tick_spacing) {
+        return Err(SwapComputeError::TickMath(format!("invalid tick array start index: {}", last_tick_array_start_index)));
+      }
+
       let (is_found, start_index) = tick_array_bit_map::next_initialized_tick_array_start_index(
         U1024(self.dynamic_info.tick_array_bitmap),
         last_tick_array_start_index,
@@ -428,7 +452,7 @@ impl PoolInfo {
     &self,
     tickarray_bitmap_extension: &TickArrayBitmapExtension,
     zero_for_one: bool,
-  ) -> Result<(bool, i32)> {
+  ) -> Result<(bool, i32), SwapComputeError> {
     let (is_initialized, start_index) = if self.is_overflow_default_tickarray_bitmap(vec![self.dynamic_info.tick_current]) {
       tickarray_bitmap_extension.check_tick_array_is_initialized(
         TickArrayState::get_array_start_index(self.dynamic_info.tick_current, self.base_info.tick_spacing),
@@ -449,8 +473,7 @@ impl PoolInfo {
       TickArrayState::get_array_start_index(self.dynamic_info.tick_current, self.base_info.tick_spacing),
       zero_for_one,
     )?;
-    //todo, 返回error
-    // require!(next_start_index.is_some(), ErrorCode::InsufficientLiquidityForDirection);
-    return Ok((false, next_start_index.unwrap()));
+    let next_start_index = next_start_index.ok_or(SwapComputeError::LiquidityExhausted("no initialized tick array in swap direction"))?;
+    return Ok((false, next_start_index));
   }
 }
@@ -1 +1,191 @@
+use std::{
+  collections::HashMap,
+  fmt::Debug,
+  time::{Duration, Instant},
+};
 
+use hmac::{Hmac, Mac};
+use prost::Message;
+use sha2::Sha256;
+use tokio::sync::RwLock;
+
+use super::{clmm_pool_utils, error::QuoteError};
+use crate::service::{
+  core::platform_fee::{FeeSide, PlatformFeeConfig},
+  pb::router::SwapV1Out,
+};
+
+/// 按 400ms 一个 slot 估算时间
+pub const MS_PER_SLOT: u64 = 400;
+
+/// 按滑点计算阈值：指定输入时为最少收到的输出数量，指定输出时为最多支付的输入数量
+pub fn other_amount_threshold(base_input: bool, input_amount: u64, output_amount: u64, slippage_bps: u64) -> u64 {
+  if base_input {
+    (u128::from(output_amount) * u128::from(10_000 - slippage_bps) / 10_000) as u64
+  } else {
+    (u128::from(input_amount) * u128::from(10_000 + slippage_bps)).div_ceil(10_000).min(u128::from(u64::MAX)) as u64
+  }
+}
+
+/// 询价结果的有效期
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QuoteExpiry {
+  /// 询价时的 slot
+  pub context_slot: u64,
+  /// 从该 slot 开始询价结果失效
+  pub expire_slot: u64,
+}
+
+impl QuoteExpiry {
+  /// 有效期取 slot-age TTL 和路由上 transfer-fee 切换费率的 slot 中较早的那个
+  pub fn new(context_slot: u64, ttl_slots: u64, fee_expiration_slot: Option<u64>) -> Self {
+    let ttl_expire_slot = context_slot.saturating_add(ttl_slots);
+    let expire_slot = clmm_pool_utils::earliest_slot(Some(ttl_expire_slot), fee_expiration_slot).unwrap_or(ttl_expire_slot);
+    Self { context_slot, expire_slot }
+  }
+
+  /// 预估的有效时长
+  pub fn ttl(&self) -> Duration {
+    Duration::from_millis(self.expire_slot.saturating_sub(self.context_slot).saturating_mul(MS_PER_SLOT))
+  }
+
+  /// 预估的失效时间，毫秒时间戳
+  pub fn estimated_expire_time(&self, now_ms: u64) -> u64 {
+    now_ms.saturating_add(self.ttl().as_millis() as u64)
+  }
+}
+
+/// 检查询价结果在 current_slot 是否仍然有效
+pub fn check_quote_expiry(expire_slot: u64, current_slot: u64) -> Result<(), QuoteError> {
+  if expire_slot == 0 {
+    return Err(QuoteError::MissingExpiry);
+  }
+  if current_slot >= expire_slot {
+    return Err(QuoteError::Expired { expire_slot, current_slot });
+  }
+  Ok(())
+}
+
+/// 服务端保存的询价结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredQuote {
+  pub quote: SwapV1Out,
+  /// HMAC-SHA256 签名，未配置签名密钥时为空
+  pub signature: Vec<u8>,
+}
+
+/// 询价结果的存储
+/// 默认使用进程内存储，多实例部署时替换为共享的存储（如 redis）
+#[tonic::async_trait]
+pub trait QuoteStore: Debug + Send + Sync {
+  /// 保存询价结果，超过 ttl 后不再返回
+  async fn put(&self, quote_id: &str, quote: StoredQuote, ttl: Duration) -> anyhow::Result<()>;
+
+  /// 读取询价结果，不存在或已超过 ttl 时返回 None
+  async fn get(&self, quote_id: &str) -> anyhow::Result<Option<StoredQuote>>;
+}
+
+/// 进程内的询价结果存储
+#[derive(Debug, Default)]
+pub struct InMemoryQuoteStore {
+  quotes: RwLock<HashMap<String, (StoredQuote, Instant)>>,
+}
+
+#[tonic::async_trait]
+impl QuoteStore for InMemoryQuoteStore {
+  async fn put(&self, quote_id: &str, quote: StoredQuote, ttl: Duration) -> anyhow::Result<()> {
+    let now = Instant::now();
+    let mut quotes = self.quotes.write().await;
+    // 写入时顺便清理过期的询价结果
+    quotes.retain(|_, (_, expire_at)| *expire_at > now);
+    quotes.insert(quote_id.to_string(), (quote, now + ttl));
+    Ok(())
+  }
+
+  async fn get(&self, quote_id: &str) -> anyhow::Result<Option<StoredQuote>> {
+    let quotes = self.quotes.read().await;
+    Ok(quotes.get(quote_id).filter(|(_, expire_at)| *expire_at > Instant::now()).map(|(quote, _)| quote.clone()))
+  }
+}
+
+/// 生成新的询价 id
+pub fn new_quote_id() -> String {
+  hex::encode(rand::random::<[u8; 16]>())
+}
+
+/// 询价结果的 HMAC-SHA256
+fn quote_mac(secret: &str, quote: &SwapV1Out) -> Hmac<Sha256> {
+  // HMAC 接受任意长度的密钥，不会失败
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+  mac.update(&quote.encode_to_vec());
+  mac
+}
+
+/// 签名后保存询价结果，使用 quote.quote_id 作为 key
+pub async fn save_quote(store: &dyn QuoteStore, secret: &str, quote: SwapV1Out, ttl: Duration) -> anyhow::Result<()> {
+  let quote_id = quote.quote_id.clone();
+  // 未配置签名密钥时不签名
+  let signature = if secret.is_empty() { Vec::new() } else { quote_mac(secret, &quote).finalize().into_bytes().to_vec() };
+  store.put(&quote_id, StoredQuote { quote, signature }, ttl).await
+}
+
+/// 读取询价结果并校验签名
+pub async fn load_quote(store: &dyn QuoteStore, secret: &str, quote_id: &str) -> anyhow::Result<SwapV1Out> {
+  let stored = store.get(quote_id).await?.ok_or_else(|| QuoteError::NotFound(quote_id.to_string()))?;
+  if stored.quote.quote_id != quote_id {
+    return Err(QuoteError::Tampered(quote_id.to_string()).into());
+  }
+
+  if !secret.is_empty() {
+    quote_mac(secret, &stored.quote).verify_slice(&stored.signature).map_err(|_| QuoteError::Tampered(quote_id.to_string()))?;
+  }
+
+  Ok(stored.quote)
+}
+
+/// 构建交易使用的询价结果
+/// 使用服务端保存的询价结果，客户端回传的询价结果必须与之一致；
+/// 客户端回传的询价结果可以修改 expire_slot 和阈值，只有 accept_client_quotes 开启且未配置签名密钥时才直接使用
+pub async fn resolve_quote(
+  store: &dyn QuoteStore,
+  secret: &str,
+  accept_client_quotes: bool,
+  quote_id: &str,
+  swap_response: Option<&SwapV1Out>,
+) -> anyhow::Result<SwapV1Out> {
+  if quote_id.is_empty() {
+    if !secret.is_empty() {
+      return Err(QuoteError::QuoteIdRequired("quote signing is enabled").into());
+    }
+    if !accept_client_quotes {
+      return Err(QuoteError::QuoteIdRequired("client quotes are not accepted").into());
+    }
+    return swap_response.cloned().ok_or(anyhow::anyhow!("Swap response is missing"));
+  }
+  let stored_quote = load_quote(store, secret, quote_id).await?;
+  if swap_response.is_some_and(|swap_response| *swap_response != stored_quote) {
+    return Err(QuoteError::Tampered(quote_id.to_string()).into());
+  }
+  Ok(stored_quote)
+}
+
+/// 构建交易时收取的平台费：扣费方向和金额
+/// 配置了平台费时只接受服务端保存的询价结果（from_store），客户端回传的询价结果可以去掉平台费；
+/// 未配置平台费时不收取，忽略询价结果中的平台费
+pub fn quote_platform_fee(config: Option<&PlatformFeeConfig>, quote: &SwapV1Out, from_store: bool) -> anyhow::Result<(FeeSide, u64)> {
+  if config.is_none() {
+    return Ok((FeeSide::default(), 0));
+  }
+  if !from_store {
+    return Err(QuoteError::QuoteIdRequired("platform fee is enabled").into());
+  }
+  let fee_amount: u64 = if quote.platform_fee_amount.is_empty() { 0 } else { quote.platform_fee_amount.parse()? };
+  // 询价时的扣费方向，配置在询价之后变化时仍按询价结果收取
+  let fee_side = match quote.platform_fee_mint.as_str() {
+    _ if fee_amount == 0 => FeeSide::default(),
+    mint if mint == quote.input_mint => FeeSide::Input,
+    mint if mint == quote.output_mint => FeeSide::Output,
+    mint => return Err(anyhow::anyhow!("Platform fee mint {} is neither the input nor the output mint", mint)),
+  };
+  Ok((fee_side, fee_amount))
+}
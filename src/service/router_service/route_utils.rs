@@ -15,14 +15,19 @@ use solana_sdk::{epoch_info::EpochInfo, pubkey::Pubkey};
 use crate::{
   constants::{BYREAL_CLMM_PROGRAM_ID, SOL_MINT, WSOL_MINT},
   service::{
-    core::{account_puller::AccountPuller, types::MintAccountBaseInfo},
+    core::{
+      account_puller::AccountPuller,
+      cu_model::{HopCuFeatures, RouteCuFeatures},
+      types::MintAccountBaseInfo,
+    },
     pb::{base, router::swap_v1_out::RoutePlan},
   },
 };
 
 use super::{
   clmm_pool_utils::{self, OneStepSwapResult},
-  types::{AllRoutePathInfo, POOL_VERSION_CLMM, PoolBaseInfo, PoolDynamicInfo, PoolInfo, RoutePathItem},
+  error::SwapComputeError,
+  types::{AllRoutePathInfo, POOL_VERSION_CLMM, PoolBaseInfo, PoolDynamicInfo, PoolInfo, RouteConstraints, RoutePathItem},
 };
 use crate::service::router_service::types::{OutputTickLiquidityInfo, TICK_ARRAY_SIZE, TickLiquidityInfo};
 use raydium_amm_v3::states::{AmmConfig, PoolState, TickArrayBitmapExtension, TickArrayState};
@@ -97,9 +102,9 @@ pub async fn fetch_all_clmm_pools(rpc_client: &RpcClient) -> Result<Vec<PoolInfo
     let tick_arrays = account_puller
       .get_multi_account_data::<TickArrayState>(&tick_array_keys)
       .await?
-      .iter()
-      .map(|(_, data)| data.unwrap())
-      .collect::<Vec<_>>();
+      .into_iter()
+      .map(|(key, data)| data.ok_or_else(|| SwapComputeError::MissingTickArray(key.to_string())))
+      .collect::<Result<Vec<_>, _>>()?;
 
     let dynamic_info = PoolDynamicInfo {
       id: pool_id.clone(),
@@ -122,44 +127,63 @@ pub async fn fetch_all_clmm_pools(rpc_client: &RpcClient) -> Result<Vec<PoolInfo
 
 /// 计算所有的路由路径
 /// 参数校验在外部进行
-pub async fn get_all_route_path(input_mint: &Pubkey, output_mint: &Pubkey, clmm_pools: &Vec<PoolInfo>) -> Result<AllRoutePathInfo> {
+pub async fn get_all_route_path(
+  input_mint: &Pubkey,
+  output_mint: &Pubkey,
+  clmm_pools: &Vec<PoolInfo>,
+  constraints: &RouteConstraints,
+) -> Result<AllRoutePathInfo> {
   // 转换 input_mint 和 output_mint 为 Pubkey(同时将SOL换为WSOL)
   let input_mint = if input_mint.eq(&SOL_MINT) { WSOL_MINT.clone() } else { input_mint.clone() };
   let output_mint = if output_mint.eq(&SOL_MINT) { WSOL_MINT.clone() } else { output_mint.clone() };
 
   let mut direct_path = Vec::new();
   let mut route_path_map = HashMap::new();
+  let allow_indirect_routes = constraints.allow_indirect_routes();
 
   // 遍历所有的池，计算路由路径
   for pool in clmm_pools {
+    if !constraints.is_pool_allowed(pool) {
+      continue;
+    }
+
     if pool.base_info.mint_a_info.mint.eq(&input_mint) && pool.base_info.mint_b_info.mint.eq(&output_mint) {
       // 直接路由
       direct_path.push(pool.clone());
     } else if pool.base_info.mint_a_info.mint.eq(&output_mint) && pool.base_info.mint_b_info.mint.eq(&input_mint) {
       // 直接路由
       direct_path.push(pool.clone());
-    } else {
-      // 间接路由
-      if pool.base_info.mint_a_info.mint.eq(&input_mint) {
-        let route_path_item: &mut RoutePathItem = route_path_map.entry(pool.base_info.mint_b_info.mint.to_string()).or_default();
-        route_path_item.route_mint = pool.base_info.mint_b_info.clone();
-        route_path_item.input_mint_pools.push(pool.clone());
+    } else if allow_indirect_routes {
+      // 间接路由, 中转代币需要满足约束（hub 代币，未被排除）
+      let (route_mint_info, is_input_side) = if pool.base_info.mint_a_info.mint.eq(&input_mint) {
+        (&pool.base_info.mint_b_info, true)
       } else if pool.base_info.mint_b_info.mint.eq(&input_mint) {
-        let route_path_item: &mut RoutePathItem = route_path_map.entry(pool.base_info.mint_a_info.mint.to_string()).or_default();
-        route_path_item.route_mint = pool.base_info.mint_a_info.clone();
-        route_path_item.input_mint_pools.push(pool.clone());
+        (&pool.base_info.mint_a_info, true)
       } else if pool.base_info.mint_a_info.mint.eq(&output_mint) {
-        let route_path_item: &mut RoutePathItem = route_path_map.entry(pool.base_info.mint_b_info.mint.to_string()).or_default();
-        route_path_item.route_mint = pool.base_info.mint_b_info.clone();
-        route_path_item.output_mint_pools.push(pool.clone());
+        (&pool.base_info.mint_b_info, false)
       } else if pool.base_info.mint_b_info.mint.eq(&output_mint) {
-        let route_path_item: &mut RoutePathItem = route_path_map.entry(pool.base_info.mint_a_info.mint.to_string()).or_default();
-        route_path_item.route_mint = pool.base_info.mint_a_info.clone();
+        (&pool.base_info.mint_a_info, false)
+      } else {
+        continue;
+      };
+
+      if !constraints.is_route_mint_allowed(&route_mint_info.mint) {
+        continue;
+      }
+
+      let route_path_item: &mut RoutePathItem = route_path_map.entry(route_mint_info.mint.to_string()).or_default();
+      route_path_item.route_mint = route_mint_info.clone();
+      if is_input_side {
+        route_path_item.input_mint_pools.push(pool.clone());
+      } else {
         route_path_item.output_mint_pools.push(pool.clone());
       }
     }
   }
 
+  // 只有一侧有池子的中转代币无法构成路由，直接去掉
+  route_path_map.retain(|_, item: &mut RoutePathItem| !item.input_mint_pools.is_empty() && !item.output_mint_pools.is_empty());
+
   Ok(AllRoutePathInfo { direct_paths: direct_path, route_paths_map: route_path_map })
 }
 
@@ -224,7 +248,9 @@ pub async fn compute_one_hop_indirect_route_base_input(
       let pool_1 = &route.input_mint_pools[first_idx];
       let result_1 = clmm_pool_utils::compute_another_amount(pool_1, &input_mint, true, input_amount, epoch_info, None)?;
 
-      assert_eq!(result_1.output_mint, route.route_mint.mint);
+      if result_1.output_mint != route.route_mint.mint {
+        return Err(anyhow::anyhow!("Route mint mismatch, expected {}, got {}", route.route_mint.mint, result_1.output_mint));
+      }
 
       let pool_2 = &route.output_mint_pools[second_idx];
       let result_2 =
@@ -383,6 +409,33 @@ impl RouteInformationType {
     }
   }
 
+  /// 路由上 transfer-fee 最早切换费率的 slot，从该 slot 开始询价结果失效
+  pub fn get_expiration_slot(&self) -> Option<u64> {
+    match self {
+      RouteInformationType::DirectRoute { swap_result, .. } => swap_result.expiration_slot,
+      RouteInformationType::OneHopIndirectRoute { swap_results, .. } => {
+        clmm_pool_utils::earliest_slot(swap_results[0].expiration_slot, swap_results[1].expiration_slot)
+      }
+    }
+  }
+
+  /// 路由上每一跳影响 cu 的特征，询价时没有附加指令
+  pub fn get_cu_features(&self) -> RouteCuFeatures {
+    let hop = |pool: &PoolInfo, swap_result: &OneStepSwapResult| {
+      HopCuFeatures::new(
+        swap_result.tick_array_keys.len(),
+        [&pool.base_info.mint_a_info.program_id, &pool.base_info.mint_b_info.program_id],
+      )
+    };
+    let hops = match self {
+      RouteInformationType::DirectRoute { pool, swap_result } => vec![hop(pool, swap_result)],
+      RouteInformationType::OneHopIndirectRoute { pools, swap_results } => {
+        vec![hop(&pools[0], &swap_results[0]), hop(&pools[1], &swap_results[1])]
+      }
+    };
+    RouteCuFeatures { hops, ..Default::default() }
+  }
+
   pub fn into_route_plan_vec(&self) -> Vec<RoutePlan> {
     match self {
       RouteInformationType::DirectRoute { pool, swap_result } => {
@@ -472,17 +525,17 @@ pub async fn get_pool_tick_liquidity<'a>(account_puller: &'a AccountPuller<'a>,
   let tick_liquidity_list = account_puller
     .get_multi_account_data::<TickArrayState>(&tick_array_keys)
     .await?
-    .iter()
-    .map(|(_, data)| Box::new(data.unwrap())) // 将TickArrayState包装在Box中
-    .collect::<Vec<_>>();
+    .into_iter()
+    .map(|(key, data)| data.map(Box::new).ok_or_else(|| SwapComputeError::MissingTickArray(key.to_string()))) // 将TickArrayState包装在Box中
+    .collect::<Result<Vec<_>, _>>()?;
 
-  let tick_liquidity_infos = generate_tick_liquidity_info(tick_liquidity_list);
+  let tick_liquidity_infos = generate_tick_liquidity_info(tick_liquidity_list)?;
 
   let mut output_tick_liquidity_infos: Vec<OutputTickLiquidityInfo> = Vec::new();
 
   for tick_liquidity_info in tick_liquidity_infos.iter() {
     let sqrt_price = tick_math::get_sqrt_price_at_tick(tick_liquidity_info.tick_index)?;
-    let tick_price = calculate_price(sqrt_price, pool_account_data.mint_decimals_0, pool_account_data.mint_decimals_1);
+    let tick_price = calculate_price(sqrt_price, pool_account_data.mint_decimals_0, pool_account_data.mint_decimals_1)?;
 
     output_tick_liquidity_infos.push(OutputTickLiquidityInfo {
       tick_index: tick_liquidity_info.tick_index,
@@ -495,7 +548,9 @@ pub async fn get_pool_tick_liquidity<'a>(account_puller: &'a AccountPuller<'a>,
 
 /// 生成每个 tick 的流动性信息
 ///
-pub fn generate_tick_liquidity_info(tick_array_accounts: Vec<Box<TickArrayState>>) -> Vec<Box<TickLiquidityInfo>> {
+pub fn generate_tick_liquidity_info(
+  tick_array_accounts: Vec<Box<TickArrayState>>,
+) -> Result<Vec<Box<TickLiquidityInfo>>, SwapComputeError> {
   let mut tick_liquidity_infos: Vec<Box<TickLiquidityInfo>> = Vec::new();
 
   // 首先遍历每个 tick_array_account,
@@ -511,8 +566,7 @@ pub fn generate_tick_liquidity_info(tick_array_accounts: Vec<Box<TickArrayState>
 
     // 检查 start_tick_index 是否严格递增
     if tick_array_account.start_tick_index <= last_tick_index {
-      // println!("LastTickIndex: {}, CurrentTickArrayAccount: {:?}", last_tick_index, tick_array_account);
-      panic!("Start tick index not match");
+      return Err(SwapComputeError::InvalidTickArrayOrder { last: last_tick_index, current: tick_array_account.start_tick_index });
     }
     last_tick_index = tick_array_account.start_tick_index;
 
@@ -525,7 +579,7 @@ pub fn generate_tick_liquidity_info(tick_array_accounts: Vec<Box<TickArrayState>
       }
       // 从左向右穿过该tick的流动性
       let pre_tick_liquidity = cur_liquidity;
-      cur_liquidity += tick_state.liquidity_net;
+      cur_liquidity = cur_liquidity.checked_add(tick_state.liquidity_net).ok_or(SwapComputeError::Overflow("tick liquidity"))?;
       if cur_liquidity != 0 {
         tick_liquidity_infos.push(Box::new(TickLiquidityInfo { tick_index: tick_state.tick, liquidity: cur_liquidity as u128 }));
       }
@@ -537,16 +591,17 @@ pub fn generate_tick_liquidity_info(tick_array_accounts: Vec<Box<TickArrayState>
     }
   }
 
-  tick_liquidity_infos
+  Ok(tick_liquidity_infos)
 }
 
 /// 计算价格，计算价格时需要
-fn calculate_price(sqrt_price_x64: u128, token0_decimals: u8, token1_decimals: u8) -> Decimal {
-  let denom = Decimal::from_u128(1_u128 << 64).unwrap();
-  let sqrt_price = Decimal::from_u128(sqrt_price_x64).unwrap().checked_div(denom).unwrap();
+fn calculate_price(sqrt_price_x64: u128, token0_decimals: u8, token1_decimals: u8) -> Result<Decimal, SwapComputeError> {
+  let denom = Decimal::from_u128(1_u128 << 64).ok_or(SwapComputeError::Overflow("price denominator"))?;
+  let sqrt_price = Decimal::from_u128(sqrt_price_x64).and_then(|x| x.checked_div(denom)).ok_or(SwapComputeError::Overflow("sqrt price"))?;
 
-  let price0 = sqrt_price.powu(2);
-  let exponent = Decimal::from(10).powi(token0_decimals as i64 - token1_decimals as i64);
+  let price0 = sqrt_price.checked_powu(2).ok_or(SwapComputeError::Overflow("price"))?;
+  let exponent =
+    Decimal::from(10).checked_powi(token0_decimals as i64 - token1_decimals as i64).ok_or(SwapComputeError::Overflow("price"))?;
 
-  price0.checked_mul(exponent).unwrap()
+  price0.checked_mul(exponent).ok_or(SwapComputeError::Overflow("price"))
 }
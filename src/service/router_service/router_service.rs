@@ -1,34 +1,80 @@
+use std::collections::HashMap;
 use std::i32;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use crate::constants::WSOL_MINT;
 use crate::nacos_config::entrance::get_nacos_config;
 use crate::service::core::account_puller::{self, AccountPuller};
-use crate::service::core::clmm_program::{self, SwapRouteInfo};
+use crate::service::core::build_tx::{MessageVersion, TransactionBuilder};
+use crate::service::core::clmm_program::{self, ExtraInstructions, SwapRouteInfo, SwapTxOptions};
+use crate::service::core::cu_model::{self, HopCuFeatures, RouteCuFeatures};
+use crate::service::core::lookup_table;
+use crate::service::core::nonce;
+use crate::service::core::platform_fee::{self, FeeSide, PlatformFeeAmounts};
+use crate::service::core::preflight::{self, SolRequirement, TokenAccountCheck};
+use crate::service::core::priority_fee;
 use crate::service::core::result_utils::convert_result;
+use crate::service::core::simulation;
+use crate::service::core::sponsor::{self, SponsorLimits};
+use crate::service::core::tip;
+use crate::service::core::token_account;
+use crate::service::core::types::MintAccountBaseInfo;
 use crate::service::pb::base::CommonResult;
 use crate::service::pb::router::router_service_server::RouterService;
 use crate::service::pb::router::{
-  CreateSwapTransactionRequest, CreateSwapTransactionResponse, QuotePriceRequest, QuotePriceResponse, SwapV1Out, TransactionData,
+  AccountMetaInfo, CreateSwapTransactionRequest, CreateSwapTransactionResponse, GetSwapInstructionsResponse, InstructionInfo,
+  QuotePriceRequest, QuotePriceResponse, SimulateSwapResponse, SimulationError, SwapV1Out, TokenBalanceChange, TransactionData, TxVersion,
   swap_v1_out::RoutePlan, swap_v1_out::SwapType,
 };
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
 use raydium_amm_v3::states::{PoolState, tick_array};
-use solana_sdk::pubkey::Pubkey;
-use spl_associated_token_account::get_associated_token_address;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
 use tonic::{Request, Response, Status};
 
+use super::error::SponsorError;
+use super::quote::{self, InMemoryQuoteStore, QuoteExpiry, QuoteStore};
 use super::route_utils::{self, RouteInformationType};
-use super::types::PoolInfo;
+use super::types::{PoolInfo, RouteConstraints};
+
+/// 构建 swap 交易需要的指令和参数
+struct PreparedSwap {
+  payer: Pubkey,
+  /// 交易的手续费支付者，代付时为代付账户，否则为钱包
+  fee_payer: Pubkey,
+  /// 代付时的限制和代付账户需要支付的 SOL，构建交易后按实际的 cu limit 再次检查
+  sponsor_cost: Option<(SponsorLimits, SolRequirement)>,
+  swap_ix: Instruction,
+  extra_instructions: ExtraInstructions,
+  tx_options: SwapTxOptions,
+  /// 新建 ATA 需要的租金
+  ata_rent_lamports: u64,
+  tip_account: Option<Pubkey>,
+  /// 打包(bundle)时放在单独交易中的小费指令
+  bundle_tip_ix: Option<Instruction>,
+  /// 钱包在 swap 中涉及的 token 账户及其代币，模拟执行时统计余额变化
+  token_accounts: Vec<(Pubkey, Pubkey)>,
+}
 
-#[derive(Debug, Default)]
-pub struct DexRouterService {}
+#[derive(Debug)]
+pub struct DexRouterService {
+  /// 询价结果的存储，构建交易时根据 quote_id 读取
+  quote_store: Arc<dyn QuoteStore>,
+}
+
+impl Default for DexRouterService {
+  fn default() -> Self {
+    Self::new(Arc::new(InMemoryQuoteStore::default()))
+  }
+}
 
 #[tonic::async_trait]
 impl RouterService for DexRouterService {
   /// 询价服务
   async fn quote_price(&self, request: Request<QuotePriceRequest>) -> Result<Response<QuotePriceResponse>, Status> {
-    convert_result(Self::quote_price_impl(request.into_inner()).await)
+    convert_result(self.quote_price_impl(request.into_inner()).await)
   }
 
   /// 构建交易：生成用于交换的交易数据
@@ -36,18 +82,38 @@ impl RouterService for DexRouterService {
     &self,
     request: Request<CreateSwapTransactionRequest>,
   ) -> Result<Response<CreateSwapTransactionResponse>, Status> {
-    convert_result(Self::create_swap_transaction_impl(request.into_inner()).await)
+    convert_result(self.create_swap_transaction_impl(request.into_inner()).await)
+  }
+
+  /// 构建指令：返回 swap 交易的各部分指令，由调用方自行组装交易
+  async fn get_swap_instructions(
+    &self,
+    request: Request<CreateSwapTransactionRequest>,
+  ) -> Result<Response<GetSwapInstructionsResponse>, Status> {
+    convert_result(self.get_swap_instructions_impl(request.into_inner()).await)
+  }
+
+  /// 模拟执行：构建交易并模拟执行，返回日志、cu 消耗和余额变化
+  async fn simulate_swap(&self, request: Request<CreateSwapTransactionRequest>) -> Result<Response<SimulateSwapResponse>, Status> {
+    convert_result(self.simulate_swap_impl(request.into_inner()).await)
   }
 }
 
 impl DexRouterService {
-  pub async fn quote_price_impl(req: QuotePriceRequest) -> core::result::Result<QuotePriceResponse, anyhow::Error> {
-    let rpc_client = get_nacos_config().await.get_rand_rpc();
+  /// 使用指定的询价结果存储，多实例部署时传入共享的存储
+  pub fn new(quote_store: Arc<dyn QuoteStore>) -> Self {
+    Self { quote_store }
+  }
+
+  pub async fn quote_price_impl(&self, req: QuotePriceRequest) -> core::result::Result<QuotePriceResponse, anyhow::Error> {
+    let nacos_config = get_nacos_config().await;
+    let rpc_client = nacos_config.get_rand_rpc();
 
     let input_mint = Pubkey::from_str(&req.input_mint)?;
     let output_mint = Pubkey::from_str(&req.output_mint)?;
     let amount: u64 = req.amount.parse()?;
     let base_input = req.is_base_input;
+    let route_constraints = RouteConstraints::new(&req, &nacos_config.get_hub_mints()?)?;
 
     // todo: 临时加速的话，可以先在启动时获取，但会导致信息不准
     // todo: 池子信息从 redis 中获取
@@ -58,186 +124,558 @@ impl DexRouterService {
       println!("Pool: {}, {}", pool.base_info.mint_a_info.mint.to_string(), pool.base_info.mint_b_info.mint.to_string());
     }
     let epoch_info = rpc_client.get_epoch_info().await?;
-    let all_route_paths = route_utils::get_all_route_path(&input_mint, &output_mint, &pool_infos).await?;
+    let all_route_paths = route_utils::get_all_route_path(&input_mint, &output_mint, &pool_infos, &route_constraints).await?;
     println!("All Route Path: {}", &all_route_paths);
 
     if all_route_paths.is_empty() {
       return Err(anyhow::anyhow!("No route found"));
     }
 
-    let best_route = route_utils::compute_best_route(all_route_paths, &input_mint, base_input, amount, &epoch_info).await?;
+    // 平台费从输入扣除（指定输入）或从输出扣除（指定输出）时，路由计算使用扣除/加上平台费后的数量
+    let platform_fee = nacos_config.get_platform_fee_config()?;
+    let route_amount = platform_fee.map_or(amount, |platform_fee| platform_fee.route_amount(amount, base_input));
+    let best_route = route_utils::compute_best_route(all_route_paths, &input_mint, base_input, route_amount, &epoch_info).await?;
     println!("Best route: {}", &best_route);
+    let fee_amounts = match &platform_fee {
+      Some(platform_fee) => platform_fee.quote_amounts(amount, base_input, best_route.get_amount_in(), best_route.get_amount_out()),
+      None => PlatformFeeAmounts { input_amount: best_route.get_amount_in(), output_amount: best_route.get_amount_out(), fee_amount: 0 },
+    };
+    let platform_fee_mint = match platform_fee.map(|platform_fee| platform_fee.side) {
+      Some(FeeSide::Input) => best_route.get_input_mint().to_string(),
+      Some(FeeSide::Output) => best_route.get_output_mint().to_string(),
+      None => String::new(),
+    };
+    let slippage_bps = u64::try_from(req.slippage_bps)
+      .ok()
+      .filter(|slippage_bps| *slippage_bps <= platform_fee::BPS_DENOMINATOR)
+      .ok_or(anyhow::anyhow!("Invalid slippage_bps {}", req.slippage_bps))?;
+    let other_amount_threshold =
+      quote::other_amount_threshold(base_input, fee_amounts.input_amount, fee_amounts.output_amount, slippage_bps);
+    // 没有最少输出的 swap 不受滑点保护，CheckTx 也会拒绝这样的交易
+    if base_input && other_amount_threshold == 0 {
+      return Err(anyhow::anyhow!("slippage_bps {} leaves no minimum output amount", slippage_bps));
+    }
+    let quote_expiry = QuoteExpiry::new(epoch_info.absolute_slot, nacos_config.get_quote_ttl_slots(), best_route.get_expiration_slot());
+    let swap_out = SwapV1Out {
+      swap_type: (if base_input { SwapType::BaseInUnspecified } else { SwapType::BaseOutUnspecified }) as i32,
+      input_mint: best_route.get_input_mint().to_string(),
+      input_amount: fee_amounts.input_amount.to_string(),
+      output_mint: best_route.get_output_mint().to_string(),
+      output_amount: fee_amounts.output_amount.to_string(),
+      // 指定输入时为最少收到的输出数量，指定输出时为最多支付的输入数量
+      other_amount_threshold: other_amount_threshold.to_string(),
+      slippage_bps: req.slippage_bps,
+      // todo: 导致的价格变化，如果多路径时，如何定义这个参数
+      price_impact_pct: 5,
+      route_plan: best_route.into_route_plan_vec(),
+      context_slot: quote_expiry.context_slot,
+      expire_slot: quote_expiry.expire_slot,
+      expire_time: quote_expiry.estimated_expire_time(chrono::Utc::now().timestamp_millis() as u64),
+      quote_id: quote::new_quote_id(),
+      estimated_compute_units: cu_model::estimate_cu(&best_route.get_cu_features()).await.units,
+      platform_fee_amount: fee_amounts.fee_amount.to_string(),
+      platform_fee_bps: platform_fee.map_or(0, |platform_fee| u32::from(platform_fee.fee_bps)),
+      platform_fee_mint,
+    };
+    quote::save_quote(self.quote_store.as_ref(), &nacos_config.quote_hmac_secret, swap_out.clone(), quote_expiry.ttl()).await?;
+
     let rsp = QuotePriceResponse {
       result: Some(CommonResult { ret_code: 0, ret_msg: "Quote price retrieved successfully".to_string() }),
       version: "V0".to_string(),
       open_time: best_route.get_pool_open_time(), // 示例时间戳
-      data: Some(SwapV1Out {
-        swap_type: SwapType::BaseInUnspecified as i32,
-        input_mint: best_route.get_input_mint().to_string(),
-        input_amount: best_route.get_amount_in().to_string(),
-        output_mint: best_route.get_output_mint().to_string(),
-        output_amount: best_route.get_amount_out().to_string(),
-        // todo, 这个字段的意义
-        other_amount_threshold: "10".to_string(),
-        // todo: 好像没意义
-        slippage_bps: 50, // 0.5%
-        // todo: 导致的价格变化，如果多路径时，如何定义这个参数
-        price_impact_pct: 5,
-        route_plan: best_route.into_route_plan_vec(),
-      }),
+      data: Some(swap_out),
     };
 
     Ok(rsp)
   }
 
   pub async fn create_swap_transaction_impl(
+    &self,
     req: CreateSwapTransactionRequest,
   ) -> core::result::Result<CreateSwapTransactionResponse, anyhow::Error> {
-    let rpc_client = get_nacos_config().await.get_rand_rpc();
-    let account_puller = AccountPuller::new(&rpc_client);
+    let prepared = self.prepare_swap(&req, true).await?;
+    let payer = prepared.payer;
+    let message_version = prepared.tx_options.message_version;
+
+    // 代付时交易已由代付账户部分签名，钱包签名后即可提交
+    let vtx =
+      clmm_program::build_swap_tx(&prepared.fee_payer, prepared.swap_ix, &prepared.extra_instructions, &prepared.tx_options).await?;
+    // 模拟得到的 cu limit 可能高于估算值，超过代付限制时丢弃已签名的交易
+    if let Some((limits, cost)) = &prepared.sponsor_cost {
+      let compute_unit_limit = sponsor::compute_unit_limit(&vtx.message).unwrap_or(cost.compute_unit_limit);
+      limits.check(&SolRequirement { compute_unit_limit, ..*cost })?;
+    }
+    let mut data = vec![TransactionData { transaction: BASE64_STANDARD.encode(bincode::serialize(&vtx)?) }];
+    if let Some(tip_ix) = prepared.bundle_tip_ix {
+      // 小费交易与 swap 交易使用相同的 blockhash，两者同时过期
+      let tip_tx = TransactionBuilder::build_versioned_transaction_sync(
+        *vtx.message.recent_blockhash(),
+        &payer,
+        &[tip_ix],
+        &[],
+        &vec![],
+        message_version,
+      )?;
+      data.push(TransactionData { transaction: BASE64_STANDARD.encode(bincode::serialize(&tip_tx)?) });
+    }
 
-    println!("req: {}", serde_json::to_string_pretty(&req)?);
+    // 这里仅作为示例，返回一个默认的响应
+    let response = CreateSwapTransactionResponse {
+      result: Some(CommonResult { ret_code: 0, ret_msg: "Swap transaction created successfully".to_string() }),
+      data,
+      ata_rent_lamports: prepared.ata_rent_lamports,
+      address_lookup_tables: vtx
+        .message
+        .address_table_lookups()
+        .map(|lookups| lookups.iter().map(|lookup| lookup.account_key.to_string()).collect())
+        .unwrap_or_default(),
+      compute_unit_price_micro_lamports: prepared.tx_options.cu_price,
+      tip_account: prepared.tip_account.map(|tip_account| tip_account.to_string()).unwrap_or_default(),
+      fee_payer: prepared.fee_payer.to_string(),
+    };
+    Ok(response)
+  }
+
+  pub async fn get_swap_instructions_impl(
+    &self,
+    req: CreateSwapTransactionRequest,
+  ) -> core::result::Result<GetSwapInstructionsResponse, anyhow::Error> {
+    // 小费交易需要与 swap 交易使用相同的 blockhash，只能由服务端构建
+    if req.tip_as_bundle {
+      return Err(anyhow::anyhow!("tip_as_bundle is not supported when returning instructions"));
+    }
+    // 代付账户的签名只能由服务端在构建交易时完成
+    if req.sponsor_fee {
+      return Err(SponsorError::Unsupported("returning instructions").into());
+    }
+    let prepared = self.prepare_swap(&req, true).await?;
+    let swap_instructions =
+      clmm_program::build_swap_instructions(&prepared.payer, prepared.swap_ix, &prepared.extra_instructions, &prepared.tx_options).await?;
+
+    let response = GetSwapInstructionsResponse {
+      result: Some(CommonResult { ret_code: 0, ret_msg: "Swap instructions created successfully".to_string() }),
+      compute_budget_instructions: swap_instructions.compute_budget.iter().map(instruction_info).collect(),
+      setup_instructions: swap_instructions.setup.iter().map(instruction_info).collect(),
+      swap_instruction: Some(instruction_info(&swap_instructions.swap)),
+      cleanup_instructions: swap_instructions.cleanup.iter().map(instruction_info).collect(),
+      address_lookup_tables: swap_instructions.address_lookup_tables.iter().map(|table| table.to_string()).collect(),
+      ata_rent_lamports: prepared.ata_rent_lamports,
+      compute_unit_limit: swap_instructions.compute_unit_limit,
+      compute_unit_price_micro_lamports: prepared.tx_options.cu_price,
+      tip_account: prepared.tip_account.map(|tip_account| tip_account.to_string()).unwrap_or_default(),
+      advance_nonce_instruction: swap_instructions.advance_nonce.as_ref().map(instruction_info),
+    };
+    Ok(response)
+  }
+
+  pub async fn simulate_swap_impl(&self, req: CreateSwapTransactionRequest) -> core::result::Result<SimulateSwapResponse, anyhow::Error> {
+    // 打包(bundle)时只模拟 swap 交易，小费交易不影响 swap 的结果
+    // 不做余额检查，余额不足等失败原因由模拟结果反映
+    let prepared = self.prepare_swap(&req, false).await?;
+    let vtx =
+      clmm_program::build_simulation_tx(&prepared.fee_payer, prepared.swap_ix, &prepared.extra_instructions, &prepared.tx_options).await?;
+
+    let nacos_config = get_nacos_config().await;
+    let rpc_client = nacos_config.get_rand_rpc();
+    // 公开接口的模拟结果由请求方决定，不用于校准 cu 模型
+    let report = simulation::simulate_with_balances(&rpc_client, &vtx, &prepared.payer, &prepared.token_accounts).await?;
+
+    let response = SimulateSwapResponse {
+      result: Some(CommonResult { ret_code: 0, ret_msg: "Swap transaction simulated successfully".to_string() }),
+      success: report.error.is_none(),
+      error: report.error.map(|error| SimulationError {
+        message: error.message,
+        instruction_index: error.instruction_index.map(u32::from),
+        program_id: error.program_id.map(|program_id| program_id.to_string()).unwrap_or_default(),
+        error_code: error.error_code,
+        error_name: error.error_name.unwrap_or_default(),
+      }),
+      logs: report.logs,
+      units_consumed: report.units_consumed,
+      token_balance_changes: report
+        .token_balance_changes
+        .iter()
+        .map(|change| TokenBalanceChange {
+          mint: change.mint.to_string(),
+          pre_amount: change.pre_amount.to_string(),
+          post_amount: change.post_amount.to_string(),
+          change: change.change().to_string(),
+        })
+        .collect(),
+      sol_balance_change: report.sol_balance_change,
+      transaction: BASE64_STANDARD.encode(bincode::serialize(&vtx)?),
+    };
+    Ok(response)
+  }
 
-    let swap_rsp = req.swap_response.ok_or(anyhow::anyhow!("Swap response is missing"))?;
+  /// 校验询价结果并准备 swap 指令和附加指令，构建交易和返回指令共用
+  /// check_balances: 是否在构建前检查钱包余额和账户状态
+  async fn prepare_swap(&self, req: &CreateSwapTransactionRequest, check_balances: bool) -> anyhow::Result<PreparedSwap> {
+    let nacos_config = get_nacos_config().await;
+    let rpc_client = nacos_config.get_rand_rpc();
+    let account_puller = AccountPuller::new(&rpc_client);
 
-    let mut tx_data = String::new();
+    println!("req: {}", serde_json::to_string_pretty(req)?);
+
+    let swap_rsp = quote::resolve_quote(
+      self.quote_store.as_ref(),
+      &nacos_config.quote_hmac_secret,
+      nacos_config.accept_client_quotes,
+      &req.quote_id,
+      req.swap_response.as_ref(),
+    )
+    .await?;
+    // 平台费以服务端保存的询价结果为准，不使用客户端回传的金额和代币
+    let platform_fee = nacos_config.get_platform_fee_config()?;
+    let (platform_fee_side, platform_fee_amount) = quote::quote_platform_fee(platform_fee.as_ref(), &swap_rsp, !req.quote_id.is_empty())?;
+    // 询价结果过期后，池子状态或 transfer-fee 可能已经变化，拒绝构建交易
+    let current_slot = rpc_client.get_slot().await?;
+    quote::check_quote_expiry(swap_rsp.expire_slot, current_slot)?;
 
     // todo: 实际实现时，需要从redis中获取池子信息，暂时先从链上获取
-    if swap_rsp.route_plan.len() == 1 {
-      // 单池
-      // todo : 放到独立的函数中处理
-      let pool_id = Pubkey::from_str(&swap_rsp.route_plan[0].pool_id)?;
-      let pool_account_data: PoolState = account_puller.get_one_account_data(&pool_id).await?;
-      let route_plan = &swap_rsp.route_plan[0];
-
-      let payer = Pubkey::from_str(&req.wallet)?;
-      let input_mint_account = Pubkey::from_str(&route_plan.input_mint)?;
-      let output_mint_account = Pubkey::from_str(&route_plan.output_mint)?;
-
-      let (input_vault_account, output_vault_account) = if pool_account_data.token_mint_0 == input_mint_account {
-        (pool_account_data.token_vault_0, pool_account_data.token_vault_1)
-      } else {
-        (pool_account_data.token_vault_1, pool_account_data.token_vault_0)
-      };
+    let route_plan = &swap_rsp.route_plan;
+    if route_plan.is_empty() || route_plan.len() > clmm_program::MAX_ROUTE_HOPS {
+      return Err(anyhow::anyhow!("Route plan length {} is not in 1..={}", route_plan.len(), clmm_program::MAX_ROUTE_HOPS));
+    }
+    // 每一跳的输出代币必须是下一跳的输入代币
+    for (prev, next) in route_plan.iter().zip(route_plan.iter().skip(1)) {
+      if prev.output_mint != next.input_mint {
+        return Err(anyhow::anyhow!("Route plan is not continuous: {} -> {}", prev.output_mint, next.input_mint));
+      }
+    }
 
-      let tick_array_bitmap_extension_key = PoolInfo::tick_array_bitmap_extension_key(&pool_id);
+    let payer = Pubkey::from_str(&req.wallet)?;
+    // 代付时由代付账户支付交易费和新建账户的租金，用户不需要持有 SOL
+    let sponsor = if req.sponsor_fee {
+      // 小费由用户指定，不能由代付账户支付
+      if req.tip_lamports > 0 || req.tip_as_bundle {
+        return Err(SponsorError::Unsupported("tip_lamports").into());
+      }
+      // 解封 SOL 会关闭 WSOL 的 ATA，代付账户支付的租金随之转给钱包
+      if req.unwrap_sol {
+        return Err(SponsorError::Unsupported("unwrap_sol").into());
+      }
+      let sponsor_config = nacos_config.get_sponsor_config().ok_or(SponsorError::NotConfigured)?;
+      Some(sponsor::load_sponsor(&sponsor_config).await?)
+    } else {
+      None
+    };
+    let fee_payer = sponsor.as_ref().map_or(payer, |sponsor| sponsor.pubkey());
+    // 询价结果中的金额和阈值都是用户视角的，包含了平台费
+    let quoted_input_amount: u64 = swap_rsp.input_amount.parse()?;
+    let quoted_output_amount: u64 = swap_rsp.output_amount.parse()?;
+    let quoted_threshold: u64 = swap_rsp.other_amount_threshold.parse()?;
+    let message_version = match TxVersion::try_from(req.tx_version) {
+      Ok(TxVersion::V0) => MessageVersion::V0,
+      Ok(TxVersion::Legacy) => MessageVersion::Legacy,
+      Err(_) => return Err(anyhow::anyhow!("Tx version {} is not supported", req.tx_version)),
+    };
+    // 旧格式交易不能使用地址查找表，不需要加载
+    let address_lookup_tables = match message_version {
+      MessageVersion::V0 => lookup_table::load_lookup_tables(&rpc_client, &nacos_config.get_address_lookup_tables()?).await?,
+      MessageVersion::Legacy => Vec::new(),
+    };
+
+    // 路由上所有代币的信息，token 账户需要按代币实际所属的 token program 推导
+    let mut route_mints = vec![Pubkey::from_str(&route_plan[0].input_mint)?];
+    for plan in route_plan.iter() {
+      route_mints.push(Pubkey::from_str(&plan.output_mint)?);
+    }
+    let mint_infos: HashMap<Pubkey, MintAccountBaseInfo> = account_puller
+      .get_multi_mint_account_with_extension_info(&route_mints)
+      .await?
+      .into_iter()
+      .map(|(mint, info)| info.map(|info| (mint, info)).ok_or_else(|| anyhow::anyhow!("Mint account not found: {}", mint)))
+      .collect::<anyhow::Result<_>>()?;
+
+    // 构建每一跳的交换信息
+    let mut swap_infos = Vec::with_capacity(route_plan.len());
+    for plan in route_plan.iter() {
+      swap_infos.push(Self::build_swap_route_info(&account_puller, plan, &mint_infos, &payer).await?);
+    }
 
-      let mut tick_array_keys = Vec::new();
-      for tk in route_plan.remaining_accounts.iter() {
-        let tick_array_key = Pubkey::from_str(&tk)?;
-        tick_array_keys.push(tick_array_key);
-        println!("===================tick_array-keys: {}", tick_array_key.to_string());
+    // 未指定计算单元价格时，按路由写入账户最近的优先费估算
+    let cu_price = match priority_fee::parse_compute_unit_price(&req.compute_unit_price_micro_lamports)? {
+      Some(cu_price) => cu_price,
+      None => {
+        let mut writable_accounts: Vec<Pubkey> = swap_infos.iter().flat_map(|swap_info| swap_info.writable_pool_accounts()).collect();
+        writable_accounts.sort_unstable();
+        writable_accounts.dedup();
+        priority_fee::estimate_compute_unit_price(&rpc_client, &writable_accounts, &nacos_config.get_priority_fee_config()).await?
+      }
+    };
+    let mut tx_options = SwapTxOptions {
+      cu_price,
+      message_version,
+      address_lookup_tables,
+      fee_payer_keypair: sponsor.as_ref().map(|sponsor| sponsor.keypair.clone()),
+      ..Default::default()
+    };
+    // 指定 nonce 账户时使用 durable nonce 构建交易，适用于签名耗时较长的场景；nonce authority 默认为钱包
+    if !req.nonce_account.is_empty() {
+      let nonce_authority = if req.nonce_authority.is_empty() { payer } else { Pubkey::from_str(&req.nonce_authority)? };
+      // 代付账户只作为 fee payer 签名，不能作为 nonce authority
+      if nonce_authority == fee_payer && sponsor.is_some() {
+        return Err(SponsorError::Unsupported("sponsor as nonce authority").into());
       }
+      tx_options.durable_nonce =
+        Some(nonce::load_durable_nonce(&rpc_client, &Pubkey::from_str(&req.nonce_account)?, &nonce_authority).await?);
+    }
 
-      // todo: 输入参数的amount, 另一个代币的量，逻辑是混乱的，要整理清楚
-      let amount: u64 = swap_rsp.input_amount.parse()?;
-      let other_amount_threshold: u64 = swap_rsp.other_amount_threshold.parse()?;
-      // todo: 直接修改为布尔值？
-      let is_base_input = match swap_rsp.swap_type {
-        0 => true,
-        1 => false,
-        _ => return Err(anyhow::anyhow!("Swap type is not supported")),
-      };
+    // todo: 直接修改为布尔值？
+    let is_base_input = match swap_rsp.swap_type {
+      0 => true,
+      1 => false,
+      _ => return Err(anyhow::anyhow!("Swap type is not supported")),
+    };
+    // swap 指令的指定数量和阈值需要去掉平台费的部分
+    let (amount, other_amount_threshold) = platform_fee::swap_amounts(
+      platform_fee_side,
+      platform_fee_amount,
+      is_base_input,
+      quoted_input_amount,
+      quoted_output_amount,
+      quoted_threshold,
+    )?;
+
+    let input_mint_info = mint_infos[&swap_infos[0].input_token_mint];
+    let output_mint = swap_infos[swap_infos.len() - 1].output_token_mint;
+    if req.wrap_sol && input_mint_info.mint != WSOL_MINT {
+      return Err(anyhow::anyhow!("wrap_sol requires WSOL input, got {}", input_mint_info.mint));
+    }
+    if req.unwrap_sol && output_mint != WSOL_MINT {
+      return Err(anyhow::anyhow!("unwrap_sol requires WSOL output, got {}", output_mint));
+    }
+
+    // 调用方指定了 token 账户时直接使用，否则使用钱包的 ATA
+    let input_token_account = if !req.input_account.is_empty() {
+      Pubkey::from_str(&req.input_account)?
+    } else {
+      get_associated_token_address_with_program_id(&payer, &input_mint_info.mint, &input_mint_info.program_id)
+    };
+    if !req.output_account.is_empty() {
+      swap_infos.last_mut().ok_or(anyhow::anyhow!("Route plan is empty"))?.output_token_account = Pubkey::from_str(&req.output_account)?;
+    }
 
-      let cu_price: u64 = req.compute_unit_price_micro_lamports.parse()?;
+    // 每一跳的输出代币（包括中转代币）都需要钱包的 ATA 接收，封装 SOL 时还需要 WSOL 的 ATA，不存在时在交易中创建
+    // 调用方指定的输入、输出账户由调用方保证存在，封装和解封 SOL 时直接使用
+    let mut ata_mint_infos: Vec<MintAccountBaseInfo> =
+      swap_infos.iter().map(|swap_info| mint_infos[&swap_info.output_token_mint]).collect();
+    if !req.output_account.is_empty() {
+      ata_mint_infos.pop();
+    }
+    if req.wrap_sol && req.input_account.is_empty() {
+      ata_mint_infos.push(input_mint_info);
+    }
+    let ata_setup = token_account::prepare_ata_setup(&account_puller, &fee_payer, &payer, &ata_mint_infos).await?;
 
-      let vtx = clmm_program::build_swap_v2_tx(
-        &payer,
-        &pool_account_data.amm_config,
-        &pool_id,
-        &input_mint_account,
-        &output_mint_account,
-        &input_vault_account,
-        &output_vault_account,
-        &pool_account_data.observation_key,
-        &tick_array_bitmap_extension_key,
-        &tick_array_keys,
-        amount,
-        other_amount_threshold,
-        0,
-        is_base_input,
-        cu_price,
-      )
-      .await?;
-
-      tx_data = BASE64_STANDARD.encode(bincode::serialize(&vtx)?);
-    } else if swap_rsp.route_plan.len() == 2 {
-      // 多次跳转的路径，需要使用路由合约进行兑换
-      let payer = Pubkey::from_str(&req.wallet)?;
-      let route_plan = &swap_rsp.route_plan;
-
-      // 获取输入代币相关信息
-      let input_mint = Pubkey::from_str(&route_plan[0].input_mint)?;
-      let input_token_account = if !req.input_account.is_empty() {
-        Pubkey::from_str(&req.input_account)?
-      } else {
-        get_associated_token_address(&payer, &input_mint)
+    // 指定 output 时，最多支付 other_amount_threshold 个输入代币；包含平台费
+    let max_input_amount = if is_base_input { quoted_input_amount } else { quoted_threshold };
+    let mut extra_instructions = ExtraInstructions { setup: ata_setup.instructions.clone(), ..Default::default() };
+    if req.wrap_sol {
+      extra_instructions.setup.extend(token_account::wrap_sol_instructions(&payer, &input_token_account, max_input_amount)?);
+    }
+
+    // 平台费：从输入扣除时在 swap 前转账，从输出扣除时在 swap 后、解封 SOL 前转账
+    if let (Some(fee_config), true) = (platform_fee, platform_fee_amount > 0) {
+      let referral_owner = if req.referral_account.is_empty() { None } else { Some(Pubkey::from_str(&req.referral_account)?) };
+      let fee_mint_info = match platform_fee_side {
+        FeeSide::Input => input_mint_info,
+        FeeSide::Output => mint_infos[&output_mint],
       };
+      let fee_transfers = platform_fee::fee_transfers(&fee_config, &fee_mint_info, platform_fee_amount, referral_owner.as_ref());
 
-      // 获取交易相关金额参数
-      let amount_in: u64 = swap_rsp.input_amount.parse()?;
-      let amount_out_minimum: u64 = swap_rsp.other_amount_threshold.parse()?;
-      let cu_price: u64 = req.compute_unit_price_micro_lamports.parse()?;
-
-      // 构建每一跳的交换信息
-      let mut swap_infos = Vec::with_capacity(route_plan.len());
-
-      // 处理两个池子的信息
-      for (i, plan) in route_plan.iter().enumerate() {
-        // 获取池子账户数据
-        let pool_id = Pubkey::from_str(&plan.pool_id)?;
-        let pool_account_data: PoolState = account_puller.get_one_account_data(&pool_id).await?;
-
-        let input_mint_account = Pubkey::from_str(&plan.input_mint)?;
-        let output_mint_account = Pubkey::from_str(&plan.output_mint)?;
-
-        // 确定代币金库账户
-        let (input_vault_account, output_vault_account) = if pool_account_data.token_mint_0 == input_mint_account {
-          (pool_account_data.token_vault_0, pool_account_data.token_vault_1)
-        } else {
-          (pool_account_data.token_vault_1, pool_account_data.token_vault_0)
-        };
-
-        // 获取tick array bitmap扩展账户
-        let tick_array_bitmap_extension_key = PoolInfo::tick_array_bitmap_extension_key(&pool_id);
-
-        // 解析tick arrays
-        let mut tick_arrays = Vec::new();
-        for tk in plan.remaining_accounts.iter() {
-          let tick_array_key = Pubkey::from_str(tk)?;
-          tick_arrays.push(tick_array_key);
+      // 平台费的接收账户由平台和推荐人预先创建，不在用户的交易中支付租金
+      let destinations: Vec<Pubkey> = fee_transfers.iter().map(|transfer| transfer.destination).collect();
+      for (destination, account) in account_puller.get_multi_accounts(&destinations).await? {
+        if account.is_none() {
+          return Err(anyhow::anyhow!("Platform fee token account {} does not exist", destination));
         }
+      }
 
-        // 构建SwapRouteInfo
-        let swap_info = SwapRouteInfo {
-          amm_config: pool_account_data.amm_config,
-          pool_state: pool_id,
-          output_token_mint: output_mint_account,
-          input_vault: input_vault_account,
-          output_vault: output_vault_account,
-          observation_state: pool_account_data.observation_key,
-          tick_array_bitmap_extension: Some(tick_array_bitmap_extension_key),
-          tick_arrays,
-        };
-
-        swap_infos.push(swap_info);
+      match platform_fee_side {
+        FeeSide::Input => extra_instructions.setup.extend(platform_fee::fee_transfer_instructions(
+          &fee_mint_info,
+          &input_token_account,
+          &payer,
+          &fee_transfers,
+        )?),
+        FeeSide::Output => extra_instructions.cleanup.extend(platform_fee::fee_transfer_instructions(
+          &fee_mint_info,
+          &swap_infos[swap_infos.len() - 1].output_token_account,
+          &payer,
+          &fee_transfers,
+        )?),
       }
+    }
+    if req.unwrap_sol {
+      let output_token_account = swap_infos[swap_infos.len() - 1].output_token_account;
+      extra_instructions.cleanup.extend(token_account::unwrap_sol_instructions(&payer, &output_token_account)?);
+    }
+
+    // 小费账户从配置中随机选择；打包(bundle)时小费放在单独的交易中，否则作为交易的最后一条指令
+    if req.tip_as_bundle && req.tip_lamports == 0 {
+      return Err(anyhow::anyhow!("tip_as_bundle requires tip_lamports"));
+    }
+    // 单独的小费交易没有 advance_nonce_account，只能使用 recent blockhash，与 durable nonce 的有效期不一致
+    if req.tip_as_bundle && tx_options.durable_nonce.is_some() {
+      return Err(anyhow::anyhow!("tip_as_bundle is not supported with durable nonce"));
+    }
+    let tip_account = if req.tip_lamports > 0 { Some(tip::pick_tip_account(&nacos_config.tip_accounts)?) } else { None };
+    let tip_ix = tip_account.map(|tip_account| tip::tip_instruction(&payer, &tip_account, req.tip_lamports)).transpose()?;
+    if let (false, Some(tip_ix)) = (req.tip_as_bundle, &tip_ix) {
+      extra_instructions.cleanup.push(tip_ix.clone());
+    }
 
-      // 构建路由交易
-      let vtx =
-        clmm_program::build_route_tx(&payer, &input_mint, &input_token_account, amount_in, amount_out_minimum, &swap_infos, cu_price)
-          .await?;
+    // 按路由和附加指令估算 cu，估算不可信时构建交易时会模拟执行
+    let cu_features = RouteCuFeatures {
+      hops: swap_infos
+        .iter()
+        .map(|swap_info| {
+          HopCuFeatures::new(
+            swap_info.tick_arrays.len(),
+            [&mint_infos[&swap_info.input_token_mint].program_id, &mint_infos[&swap_info.output_token_mint].program_id],
+          )
+        })
+        .collect(),
+      ata_creates: ata_setup.created_accounts.len(),
+      extra_instructions: extra_instructions.setup.len() + extra_instructions.cleanup.len() - ata_setup.instructions.len()
+        + usize::from(tx_options.durable_nonce.is_some()),
+    };
+    tx_options.cu_estimate = cu_model::estimate_cu(&cu_features).await;
+
+    // 优先费按模型估算的 cu limit 计算；代付时多一个签名，打包时小费交易多一个签名
+    let sol_requirement = SolRequirement {
+      signatures: 1
+        + u64::from(fee_payer != payer)
+        + u64::from(tx_options.durable_nonce.is_some_and(|durable_nonce| durable_nonce.authority != payer))
+        + u64::from(req.tip_as_bundle),
+      compute_unit_limit: TransactionBuilder::compute_unit_limit_with_margin(tx_options.cu_estimate.units, nacos_config.get_cu_factor()),
+      cu_price,
+      rent_lamports: ata_setup.rent_lamports,
+      wrap_lamports: if req.wrap_sol { max_input_amount } else { 0 },
+      tip_lamports: req.tip_lamports,
+      // 付款账户的余额低于免租金最低余额时交易会失败，只在检查余额时查询
+      rent_exempt_minimum: if check_balances { rpc_client.get_minimum_balance_for_rent_exemption(0).await? } else { 0 },
+    };
+    // 代付时钱包只需要支付封装的 SOL，交易费和租金由代付账户支付
+    let sponsor_cost = sponsor.as_ref().map(|sponsor| (sponsor.limits, SolRequirement { wrap_lamports: 0, ..sol_requirement }));
+    if let Some((limits, cost)) = &sponsor_cost {
+      limits.check(cost)?;
+    }
 
-      tx_data = BASE64_STANDARD.encode(bincode::serialize(&vtx)?);
-    } else {
-      return Err(anyhow::anyhow!("Route plan length is error"));
+    if check_balances {
+      // 输入账户由钱包转出；封装 SOL 时输入在交易中转入，WSOL 的 ATA 不存在时在交易中创建，调用方指定的账户必须已经存在
+      let mut token_checks = vec![TokenAccountCheck {
+        account: input_token_account,
+        mint: input_mint_info.mint,
+        required_amount: if req.wrap_sol { 0 } else { max_input_amount },
+        owner: Some(payer),
+        created_if_missing: req.wrap_sol && req.input_account.is_empty(),
+      }];
+      // 输出账户（包括中转代币）不能被冻结；钱包的 ATA 不存在时在交易中创建，调用方指定的输出账户必须已经存在
+      token_checks.extend(swap_infos.iter().map(|swap_info| TokenAccountCheck {
+        account: swap_info.output_token_account,
+        mint: swap_info.output_token_mint,
+        required_amount: 0,
+        owner: None,
+        created_if_missing: true,
+      }));
+      if let (false, Some(output_check)) = (req.output_account.is_empty(), token_checks.last_mut()) {
+        output_check.created_if_missing = false;
+      }
+      // 解封 SOL 时由钱包关闭输出账户
+      if let (true, Some(output_check)) = (req.unwrap_sol, token_checks.last_mut()) {
+        output_check.owner = Some(payer);
+      }
+      match &sponsor_cost {
+        Some((_, cost)) => {
+          let wallet_requirement = SolRequirement {
+            wrap_lamports: sol_requirement.wrap_lamports,
+            rent_exempt_minimum: sol_requirement.rent_exempt_minimum,
+            ..Default::default()
+          };
+          preflight::check_balances(&account_puller, &payer, &token_checks, &wallet_requirement).await?;
+          preflight::check_sol_balance(&fee_payer, rpc_client.get_balance(&fee_payer).await?, cost)?;
+        }
+        None => preflight::check_balances(&account_puller, &payer, &token_checks, &sol_requirement).await?,
+      }
     }
 
-    // 这里仅作为示例，返回一个默认的响应
-    let response = CreateSwapTransactionResponse {
-      result: Some(CommonResult { ret_code: 0, ret_msg: "Swap transaction created successfully".to_string() }),
-      data: vec![TransactionData { transaction: tx_data }],
+    let swap_ix = if let [swap_info] = swap_infos.as_slice() {
+      // 单池，直接调用 clmm 合约
+      clmm_program::swap_v2_instruction(&payer, &input_token_account, swap_info, amount, other_amount_threshold, 0, is_base_input)
+    } else {
+      // 多次跳转的路径，需要使用路由合约进行兑换
+      clmm_program::route_instruction(&payer, &input_mint_info.mint, &input_token_account, amount, other_amount_threshold, &swap_infos)?
     };
-    Ok(response)
+
+    Ok(PreparedSwap {
+      payer,
+      fee_payer,
+      sponsor_cost,
+      swap_ix,
+      extra_instructions,
+      tx_options,
+      ata_rent_lamports: ata_setup.rent_lamports,
+      tip_account,
+      bundle_tip_ix: if req.tip_as_bundle { tip_ix } else { None },
+      token_accounts: std::iter::once((input_token_account, input_mint_info.mint))
+        .chain(swap_infos.iter().map(|swap_info| (swap_info.output_token_account, swap_info.output_token_mint)))
+        .collect(),
+    })
+  }
+
+  /// 根据询价结果中的一跳，从链上获取池子信息并组装该跳 swap 需要的账户
+  /// 该跳的输出账户默认为 owner 的 ATA
+  async fn build_swap_route_info(
+    account_puller: &AccountPuller<'_>,
+    plan: &RoutePlan,
+    mint_infos: &HashMap<Pubkey, MintAccountBaseInfo>,
+    owner: &Pubkey,
+  ) -> anyhow::Result<SwapRouteInfo> {
+    let pool_id = Pubkey::from_str(&plan.pool_id)?;
+    let pool_account_data: PoolState = account_puller.get_one_account_data(&pool_id).await?;
+
+    let input_token_mint = Pubkey::from_str(&plan.input_mint)?;
+    let output_token_mint = Pubkey::from_str(&plan.output_mint)?;
+    let output_token_program =
+      mint_infos.get(&output_token_mint).ok_or_else(|| anyhow::anyhow!("Mint account not found: {}", output_token_mint))?.program_id;
+
+    // 确定代币金库账户
+    let (input_vault, output_vault) = if pool_account_data.token_mint_0 == input_token_mint {
+      (pool_account_data.token_vault_0, pool_account_data.token_vault_1)
+    } else {
+      (pool_account_data.token_vault_1, pool_account_data.token_vault_0)
+    };
+
+    // 询价计算过程中涉及的 tick arrays
+    let tick_arrays = plan.remaining_accounts.iter().map(|key| Pubkey::from_str(key)).collect::<Result<Vec<_>, _>>()?;
+
+    Ok(SwapRouteInfo {
+      amm_config: pool_account_data.amm_config,
+      pool_state: pool_id,
+      input_token_mint,
+      output_token_mint,
+      output_token_account: get_associated_token_address_with_program_id(owner, &output_token_mint, &output_token_program),
+      input_vault,
+      output_vault,
+      observation_state: pool_account_data.observation_key,
+      tick_array_bitmap_extension: Some(PoolInfo::tick_array_bitmap_extension_key(&pool_id)),
+      tick_arrays,
+    })
+  }
+}
+
+/// 指令转换为接口返回的格式，指令数据使用 base64 编码
+fn instruction_info(ix: &Instruction) -> InstructionInfo {
+  InstructionInfo {
+    program_id: ix.program_id.to_string(),
+    accounts: ix
+      .accounts
+      .iter()
+      .map(|meta| AccountMetaInfo { pubkey: meta.pubkey.to_string(), is_signer: meta.is_signer, is_writable: meta.is_writable })
+      .collect(),
+    data: BASE64_STANDARD.encode(&ix.data),
   }
 }
@@ -0,0 +1,1123 @@
+/// 询价计算与链上 CLMM 程序执行结果的对拍测试
+///
+/// 未设置 `BYREAL_CLMM_PROGRAM_SO` 时，测试会用 `cargo build-sbf` 从 raydium-amm-v3 依赖构建一次 CLMM 程序，
+/// 需要安装 Solana CLI：`cargo test clmm_differential -- --ignored`
+#[cfg(test)]
+mod clmm_differential {
+  use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+  };
+
+  use anchor_lang::{AccountDeserialize, AccountSerialize, Discriminator, Id, InstructionData, ToAccountMetas};
+  use anchor_spl::memo::Memo;
+  use litesvm::LiteSVM;
+  use raydium_amm_v3::{
+    libraries::tick_math,
+    states::{AMM_CONFIG_SEED, AmmConfig, OBSERVATION_SEED, ObservationState, POOL_SEED, POOL_VAULT_SEED, PoolState, TickArrayState},
+  };
+  use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    compute_budget::ComputeBudgetInstruction,
+    epoch_info::EpochInfo,
+    instruction::{AccountMeta, Instruction},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    transaction::Transaction,
+  };
+  use spl_associated_token_account::get_associated_token_address_with_program_id;
+  use spl_token_2022::{
+    extension::{
+      BaseStateWithExtensionsMut, ExtensionType, StateWithExtensions, StateWithExtensionsMut,
+      transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig},
+    },
+    state::{Account as TokenAccount, AccountState, Mint},
+  };
+
+  use crate::{
+    constants::BYREAL_CLMM_PROGRAM_ID,
+    service::{
+      core::{build_tx::MAX_COMPUTE_UNIT_LIMIT, types::MintAccountBaseInfo},
+      router_service::{
+        clmm_pool_utils::compute_another_amount,
+        fixtures,
+        types::{POOL_VERSION_CLMM, PoolBaseInfo, PoolDynamicInfo, PoolInfo},
+      },
+    },
+  };
+
+  const PROGRAM_SO_ENV: &str = "BYREAL_CLMM_PROGRAM_SO";
+
+  const TICK_SPACING: u16 = 10;
+  const TRADE_FEE_RATE: u32 = 2500;
+  const MINT_DECIMALS: u8 = 6;
+  const TRANSFER_FEE_MAXIMUM: u64 = 5_000_000_000;
+  const TOKEN_BALANCE: u64 = 1_000_000_000_000_000;
+
+  const FIXTURE_EPOCH: u64 = 600;
+  const SLOTS_IN_EPOCH: u64 = 432_000;
+  const FIXTURE_TIMESTAMP: i64 = 1_700_000_000;
+
+  /// (tick_lower, tick_upper, liquidity)
+  /// 当前 tick 为 0，价格向两侧移动时都会穿过多个 tick 和多个 tick-array
+  const FIXTURE_POSITIONS: [(i32, i32, u128); 3] =
+    [(-1200, 1200, 2_000_000_000_000), (-300, 300, 5_000_000_000_000), (-600, 900, 1_000_000_000_000)];
+
+  /// 只在当前 tick-array 内成交的数量
+  const SMALL_AMOUNT: u64 = 1_000_000;
+  /// 会穿过多个 tick-array 的数量
+  const LARGE_AMOUNT: u64 = 150_000_000_000;
+
+  struct Fixture {
+    svm: LiteSVM,
+    payer: Keypair,
+    pool: PoolInfo,
+    epoch_info: EpochInfo,
+  }
+
+  /// 链上执行一次 swap 之后的结果
+  struct ProgramSwapResult {
+    input_spent: u64,
+    output_received: u64,
+    sqrt_price_x64: u128,
+    tick_current: i32,
+    liquidity: u128,
+  }
+
+  fn rent_exempt_account(svm: &LiteSVM, owner: Pubkey, data: Vec<u8>) -> Account {
+    Account { lamports: svm.minimum_balance_for_rent_exemption(data.len()), data, owner, executable: false, rent_epoch: 0 }
+  }
+
+  fn zero_copy_account_data<T: Discriminator + bytemuck::Pod>(state: &T) -> Vec<u8> {
+    let mut data = T::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(bytemuck::bytes_of(state));
+    data
+  }
+
+  /// `transfer_fee_basis_points` 为 None 时创建 spl-token 的 mint，否则创建带 transfer-fee 的 token-2022 mint
+  fn set_mint(svm: &mut LiteSVM, mint: Pubkey, transfer_fee_basis_points: Option<u16>) -> MintAccountBaseInfo {
+    let base = Mint {
+      mint_authority: COption::None,
+      supply: u64::MAX,
+      decimals: MINT_DECIMALS,
+      is_initialized: true,
+      freeze_authority: COption::None,
+    };
+
+    let (program_id, data, transfer_fee_config) = match transfer_fee_basis_points {
+      None => {
+        let mut data = vec![0u8; Mint::LEN];
+        base.pack_into_slice(&mut data);
+        (spl_token::id(), data, None)
+      }
+      Some(basis_points) => {
+        let space = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+        let mut data = vec![0u8; space];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        let transfer_fee =
+          TransferFee { epoch: 0.into(), maximum_fee: TRANSFER_FEE_MAXIMUM.into(), transfer_fee_basis_points: basis_points.into() };
+        let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        config.older_transfer_fee = transfer_fee;
+        config.newer_transfer_fee = transfer_fee;
+        let config = *config;
+        state.base = base;
+        state.pack_base();
+        state.init_account_type().unwrap();
+        (spl_token_2022::id(), data, Some(config))
+      }
+    };
+
+    let account = rent_exempt_account(svm, program_id, data);
+    svm.set_account(mint, account).unwrap();
+    MintAccountBaseInfo { mint, program_id, decimal: MINT_DECIMALS, transfer_fee_config, ..Default::default() }
+  }
+
+  fn set_token_account(svm: &mut LiteSVM, address: Pubkey, mint: &MintAccountBaseInfo, owner: Pubkey, amount: u64) {
+    let base = TokenAccount {
+      mint: mint.mint,
+      owner,
+      amount,
+      delegate: COption::None,
+      state: AccountState::Initialized,
+      is_native: COption::None,
+      delegated_amount: 0,
+      close_authority: COption::None,
+    };
+
+    let data = if mint.transfer_fee_config.is_none() {
+      let mut data = vec![0u8; TokenAccount::LEN];
+      base.pack_into_slice(&mut data);
+      data
+    } else {
+      let space = ExtensionType::try_calculate_account_len::<TokenAccount>(&[ExtensionType::TransferFeeAmount]).unwrap();
+      let mut data = vec![0u8; space];
+      let mut state = StateWithExtensionsMut::<TokenAccount>::unpack_uninitialized(&mut data).unwrap();
+      state.init_extension::<TransferFeeAmount>(true).unwrap();
+      state.base = base;
+      state.pack_base();
+      state.init_account_type().unwrap();
+      data
+    };
+
+    let account = rent_exempt_account(svm, mint.program_id, data);
+    svm.set_account(address, account).unwrap();
+  }
+
+  fn token_balance(svm: &LiteSVM, address: &Pubkey) -> u64 {
+    let account = svm.get_account(address).unwrap();
+    StateWithExtensions::<TokenAccount>::unpack(&account.data).unwrap().base.amount
+  }
+
+  /// CLMM 程序的 .so 文件：优先使用 BYREAL_CLMM_PROGRAM_SO 指定的文件，否则从 Cargo.lock 锁定的 raydium-amm-v3 源码构建
+  fn program_so() -> &'static Path {
+    static PROGRAM_SO: OnceLock<PathBuf> = OnceLock::new();
+    PROGRAM_SO.get_or_init(|| {
+      if let Ok(program_so) = std::env::var(PROGRAM_SO_ENV) {
+        return PathBuf::from(program_so);
+      }
+
+      let output = Command::new(env!("CARGO"))
+        .args(["metadata", "--format-version", "1", "--manifest-path", concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")])
+        .output()
+        .expect("failed to run cargo metadata");
+      assert!(output.status.success(), "cargo metadata failed: {}", String::from_utf8_lossy(&output.stderr));
+      let metadata: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+      let manifest_path = metadata["packages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|package| package["name"] == "raydium-amm-v3")
+        .and_then(|package| package["manifest_path"].as_str())
+        .expect("raydium-amm-v3 is not a dependency");
+      let target_dir = Path::new(metadata["target_directory"].as_str().unwrap()).join("sbf");
+
+      // 依赖源码目录是只读的 git checkout，构建产物放在本仓库的 target 目录下
+      let status = Command::new(env!("CARGO"))
+        .args(["build-sbf", "--manifest-path", manifest_path, "--sbf-out-dir"])
+        .arg(target_dir.join("deploy"))
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run cargo build-sbf ({}), install the Solana CLI or set {}", e, PROGRAM_SO_ENV));
+      assert!(status.success(), "cargo build-sbf failed, install the Solana CLI or set {}", PROGRAM_SO_ENV);
+      target_dir.join("deploy/raydium_amm_v3.so")
+    })
+  }
+
+  /// 在 SVM 中创建池子及相关账户，同时返回询价时使用的 PoolInfo
+  fn setup_fixture(transfer_fee_basis_points: [Option<u16>; 2]) -> Fixture {
+    let mut svm = LiteSVM::new();
+    svm.add_program_from_file(BYREAL_CLMM_PROGRAM_ID, program_so()).unwrap();
+
+    let mut clock: Clock = svm.get_sysvar();
+    clock.epoch = FIXTURE_EPOCH;
+    clock.slot = FIXTURE_EPOCH * SLOTS_IN_EPOCH + 1;
+    clock.unix_timestamp = FIXTURE_TIMESTAMP;
+    svm.set_sysvar(&clock);
+    let epoch_info = EpochInfo {
+      epoch: FIXTURE_EPOCH,
+      slot_index: 1,
+      slots_in_epoch: SLOTS_IN_EPOCH,
+      absolute_slot: clock.slot,
+      block_height: clock.slot,
+      transaction_count: None,
+    };
+
+    let payer = Keypair::new();
+    svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+
+    // token_mint_0 < token_mint_1
+    let (key_0, key_1) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let (key_0, key_1) = if key_0 < key_1 { (key_0, key_1) } else { (key_1, key_0) };
+    let mint_a_info = set_mint(&mut svm, key_0, transfer_fee_basis_points[0]);
+    let mint_b_info = set_mint(&mut svm, key_1, transfer_fee_basis_points[1]);
+
+    let config_index: u16 = 0;
+    let (amm_config_key, amm_config_bump) =
+      Pubkey::find_program_address(&[AMM_CONFIG_SEED.as_bytes(), &config_index.to_be_bytes()], &BYREAL_CLMM_PROGRAM_ID);
+    let amm_config = AmmConfig {
+      bump: amm_config_bump,
+      index: config_index,
+      trade_fee_rate: TRADE_FEE_RATE,
+      tick_spacing: TICK_SPACING,
+      ..Default::default()
+    };
+    let mut amm_config_data = Vec::new();
+    amm_config.try_serialize(&mut amm_config_data).unwrap();
+    let account = rent_exempt_account(&svm, BYREAL_CLMM_PROGRAM_ID, amm_config_data);
+    svm.set_account(amm_config_key, account).unwrap();
+
+    let (pool_id, pool_bump) = Pubkey::find_program_address(
+      &[POOL_SEED.as_bytes(), amm_config_key.as_ref(), mint_a_info.mint.as_ref(), mint_b_info.mint.as_ref()],
+      &BYREAL_CLMM_PROGRAM_ID,
+    );
+    let token_vault_a =
+      Pubkey::find_program_address(&[POOL_VAULT_SEED.as_bytes(), pool_id.as_ref(), mint_a_info.mint.as_ref()], &BYREAL_CLMM_PROGRAM_ID).0;
+    let token_vault_b =
+      Pubkey::find_program_address(&[POOL_VAULT_SEED.as_bytes(), pool_id.as_ref(), mint_b_info.mint.as_ref()], &BYREAL_CLMM_PROGRAM_ID).0;
+    let observation_key = Pubkey::find_program_address(&[OBSERVATION_SEED.as_bytes(), pool_id.as_ref()], &BYREAL_CLMM_PROGRAM_ID).0;
+    let tick_array_bitmap_extension_key = PoolInfo::tick_array_bitmap_extension_key(&pool_id);
+
+    // 池子的金库和用户的代币账户
+    set_token_account(&mut svm, token_vault_a, &mint_a_info, pool_id, TOKEN_BALANCE);
+    set_token_account(&mut svm, token_vault_b, &mint_b_info, pool_id, TOKEN_BALANCE);
+    for mint_info in [&mint_a_info, &mint_b_info] {
+      let user_account = get_associated_token_address_with_program_id(&payer.pubkey(), &mint_info.mint, &mint_info.program_id);
+      set_token_account(&mut svm, user_account, mint_info, payer.pubkey(), TOKEN_BALANCE);
+    }
+
+    // tick-array 及 bitmap
+    let tick_arrays = fixtures::tick_arrays_from_positions(pool_id, TICK_SPACING, &FIXTURE_POSITIONS);
+    for tick_array in tick_arrays.iter() {
+      let account = rent_exempt_account(&svm, BYREAL_CLMM_PROGRAM_ID, zero_copy_account_data(tick_array));
+      svm.set_account(PoolInfo::get_pda_tick_array_address(&pool_id, tick_array.start_tick_index), account).unwrap();
+    }
+    let start_indexes: Vec<i32> = tick_arrays.iter().map(|tick_array| tick_array.start_tick_index).collect();
+    let (tick_array_bitmap, tick_array_bitmap_extension) = fixtures::tick_array_bitmaps(pool_id, TICK_SPACING, &start_indexes);
+    let account = rent_exempt_account(&svm, BYREAL_CLMM_PROGRAM_ID, zero_copy_account_data(&tick_array_bitmap_extension));
+    svm.set_account(tick_array_bitmap_extension_key, account).unwrap();
+
+    let observation_state = ObservationState { pool_id, ..Default::default() };
+    let account = rent_exempt_account(&svm, BYREAL_CLMM_PROGRAM_ID, zero_copy_account_data(&observation_state));
+    svm.set_account(observation_key, account).unwrap();
+
+    let tick_current = 0;
+    let sqrt_price_x64 = tick_math::get_sqrt_price_at_tick(tick_current).unwrap();
+    let liquidity = fixtures::active_liquidity(&FIXTURE_POSITIONS, tick_current);
+    let pool_state = PoolState {
+      bump: [pool_bump],
+      amm_config: amm_config_key,
+      owner: payer.pubkey(),
+      token_mint_0: mint_a_info.mint,
+      token_mint_1: mint_b_info.mint,
+      token_vault_0: token_vault_a,
+      token_vault_1: token_vault_b,
+      observation_key,
+      mint_decimals_0: MINT_DECIMALS,
+      mint_decimals_1: MINT_DECIMALS,
+      tick_spacing: TICK_SPACING,
+      liquidity,
+      sqrt_price_x64,
+      tick_current,
+      tick_array_bitmap,
+      ..Default::default()
+    };
+    let account = rent_exempt_account(&svm, BYREAL_CLMM_PROGRAM_ID, zero_copy_account_data(&pool_state));
+    svm.set_account(pool_id, account).unwrap();
+
+    let pool = PoolInfo {
+      base_info: PoolBaseInfo {
+        id: pool_id,
+        version: POOL_VERSION_CLMM,
+        amm_config: amm_config_key,
+        open_time: 0,
+        tick_spacing: TICK_SPACING,
+        mint_a_info,
+        mint_b_info,
+        token_vault_a,
+        token_vault_b,
+        observation_key,
+        tick_array_bitmap_extension_key,
+        trade_fee_rate: TRADE_FEE_RATE,
+      },
+      dynamic_info: PoolDynamicInfo {
+        id: pool_id,
+        liquidity,
+        sqrt_price_x64,
+        tick_current,
+        tick_array_bitmap,
+        tick_array_bitmap_extension,
+        all_tick_array_state: tick_arrays,
+      },
+    };
+
+    Fixture { svm, payer, pool, epoch_info }
+  }
+
+  /// 在 SVM 中执行 swap_v2，tick-array 按照 swap 方向传入全部已初始化的账户，不依赖询价的计算结果
+  fn execute_program_swap(fixture: &mut Fixture, zero_for_one: bool, base_input: bool, amount: u64) -> ProgramSwapResult {
+    let pool = &fixture.pool;
+    let payer = fixture.payer.pubkey();
+    let (input_mint, output_mint, input_vault, output_vault) = if zero_for_one {
+      (&pool.base_info.mint_a_info, &pool.base_info.mint_b_info, pool.base_info.token_vault_a, pool.base_info.token_vault_b)
+    } else {
+      (&pool.base_info.mint_b_info, &pool.base_info.mint_a_info, pool.base_info.token_vault_b, pool.base_info.token_vault_a)
+    };
+    let input_token_account = get_associated_token_address_with_program_id(&payer, &input_mint.mint, &input_mint.program_id);
+    let output_token_account = get_associated_token_address_with_program_id(&payer, &output_mint.mint, &output_mint.program_id);
+
+    let current_start_index = TickArrayState::get_array_start_index(pool.dynamic_info.tick_current, TICK_SPACING);
+    let mut tick_array_start_indexes: Vec<i32> = pool
+      .dynamic_info
+      .all_tick_array_state
+      .iter()
+      .map(|tick_array| tick_array.start_tick_index)
+      .filter(|start_index| if zero_for_one { *start_index <= current_start_index } else { *start_index >= current_start_index })
+      .collect();
+    if zero_for_one {
+      tick_array_start_indexes.reverse();
+    }
+
+    let mut accounts = raydium_amm_v3::accounts::SwapSingleV2 {
+      payer,
+      amm_config: pool.base_info.amm_config,
+      pool_state: pool.base_info.id,
+      input_token_account,
+      output_token_account,
+      input_vault,
+      output_vault,
+      observation_state: pool.base_info.observation_key,
+      token_program: spl_token::id(),
+      token_program_2022: spl_token_2022::id(),
+      memo_program: Memo::id(),
+      input_vault_mint: input_mint.mint,
+      output_vault_mint: output_mint.mint,
+    }
+    .to_account_metas(None);
+    accounts.push(AccountMeta::new_readonly(pool.base_info.tick_array_bitmap_extension_key, false));
+    for start_index in tick_array_start_indexes {
+      accounts.push(AccountMeta::new(PoolInfo::get_pda_tick_array_address(&pool.base_info.id, start_index), false));
+    }
+
+    let args = raydium_amm_v3::instruction::SwapV2 {
+      amount,
+      other_amount_threshold: if base_input { 0 } else { u64::MAX },
+      sqrt_price_limit_x64: 0,
+      is_base_input: base_input,
+    };
+    let instructions = [
+      ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+      Instruction { program_id: BYREAL_CLMM_PROGRAM_ID, accounts, data: args.data() },
+    ];
+
+    let input_before = token_balance(&fixture.svm, &input_token_account);
+    let output_before = token_balance(&fixture.svm, &output_token_account);
+
+    let tx = Transaction::new_signed_with_payer(&instructions, Some(&payer), &[&fixture.payer], fixture.svm.latest_blockhash());
+    if let Err(failed) = fixture.svm.send_transaction(tx) {
+      panic!("swap_v2 failed: {:?}\n{}", failed.err, failed.meta.logs.join("\n"));
+    }
+
+    let pool_account = fixture.svm.get_account(&pool.base_info.id).unwrap();
+    let pool_state = PoolState::try_deserialize(&mut pool_account.data.as_slice()).unwrap();
+
+    ProgramSwapResult {
+      input_spent: input_before - token_balance(&fixture.svm, &input_token_account),
+      output_received: token_balance(&fixture.svm, &output_token_account) - output_before,
+      sqrt_price_x64: pool_state.sqrt_price_x64,
+      tick_current: pool_state.tick_current,
+      liquidity: pool_state.liquidity,
+    }
+  }
+
+  /// 对每个方向、exact-in / exact-out、大小两种数量分别比对询价结果与链上执行结果
+  fn assert_quotes_match_program(transfer_fee_basis_points: [Option<u16>; 2]) {
+    for zero_for_one in [true, false] {
+      for base_input in [true, false] {
+        for amount in [SMALL_AMOUNT, LARGE_AMOUNT] {
+          let mut fixture = setup_fixture(transfer_fee_basis_points);
+          let pool = &fixture.pool;
+          let input_mint = if zero_for_one { pool.base_info.mint_a_info.mint } else { pool.base_info.mint_b_info.mint };
+
+          let quote = compute_another_amount(pool, &input_mint, base_input, amount, &fixture.epoch_info, None).unwrap();
+          let program = execute_program_swap(&mut fixture, zero_for_one, base_input, amount);
+
+          let case = format!(
+            "fees: {:?}, zero_for_one: {}, base_input: {}, amount: {}",
+            transfer_fee_basis_points, zero_for_one, base_input, amount
+          );
+          if base_input {
+            assert_eq!(program.input_spent, amount, "{}", case);
+            assert_eq!(program.output_received, quote.amount_calculated, "{}", case);
+          } else {
+            assert_eq!(program.output_received, amount, "{}", case);
+            assert_eq!(program.input_spent, quote.amount_calculated, "{}", case);
+          }
+          assert_eq!(program.sqrt_price_x64, quote.after_sqrt_price_x64, "{}", case);
+          assert_eq!(program.tick_current, quote.after_tick, "{}", case);
+          assert_eq!(program.liquidity, quote.after_liquidity, "{}", case);
+        }
+      }
+    }
+  }
+
+  #[test]
+  #[ignore = "builds the CLMM program with cargo build-sbf"]
+  fn swap_compute_matches_program_spl_token() {
+    assert_quotes_match_program([None, None]);
+  }
+
+  #[test]
+  #[ignore = "builds the CLMM program with cargo build-sbf"]
+  fn swap_compute_matches_program_token_2022_transfer_fee() {
+    assert_quotes_match_program([Some(150), None]);
+    assert_quotes_match_program([None, Some(150)]);
+    assert_quotes_match_program([Some(150), Some(35)]);
+  }
+
+  /// 单步 swap 的期望结果，按 CLMM 程序 swap_math 的公式（sqrt price 的计算和 compute_swap_step 的取整方式）计算得到
+  /// 数量足够小，swap 不会穿过 tick，不需要链上程序即可运行
+  #[derive(serde::Deserialize)]
+  struct SwapStepFixture {
+    tick_spacing: u16,
+    tick_current: i32,
+    trade_fee_rate: u32,
+    positions: Vec<(i32, i32, u128)>,
+    cases: Vec<SwapStepCase>,
+  }
+
+  #[derive(serde::Deserialize)]
+  struct SwapStepCase {
+    zero_for_one: bool,
+    base_input: bool,
+    amount: u64,
+    amount_calculated: u64,
+    /// u128 以字符串保存
+    after_sqrt_price_x64: String,
+  }
+
+  #[test]
+  fn swap_compute_matches_checked_in_fixture() {
+    let fixture: SwapStepFixture = serde_json::from_str(include_str!("testdata/clmm_swap_steps.json")).unwrap();
+    let pool = fixtures::synthetic_pool(fixture.tick_spacing, fixture.tick_current, fixture.trade_fee_rate, &fixture.positions);
+    let epoch_info = EpochInfo {
+      epoch: FIXTURE_EPOCH,
+      slot_index: 0,
+      slots_in_epoch: SLOTS_IN_EPOCH,
+      absolute_slot: FIXTURE_EPOCH * SLOTS_IN_EPOCH,
+      block_height: FIXTURE_EPOCH * SLOTS_IN_EPOCH,
+      transaction_count: None,
+    };
+
+    for case in fixture.cases {
+      let input_mint = if case.zero_for_one { pool.base_info.mint_a_info.mint } else { pool.base_info.mint_b_info.mint };
+      let result = compute_another_amount(&pool, &input_mint, case.base_input, case.amount, &epoch_info, None).unwrap();
+      let name = format!("zero_for_one={} base_input={} amount={}", case.zero_for_one, case.base_input, case.amount);
+      assert_eq!(result.amount_calculated, case.amount_calculated, "{}", name);
+      assert_eq!(result.after_sqrt_price_x64.to_string(), case.after_sqrt_price_x64, "{}", name);
+    }
+  }
+}
+
+/// 询价计算的单元测试，不需要链上程序
+#[cfg(test)]
+mod quote_math {
+  use solana_sdk::{epoch_info::EpochInfo, pubkey::Pubkey};
+  use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+
+  use crate::service::router_service::{
+    clmm_pool_utils::{compute_another_amount, get_transfer_amount_fee},
+    fixtures,
+    types::PoolInfo,
+  };
+
+  const TICK_SPACING: u16 = 10;
+  const TRADE_FEE_RATE: u32 = 2500;
+  /// (tick_lower, tick_upper, liquidity)，当前 tick 为 0，两侧各有多个 tick-array
+  const POSITIONS: [(i32, i32, u128); 2] = [(-1200, 1200, 2_000_000_000_000), (-300, 300, 5_000_000_000_000)];
+
+  fn epoch_info() -> EpochInfo {
+    EpochInfo {
+      epoch: 600,
+      slot_index: 0,
+      slots_in_epoch: 432_000,
+      absolute_slot: 259_200_000,
+      block_height: 259_200_000,
+      transaction_count: None,
+    }
+  }
+
+  fn pool() -> PoolInfo {
+    fixtures::synthetic_pool(TICK_SPACING, 0, TRADE_FEE_RATE, &POSITIONS)
+  }
+
+  fn transfer_fee_config(transfer_fee_basis_points: u16) -> TransferFeeConfig {
+    let transfer_fee =
+      TransferFee { epoch: 0.into(), maximum_fee: u64::MAX.into(), transfer_fee_basis_points: transfer_fee_basis_points.into() };
+    TransferFeeConfig { older_transfer_fee: transfer_fee, newer_transfer_fee: transfer_fee, ..Default::default() }
+  }
+
+  #[test]
+  fn exact_output_quote_returns_required_input() {
+    let pool = pool();
+    let input_mint = pool.base_info.mint_a_info.mint;
+    let output_amount = 1_000_000;
+
+    let exact_out = compute_another_amount(&pool, &input_mint, false, output_amount, &epoch_info(), None).unwrap();
+    // 价格为 1，加上交易手续费后需要的输入多于输出
+    assert!(exact_out.amount_calculated > output_amount);
+    // 按报价的输入指定输入 swap，至少得到指定的输出
+    let exact_in = compute_another_amount(&pool, &input_mint, true, exact_out.amount_calculated, &epoch_info(), None).unwrap();
+    assert!(exact_in.amount_calculated >= output_amount);
+  }
+
+  #[test]
+  fn added_transfer_fee_is_the_inverse_fee() {
+    let config = Some(transfer_fee_config(100));
+    for amount in [1, 99, 9_900, 1_000_000, 123_456_789] {
+      let added = get_transfer_amount_fee(amount, &config, &epoch_info(), true).unwrap();
+      // 转账时按转出的数量扣除手续费，到账的恰好是 amount
+      assert_eq!(get_transfer_amount_fee(added.amount, &config, &epoch_info(), false).unwrap().amount, amount);
+    }
+  }
+
+  #[test]
+  fn exact_output_adds_transfer_fees_on_each_side() {
+    let plain = pool();
+    let input_mint = plain.base_info.mint_a_info.mint;
+    let config = Some(transfer_fee_config(100));
+    let output_amount = 1_000_000;
+    let quote = |pool: &PoolInfo, amount: u64| {
+      compute_another_amount(pool, &input_mint, false, amount, &epoch_info(), None).unwrap().amount_calculated
+    };
+
+    // 输入代币有转账手续费：池子收到的输入不变，用户需要多支付手续费
+    let mut input_fee = plain.clone();
+    input_fee.base_info.mint_a_info.transfer_fee_config = config;
+    let required_input = get_transfer_amount_fee(quote(&plain, output_amount), &config, &epoch_info(), true).unwrap().amount;
+    assert_eq!(quote(&input_fee, output_amount), required_input);
+
+    // 输出代币有转账手续费：池子需要多转出手续费，用户才能收到指定的输出
+    let mut output_fee = plain.clone();
+    output_fee.base_info.mint_b_info.transfer_fee_config = config;
+    let gross_output = get_transfer_amount_fee(output_amount, &config, &epoch_info(), true).unwrap().amount;
+    assert_eq!(quote(&output_fee, output_amount), quote(&plain, gross_output));
+  }
+
+  #[test]
+  fn tick_arrays_are_dequeued_in_swap_direction() {
+    let pool = pool();
+    let start_indexes = |zero_for_one| -> Vec<i32> {
+      pool.get_tick_array_dequeue(0, zero_for_one).unwrap().iter().map(|tick_array| tick_array.start_tick_index).collect()
+    };
+    // 价格下降时 tick 向左移动，上升时向右移动
+    assert_eq!(start_indexes(true), vec![0, -600, -1200]);
+    assert_eq!(start_indexes(false), vec![0, 1200]);
+  }
+
+  #[test]
+  fn swap_skips_uninitialized_tick_arrays_more_than_once() {
+    // tick_spacing 为 1 时每个 tick-array 有 60 个 tick，三个仓位分别在 0、60、180 开始的 tick-array 中，120 开始的没有初始化
+    let positions = [(0, 10, 1_000_000_000_000), (100, 110, 1_000_000_000_000), (200, 210, 1_000_000_000_000)];
+    let pool = fixtures::synthetic_pool(1, 5, 0, &positions);
+    let input_mint = pool.base_info.mint_b_info.mint;
+
+    // 输入足够穿过前两个仓位，在第三个仓位中成交，需要连续两次查找下一个已初始化的 tick-array
+    let result = compute_another_amount(&pool, &input_mint, true, 1_000_000_000, &epoch_info(), None).unwrap();
+    assert!((200..210).contains(&result.after_tick));
+    let tick_array_keys: Vec<Pubkey> =
+      [0, 60, 180].iter().map(|start_index| PoolInfo::get_pda_tick_array_address(&pool.base_info.id, *start_index)).collect();
+    assert_eq!(result.tick_array_keys, tick_array_keys);
+  }
+}
+
+/// tick、流动性相关工具函数的性质测试
+#[cfg(test)]
+mod properties {
+  use std::{collections::BTreeSet, ops::RangeInclusive};
+
+  use proptest::{prelude::*, sample::Index, test_runner::TestCaseError};
+  use raydium_amm_v3::{libraries::tick_math, states::TickArrayState};
+  use solana_sdk::{epoch_info::EpochInfo, pubkey::Pubkey};
+  use spl_token_2022::extension::transfer_fee::{MAX_FEE_BASIS_POINTS, TransferFee, TransferFeeConfig};
+
+  use crate::service::router_service::{
+    clmm_pool_utils::{compute_another_amount, get_transfer_amount_fee},
+    error::SwapComputeError,
+    fixtures,
+    route_utils::generate_tick_liquidity_info,
+    types::{PoolBaseInfo, PoolDynamicInfo, PoolInfo},
+  };
+
+  const SLOTS_IN_EPOCH: u64 = 432_000;
+  const TRADE_FEE_RATE: u32 = 2500;
+
+  fn epoch_info(epoch: u64) -> EpochInfo {
+    EpochInfo {
+      epoch,
+      slot_index: 0,
+      slots_in_epoch: SLOTS_IN_EPOCH,
+      absolute_slot: epoch * SLOTS_IN_EPOCH,
+      block_height: epoch * SLOTS_IN_EPOCH,
+      transaction_count: None,
+    }
+  }
+
+  fn tick_spacing_strategy() -> impl Strategy<Value = u16> {
+    prop::sample::select(vec![1u16, 10, 60, 120])
+  }
+
+  /// 合法的 tick-array 序号 (start_tick_index / tick_count) 范围
+  fn tick_array_index_range(tick_spacing: u16) -> RangeInclusive<i32> {
+    let tick_count = TickArrayState::tick_count(tick_spacing);
+    TickArrayState::get_array_start_index(tick_math::MIN_TICK, tick_spacing) / tick_count
+      ..=TickArrayState::get_array_start_index(tick_math::MAX_TICK, tick_spacing) / tick_count
+  }
+
+  /// tick-array 的 start_tick_index，一半落在默认 bitmap 边界 (±512) 附近，一半落在整个 tick 范围内
+  fn tick_array_start_index_strategy(tick_spacing: u16) -> impl Strategy<Value = i32> {
+    let range = tick_array_index_range(tick_spacing);
+    let near_boundary = (*range.start()).max(-520)..=(*range.end()).min(520);
+    let tick_count = TickArrayState::tick_count(tick_spacing);
+    prop_oneof![near_boundary, range].prop_map(move |index| index * tick_count)
+  }
+
+  /// (tick_spacing, 已初始化的 tick-array, 查询起点)
+  fn initialized_tick_arrays_strategy() -> impl Strategy<Value = (u16, BTreeSet<i32>, i32)> {
+    tick_spacing_strategy().prop_flat_map(|tick_spacing| {
+      (
+        Just(tick_spacing),
+        prop::collection::btree_set(tick_array_start_index_strategy(tick_spacing), 0..24),
+        tick_array_start_index_strategy(tick_spacing),
+      )
+    })
+  }
+
+  /// 以 tick_spacing 对齐的仓位，围绕 center_tick 分布，仓位之间可能重叠也可能留有流动性为 0 的空隙
+  fn positions_strategy(tick_spacing: u16, center_tick: i32) -> impl Strategy<Value = Vec<(i32, i32, u128)>> {
+    let spacing = i32::from(tick_spacing);
+    let center = center_tick.div_euclid(spacing);
+    prop::collection::vec((center - 300..center + 300, 1..=300i32, 1_000_000u128..=1_000_000_000_000_000), 1..6).prop_map(
+      move |positions| {
+        positions.into_iter().map(|(lower, width, liquidity)| (lower * spacing, (lower + width) * spacing, liquidity)).collect()
+      },
+    )
+  }
+
+  fn spacing_and_positions_strategy() -> impl Strategy<Value = (u16, Vec<(i32, i32, u128)>)> {
+    tick_spacing_strategy().prop_flat_map(|tick_spacing| (Just(tick_spacing), positions_strategy(tick_spacing, 0)))
+  }
+
+  proptest! {
+    #[test]
+    fn tick_array_keys_follow_start_index_order((tick_spacing, start_indexes, _) in initialized_tick_arrays_strategy()) {
+      let pool_id = Pubkey::new_unique();
+      let start_indexes: Vec<i32> = start_indexes.into_iter().collect();
+      let (tick_array_bitmap, tick_array_bitmap_extension) = fixtures::tick_array_bitmaps(pool_id, tick_spacing, &start_indexes);
+
+      let keys = PoolInfo::calculate_all_tick_array_keys(&pool_id, tick_spacing, &tick_array_bitmap, &tick_array_bitmap_extension);
+      let expected: Vec<Pubkey> =
+        start_indexes.iter().map(|start_index| PoolInfo::get_pda_tick_array_address(&pool_id, *start_index)).collect();
+      prop_assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn next_initialized_tick_array_is_nearest_in_direction(
+      (tick_spacing, start_indexes, last_start_index) in initialized_tick_arrays_strategy(),
+      zero_for_one in any::<bool>(),
+    ) {
+      let pool_id = Pubkey::new_unique();
+      let (tick_array_bitmap, tick_array_bitmap_extension) =
+        fixtures::tick_array_bitmaps(pool_id, tick_spacing, &start_indexes.iter().copied().collect::<Vec<_>>());
+      let pool = PoolInfo {
+        base_info: PoolBaseInfo { id: pool_id, tick_spacing, ..Default::default() },
+        dynamic_info: PoolDynamicInfo { id: pool_id, tick_array_bitmap, ..Default::default() },
+      };
+
+      let next = pool.next_initialized_tick_array_start_index(&tick_array_bitmap_extension, last_start_index, zero_for_one).unwrap();
+      let expected = if zero_for_one {
+        start_indexes.range(..last_start_index).next_back().copied()
+      } else {
+        start_indexes.range(last_start_index + 1..).next().copied()
+      };
+      prop_assert_eq!(next, expected);
+    }
+
+    #[test]
+    fn tick_liquidity_matches_active_positions((tick_spacing, positions) in spacing_and_positions_strategy()) {
+      let tick_arrays = fixtures::tick_arrays_from_positions(Pubkey::new_unique(), tick_spacing, &positions);
+      let changed_ticks: Vec<i32> = tick_arrays
+        .iter()
+        .flat_map(|tick_array| tick_array.ticks.iter().filter(|tick| tick.liquidity_net != 0).map(|tick| tick.tick).collect::<Vec<_>>())
+        .collect();
+
+      let infos = generate_tick_liquidity_info(tick_arrays.into_iter().map(Box::new).collect()).unwrap();
+
+      for pair in infos.windows(2) {
+        prop_assert!(pair[0].tick_index <= pair[1].tick_index);
+      }
+      for info in infos.iter() {
+        prop_assert!(info.liquidity > 0);
+        prop_assert_eq!(info.liquidity, fixtures::active_liquidity(&positions, info.tick_index));
+      }
+      // 流动性变化后不为 0 的 tick 都要有记录，流动性为 0 的区间不记录
+      for tick in changed_ticks {
+        let liquidity = fixtures::active_liquidity(&positions, tick);
+        prop_assert_eq!(infos.iter().any(|info| info.tick_index == tick && info.liquidity == liquidity), liquidity != 0);
+      }
+    }
+
+    #[test]
+    fn unordered_tick_arrays_are_rejected((tick_spacing, positions) in spacing_and_positions_strategy(), index in any::<Index>()) {
+      let mut tick_arrays = fixtures::tick_arrays_from_positions(Pubkey::new_unique(), tick_spacing, &positions);
+      prop_assume!(tick_arrays.len() >= 2);
+      let i = index.index(tick_arrays.len() - 1);
+      tick_arrays.swap(i, i + 1);
+
+      let result = generate_tick_liquidity_info(tick_arrays.into_iter().map(Box::new).collect());
+      let is_order_error = matches!(result, Err(SwapComputeError::InvalidTickArrayOrder { .. }));
+      prop_assert!(is_order_error);
+    }
+
+    #[test]
+    fn transfer_fee_conserves_amount(
+      transfer_fee_basis_points in 0..=MAX_FEE_BASIS_POINTS,
+      maximum_fee in 0..=u64::MAX / 2,
+      amount in 0..=u64::MAX / 2,
+      newer_epoch in 0u64..1000,
+      epoch in 0u64..1000,
+      add_fee in any::<bool>(),
+    ) {
+      let transfer_fee =
+        TransferFee { epoch: newer_epoch.into(), maximum_fee: maximum_fee.into(), transfer_fee_basis_points: transfer_fee_basis_points.into() };
+      let config = Some(TransferFeeConfig { older_transfer_fee: transfer_fee, newer_transfer_fee: transfer_fee, ..Default::default() });
+
+      let result = get_transfer_amount_fee(amount, &config, &epoch_info(epoch), add_fee).unwrap();
+      prop_assert!(result.fee <= maximum_fee);
+      if add_fee {
+        prop_assert_eq!(result.amount, amount + result.fee);
+      } else {
+        prop_assert_eq!(result.amount + result.fee, amount);
+      }
+      prop_assert_eq!(result.expiration_slot, (epoch < newer_epoch).then_some(newer_epoch * SLOTS_IN_EPOCH));
+
+      let result = get_transfer_amount_fee(amount, &None, &epoch_info(epoch), add_fee).unwrap();
+      prop_assert_eq!((result.amount, result.fee, result.expiration_slot), (amount, 0, None));
+    }
+
+    #[test]
+    fn swap_moves_price_in_direction_and_is_monotonic_in_amount(
+      (tick_spacing, tick_current, positions) in prop::sample::select(vec![1u16, 10, 60])
+        .prop_flat_map(|tick_spacing| (Just(tick_spacing), -2000i32..2000))
+        .prop_flat_map(|(tick_spacing, tick_current)| (Just(tick_spacing), Just(tick_current), positions_strategy(tick_spacing, tick_current))),
+      zero_for_one in any::<bool>(),
+      base_input in any::<bool>(),
+      amount in 1u64..=1_000_000_000_000,
+    ) {
+      let pool = fixtures::synthetic_pool(tick_spacing, tick_current, TRADE_FEE_RATE, &positions);
+      let input_mint = if zero_for_one { pool.base_info.mint_a_info.mint } else { pool.base_info.mint_b_info.mint };
+      let compute = |amount: u64| -> Result<_, TestCaseError> {
+        match compute_another_amount(&pool, &input_mint, base_input, amount, &epoch_info(0), None) {
+          Ok(result) => Ok(Some(result)),
+          // 流动性不足、步数超限是合法的失败
+          Err(SwapComputeError::LiquidityExhausted(_)) | Err(SwapComputeError::StepLimitExceeded(_)) => Ok(None),
+          Err(err) => Err(TestCaseError::fail(err.to_string())),
+        }
+      };
+
+      let Some(result) = compute(amount)? else { return Ok(()) };
+      prop_assert!(result.amount_specified_remaining <= amount);
+      if zero_for_one {
+        prop_assert!(result.after_sqrt_price_x64 <= result.before_sqrt_price_x64);
+      } else {
+        prop_assert!(result.after_sqrt_price_x64 >= result.before_sqrt_price_x64);
+      }
+      if base_input {
+        prop_assert!(result.fee_amount <= amount - result.amount_specified_remaining);
+      } else {
+        prop_assert!(result.fee_amount <= result.amount_calculated);
+      }
+
+      // 指定的数量越大，计算得到的另一侧数量不会更小
+      if let Some(larger) = compute(amount + amount / 2)? {
+        prop_assert!(larger.amount_calculated >= result.amount_calculated);
+      }
+    }
+  }
+}
+
+/// 询价结果有效期的计算与校验
+#[cfg(test)]
+mod quote_expiry {
+  use crate::service::router_service::{
+    error::QuoteError,
+    quote::{QuoteExpiry, check_quote_expiry},
+  };
+
+  #[test]
+  fn expire_slot_is_earliest_of_ttl_and_fee_change() {
+    assert_eq!(QuoteExpiry::new(1000, 150, None).expire_slot, 1150);
+    assert_eq!(QuoteExpiry::new(1000, 150, Some(1100)).expire_slot, 1100);
+    assert_eq!(QuoteExpiry::new(1000, 150, Some(2000)).expire_slot, 1150);
+    assert_eq!(QuoteExpiry::new(1000, 150, Some(1100)).estimated_expire_time(10_000), 10_000 + 100 * 400);
+  }
+
+  #[test]
+  fn expired_or_missing_quotes_are_rejected() {
+    assert_eq!(check_quote_expiry(1150, 1149), Ok(()));
+    assert_eq!(check_quote_expiry(1150, 1150), Err(QuoteError::Expired { expire_slot: 1150, current_slot: 1150 }));
+    assert_eq!(check_quote_expiry(0, 1), Err(QuoteError::MissingExpiry));
+  }
+}
+
+/// 服务端保存的询价结果
+#[cfg(test)]
+mod quote_registry {
+  use std::time::Duration;
+
+  use solana_sdk::pubkey::Pubkey;
+
+  use crate::service::{
+    core::platform_fee::{FeeSide, PlatformFeeConfig},
+    pb::router::SwapV1Out,
+    router_service::{
+      error::QuoteError,
+      quote::{InMemoryQuoteStore, QuoteStore, StoredQuote, load_quote, new_quote_id, quote_platform_fee, resolve_quote, save_quote},
+    },
+  };
+
+  const SECRET: &str = "quote-secret";
+  const TTL: Duration = Duration::from_secs(60);
+
+  fn sample_quote() -> SwapV1Out {
+    SwapV1Out { quote_id: new_quote_id(), input_amount: "1000".to_string(), expire_slot: 1150, ..Default::default() }
+  }
+
+  fn quote_error(err: anyhow::Error) -> QuoteError {
+    err.downcast::<QuoteError>().unwrap()
+  }
+
+  #[tokio::test]
+  async fn saved_quote_is_loaded_by_id() {
+    let store = InMemoryQuoteStore::default();
+    let quote = sample_quote();
+    save_quote(&store, SECRET, quote.clone(), TTL).await.unwrap();
+
+    assert_eq!(load_quote(&store, SECRET, &quote.quote_id).await.unwrap(), quote);
+    assert_eq!(quote_error(load_quote(&store, SECRET, "unknown").await.unwrap_err()), QuoteError::NotFound("unknown".to_string()));
+  }
+
+  #[tokio::test]
+  async fn quote_is_dropped_after_ttl() {
+    let store = InMemoryQuoteStore::default();
+    let quote = sample_quote();
+    save_quote(&store, "", quote.clone(), Duration::ZERO).await.unwrap();
+
+    assert!(matches!(quote_error(load_quote(&store, "", &quote.quote_id).await.unwrap_err()), QuoteError::NotFound(_)));
+  }
+
+  #[tokio::test]
+  async fn tampered_quote_is_rejected() {
+    let store = InMemoryQuoteStore::default();
+    let quote = sample_quote();
+    save_quote(&store, SECRET, quote.clone(), TTL).await.unwrap();
+
+    // 共享存储中的询价结果被修改，签名不再匹配
+    let mut stored = store.get(&quote.quote_id).await.unwrap().unwrap();
+    stored.quote.input_amount = "1".to_string();
+    store.put(&quote.quote_id, stored, TTL).await.unwrap();
+    assert_eq!(quote_error(load_quote(&store, SECRET, &quote.quote_id).await.unwrap_err()), QuoteError::Tampered(quote.quote_id.clone()));
+
+    // 未签名的询价结果在配置了密钥后同样被拒绝
+    store.put(&quote.quote_id, StoredQuote { quote: quote.clone(), signature: Vec::new() }, TTL).await.unwrap();
+    assert_eq!(quote_error(load_quote(&store, SECRET, &quote.quote_id).await.unwrap_err()), QuoteError::Tampered(quote.quote_id.clone()));
+  }
+
+  #[tokio::test]
+  async fn forged_expire_slot_is_rejected() {
+    let store = InMemoryQuoteStore::default();
+    let quote = sample_quote();
+    save_quote(&store, SECRET, quote.clone(), TTL).await.unwrap();
+    let forged = SwapV1Out { expire_slot: u64::MAX, ..quote.clone() };
+
+    // 配置了签名密钥时不接受没有 quote_id 的询价结果
+    assert_eq!(
+      quote_error(resolve_quote(&store, SECRET, false, "", Some(&forged)).await.unwrap_err()),
+      QuoteError::QuoteIdRequired("quote signing is enabled")
+    );
+    // 回传的询价结果与保存的不一致
+    assert_eq!(
+      quote_error(resolve_quote(&store, SECRET, false, &quote.quote_id, Some(&forged)).await.unwrap_err()),
+      QuoteError::Tampered(quote.quote_id.clone())
+    );
+    assert_eq!(resolve_quote(&store, SECRET, false, &quote.quote_id, None).await.unwrap(), quote);
+    assert_eq!(resolve_quote(&store, SECRET, false, &quote.quote_id, Some(&quote)).await.unwrap(), quote);
+  }
+
+  #[tokio::test]
+  async fn client_quotes_require_opt_in() {
+    let store = InMemoryQuoteStore::default();
+    let quote = sample_quote();
+
+    // 默认不接受没有 quote_id 的询价结果，未配置签名密钥时也一样
+    assert_eq!(
+      quote_error(resolve_quote(&store, "", false, "", Some(&quote)).await.unwrap_err()),
+      QuoteError::QuoteIdRequired("client quotes are not accepted")
+    );
+    assert_eq!(resolve_quote(&store, "", true, "", Some(&quote)).await.unwrap(), quote);
+    // 配置了签名密钥时开启也不接受
+    assert_eq!(
+      quote_error(resolve_quote(&store, SECRET, true, "", Some(&quote)).await.unwrap_err()),
+      QuoteError::QuoteIdRequired("quote signing is enabled")
+    );
+  }
+
+  fn fee_quote(platform_fee_amount: &str, platform_fee_mint: &str) -> SwapV1Out {
+    SwapV1Out {
+      input_mint: "input".to_string(),
+      output_mint: "output".to_string(),
+      platform_fee_amount: platform_fee_amount.to_string(),
+      platform_fee_mint: platform_fee_mint.to_string(),
+      ..sample_quote()
+    }
+  }
+
+  #[test]
+  fn platform_fee_requires_a_stored_quote() {
+    let config = PlatformFeeConfig { fee_bps: 100, side: FeeSide::Output, fee_owner: Pubkey::new_unique(), referral_share_bps: 0 };
+
+    // 客户端回传的询价结果去掉或改小了平台费
+    for forged in [fee_quote("0", "output"), fee_quote("", ""), fee_quote("1", "input")] {
+      assert_eq!(
+        quote_error(quote_platform_fee(Some(&config), &forged, false).unwrap_err()),
+        QuoteError::QuoteIdRequired("platform fee is enabled")
+      );
+    }
+
+    // 服务端保存的询价结果按询价时的扣费方向收取
+    assert_eq!(quote_platform_fee(Some(&config), &fee_quote("50", "output"), true).unwrap(), (FeeSide::Output, 50));
+    assert_eq!(quote_platform_fee(Some(&config), &fee_quote("50", "input"), true).unwrap(), (FeeSide::Input, 50));
+    assert!(quote_platform_fee(Some(&config), &fee_quote("50", "other"), true).is_err());
+
+    // 未配置平台费时不收取
+    assert_eq!(quote_platform_fee(None, &fee_quote("50", "output"), false).unwrap(), (FeeSide::Output, 0));
+  }
+}
+
+#[cfg(test)]
+mod route_constraints {
+  use solana_sdk::pubkey::Pubkey;
+
+  use crate::service::{
+    pb::router::QuotePriceRequest,
+    router_service::{
+      fixtures,
+      route_utils::get_all_route_path,
+      types::{POOL_VERSION_CPMM, PoolInfo, RouteConstraints},
+    },
+  };
+
+  fn pool(mint_a: Pubkey, mint_b: Pubkey) -> PoolInfo {
+    let mut pool = fixtures::synthetic_pool(1, 0, 0, &[(-100, 100, 1_000_000)]);
+    pool.base_info.mint_a_info.mint = mint_a;
+    pool.base_info.mint_b_info.mint = mint_b;
+    pool
+  }
+
+  /// 输入、输出代币之间有一个直接池子，以及分别经过 hub 代币和普通代币中转的两条路径
+  fn pools(input: Pubkey, output: Pubkey, hub: Pubkey, other: Pubkey) -> Vec<PoolInfo> {
+    vec![pool(input, output), pool(input, hub), pool(hub, output), pool(other, input), pool(output, other)]
+  }
+
+  async fn route_mints(constraints: &RouteConstraints, pools: &[PoolInfo], input: &Pubkey, output: &Pubkey) -> (usize, Vec<String>) {
+    let paths = get_all_route_path(input, output, &pools.to_vec(), constraints).await.unwrap();
+    let mut mints: Vec<String> = paths.route_paths_map.into_keys().collect();
+    mints.sort();
+    (paths.direct_paths.len(), mints)
+  }
+
+  #[tokio::test]
+  async fn hub_mints_limit_route_mints() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let pools = pools(input, output, hub, other);
+
+    let constraints = RouteConstraints::new(&QuotePriceRequest::default(), &[hub]).unwrap();
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, vec![hub.to_string()]));
+
+    // 没有配置 hub 代币时任何代币都可以中转
+    let constraints = RouteConstraints::new(&QuotePriceRequest::default(), &[]).unwrap();
+    let mut expected = vec![hub.to_string(), other.to_string()];
+    expected.sort();
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, expected));
+  }
+
+  #[tokio::test]
+  async fn max_hops_of_one_allows_direct_routes_only() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let pools = pools(input, output, hub, other);
+
+    for max_hops in [0, 2, 3] {
+      let constraints = RouteConstraints::new(&QuotePriceRequest { max_hops, ..Default::default() }, &[hub]).unwrap();
+      assert!(constraints.allow_indirect_routes());
+      assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, vec![hub.to_string()]));
+    }
+
+    let constraints = RouteConstraints::new(&QuotePriceRequest { max_hops: 1, ..Default::default() }, &[hub]).unwrap();
+    assert!(!constraints.allow_indirect_routes());
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, vec![]));
+
+    assert!(RouteConstraints::new(&QuotePriceRequest { max_hops: -1, ..Default::default() }, &[hub]).is_err());
+  }
+
+  #[tokio::test]
+  async fn excluded_pools_are_dropped() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let pools = pools(input, output, hub, other);
+
+    // 排除直接池子和 input -> hub 的池子，只剩经过 other 中转的路由
+    let exclude_pools = vec![pools[0].base_info.id.to_string(), pools[1].base_info.id.to_string()];
+    let constraints = RouteConstraints::new(&QuotePriceRequest { exclude_pools, ..Default::default() }, &[]).unwrap();
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (0, vec![other.to_string()]));
+  }
+
+  #[tokio::test]
+  async fn excluded_mints_are_not_used_as_route_mints() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let pools = pools(input, output, hub, other);
+
+    let constraints =
+      RouteConstraints::new(&QuotePriceRequest { exclude_mints: vec![other.to_string()], ..Default::default() }, &[]).unwrap();
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, vec![hub.to_string()]));
+  }
+
+  #[tokio::test]
+  async fn pools_of_other_venues_are_dropped() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let mut pools = pools(input, output, hub, other);
+    // 直接池子和 hub -> output 的池子是 cpmm 池子
+    pools[0].base_info.version = POOL_VERSION_CPMM;
+    pools[2].base_info.version = POOL_VERSION_CPMM;
+
+    let constraints =
+      RouteConstraints::new(&QuotePriceRequest { allowed_venues: vec!["clmm".to_string()], ..Default::default() }, &[]).unwrap();
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (0, vec![other.to_string()]));
+
+    let constraints =
+      RouteConstraints::new(&QuotePriceRequest { allowed_venues: vec!["CPMM".to_string()], ..Default::default() }, &[]).unwrap();
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, vec![]));
+
+    assert!(RouteConstraints::new(&QuotePriceRequest { allowed_venues: vec!["orca".to_string()], ..Default::default() }, &[]).is_err());
+  }
+
+  #[tokio::test]
+  async fn only_direct_routes_drop_route_mints() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let pools = pools(input, output, hub, other);
+
+    let constraints = RouteConstraints::new(&QuotePriceRequest { only_direct_routes: true, ..Default::default() }, &[hub]).unwrap();
+    assert!(!constraints.allow_indirect_routes());
+    assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, vec![]));
+  }
+
+  #[tokio::test]
+  async fn only_direct_routes_take_precedence_over_max_hops() {
+    let [input, output, hub, other] = [(); 4].map(|_| Pubkey::new_unique());
+    let pools = pools(input, output, hub, other);
+
+    // 任一条件只允许直接路由时都不使用中转代币
+    for (only_direct_routes, max_hops, allow_indirect_routes) in
+      [(true, 0, false), (true, 1, false), (true, 2, false), (true, 3, false), (false, 1, false), (false, 0, true), (false, 2, true)]
+    {
+      let req = QuotePriceRequest { only_direct_routes, max_hops, ..Default::default() };
+      let constraints = RouteConstraints::new(&req, &[hub]).unwrap();
+      assert_eq!(constraints.allow_indirect_routes(), allow_indirect_routes, "{:?}", (only_direct_routes, max_hops));
+
+      let route_mints_expected = if allow_indirect_routes { vec![hub.to_string()] } else { vec![] };
+      assert_eq!(route_mints(&constraints, &pools, &input, &output).await, (1, route_mints_expected));
+    }
+  }
+}
@@ -1,16 +1,26 @@
 use std::{
-  collections::HashMap,
+  collections::{HashMap, HashSet},
   fmt::{self, Debug},
+  str::FromStr,
 };
 
 use anchor_lang::prelude::*;
 use raydium_amm_v3::states::{TickArrayBitmapExtension, TickArrayState};
 
-use crate::service::core::types::MintAccountBaseInfo;
+use crate::service::{core::types::MintAccountBaseInfo, pb::router::QuotePriceRequest};
 
 pub const POOL_VERSION_CLMM: u8 = 6; // Constant for CLMM pool version
 pub const POOL_VERSION_CPMM: u8 = 7; // Constant for CPMM pool version
 
+/// 池子类型(venue)名称 => 池子版本
+pub fn pool_version_from_venue(venue: &str) -> Option<u8> {
+  match venue.to_ascii_lowercase().as_str() {
+    "clmm" => Some(POOL_VERSION_CLMM),
+    "cpmm" => Some(POOL_VERSION_CPMM),
+    _ => None,
+  }
+}
+
 /// 池子的静态信息
 #[derive(Debug, Default, Clone)]
 pub struct PoolBaseInfo {
@@ -140,6 +150,79 @@ impl AllRoutePathInfo {
   }
 }
 
+/// 询价时的路由约束条件
+/// 由请求参数和 nacos 配置的 hub 代币共同决定
+#[derive(Debug, Default, Clone)]
+pub struct RouteConstraints {
+  /// 只使用直接路由
+  pub only_direct_routes: bool,
+  /// 路由最多经过的池子数量, 0 表示不限制, 1 表示只使用直接路由
+  pub max_hops: u32,
+  /// 不参与路由的池子
+  pub exclude_pools: HashSet<Pubkey>,
+  /// 不能作为中转代币的 mint
+  pub exclude_mints: HashSet<Pubkey>,
+  /// 允许使用的池子版本, 为空表示不限制
+  pub allowed_pool_versions: HashSet<u8>,
+  /// 允许作为中转代币的 mint, 为空表示不限制
+  pub hub_mints: HashSet<Pubkey>,
+}
+
+impl RouteConstraints {
+  /// 根据询价请求和配置的 hub 代币生成路由约束
+  pub fn new(req: &QuotePriceRequest, hub_mints: &[Pubkey]) -> anyhow::Result<Self> {
+    if req.max_hops < 0 {
+      return Err(anyhow::anyhow!("max_hops must not be negative"));
+    }
+
+    let exclude_pools = req
+      .exclude_pools
+      .iter()
+      .map(|pool| Pubkey::from_str(pool).map_err(|e| anyhow::anyhow!("Invalid exclude pool: {}, {}", pool, e)))
+      .collect::<anyhow::Result<HashSet<_>>>()?;
+    let exclude_mints = req
+      .exclude_mints
+      .iter()
+      .map(|mint| Pubkey::from_str(mint).map_err(|e| anyhow::anyhow!("Invalid exclude mint: {}, {}", mint, e)))
+      .collect::<anyhow::Result<HashSet<_>>>()?;
+    let allowed_pool_versions = req
+      .allowed_venues
+      .iter()
+      .map(|venue| pool_version_from_venue(venue).ok_or_else(|| anyhow::anyhow!("Unsupported venue: {}", venue)))
+      .collect::<anyhow::Result<HashSet<_>>>()?;
+
+    Ok(Self {
+      only_direct_routes: req.only_direct_routes,
+      max_hops: req.max_hops as u32,
+      exclude_pools,
+      exclude_mints,
+      allowed_pool_versions,
+      hub_mints: hub_mints.iter().cloned().collect(),
+    })
+  }
+
+  /// 是否允许经过中转代币的路由（至少2个池子）
+  pub fn allow_indirect_routes(&self) -> bool {
+    !self.only_direct_routes && (self.max_hops == 0 || self.max_hops >= 2)
+  }
+
+  /// 池子是否可以参与路由
+  pub fn is_pool_allowed(&self, pool: &PoolInfo) -> bool {
+    if self.exclude_pools.contains(&pool.base_info.id) {
+      return false;
+    }
+    self.allowed_pool_versions.is_empty() || self.allowed_pool_versions.contains(&pool.base_info.version)
+  }
+
+  /// 代币是否可以作为中转代币
+  pub fn is_route_mint_allowed(&self, mint: &Pubkey) -> bool {
+    if self.exclude_mints.contains(mint) {
+      return false;
+    }
+    self.hub_mints.is_empty() || self.hub_mints.contains(mint)
+  }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TickLiquidityInfo {
   pub tick_index: i32,